@@ -1,3 +1,31 @@
+use std::path::{Path, PathBuf};
+
 fn main() {
+  // Reported by `backend_info`. Cargo writes the lock file before building,
+  // next to the manifest or at the workspace root above it.
+  let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set");
+  let lock = find_lock(Path::new(&manifest_dir))
+    .unwrap_or_else(|| panic!("No Cargo.lock found in or above {}", manifest_dir));
+  let pty_version = locked_version(&lock, "portable-pty")
+    .unwrap_or_else(|| panic!("No portable-pty version in {}", lock.display()));
+  println!("cargo:rustc-env=KARPI_PORTABLE_PTY_VERSION={}", pty_version);
+  println!("cargo:rerun-if-changed={}", lock.display());
   tauri_build::build()
 }
+
+/// The Cargo.lock in `dir` or the nearest directory above it
+fn find_lock(dir: &Path) -> Option<PathBuf> {
+  dir
+    .ancestors()
+    .map(|dir| dir.join("Cargo.lock"))
+    .find(|lock| lock.is_file())
+}
+
+/// Version of `package` resolved in `lock`
+fn locked_version(lock: &Path, package: &str) -> Option<String> {
+  let lock = std::fs::read_to_string(lock).ok()?;
+  let name = format!("name = \"{}\"", package);
+  let mut lines = lock.lines().skip_while(|line| *line != name).skip(1);
+  let version = lines.next()?.strip_prefix("version = \"")?.strip_suffix('"')?;
+  Some(version.to_string())
+}
@@ -25,8 +25,16 @@ fn resolve_bun() -> String {
     "bun".to_string() // last resort — hope it's on PATH
 }
 
+/// Run the karpi CLI with `args` and return its JSON output.
+///
+/// With `no_controlling_tty` (Unix only), the CLI runs in a new session
+/// (`setsid`) without a controlling terminal, so anything it tries to ask
+/// interactively on `/dev/tty` fails straight away instead of waiting for an
+/// answer that can't come. Its stdin is never connected either way. Without
+/// it the CLI shares the app's controlling terminal, if the app was started
+/// from one.
 #[tauri::command]
-async fn run_karpi(args: Vec<String>) -> Result<String, String> {
+async fn run_karpi(args: Vec<String>, no_controlling_tty: Option<bool>) -> Result<String, String> {
     // Resolve the karpi project root (two levels up from src-tauri)
     let karpi_root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
         .parent()
@@ -36,12 +44,21 @@ async fn run_karpi(args: Vec<String>) -> Result<String, String> {
     let entry = karpi_root.join("src").join("index.ts");
     let bun = resolve_bun();
 
-    let output = tokio::process::Command::new(&bun)
+    let mut command = std::process::Command::new(&bun);
+    command
         .arg("run")
         .arg(&entry)
         .arg("--json")
         .args(&args)
-        .current_dir(karpi_root)
+        .current_dir(karpi_root);
+    if no_controlling_tty.unwrap_or(false) {
+        #[cfg(unix)]
+        without_controlling_tty(&mut command);
+        #[cfg(not(unix))]
+        return Err("Running without a controlling terminal is only supported on Unix".to_string());
+    }
+
+    let output = tokio::process::Command::from(command)
         .output()
         .await
         .map_err(|e| format!("Failed to run karpi (bun={}): {}", bun, e))?;
@@ -69,6 +86,48 @@ async fn run_karpi(args: Vec<String>) -> Result<String, String> {
     }
 }
 
+/// Start `command` in a new session, which has no controlling terminal
+#[cfg(unix)]
+fn without_controlling_tty(command: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+    unsafe {
+        // Only async-signal-safe calls are allowed between fork and exec
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Version and build details to paste into bug reports, also written to
+/// `terminal::create_debug_bundle`'s bundles
+#[derive(serde::Serialize)]
+struct BackendInfo {
+    version: &'static str,
+    portable_pty_version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    /// `debug` or `release`
+    profile: &'static str,
+}
+
+#[tauri::command]
+fn backend_info() -> BackendInfo {
+    BackendInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        portable_pty_version: env!("KARPI_PORTABLE_PTY_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -81,12 +140,143 @@ pub fn run() {
         .manage(TerminalState::default())
         .invoke_handler(tauri::generate_handler![
             run_karpi,
+            backend_info,
             terminal::spawn_terminal,
             terminal::write_terminal,
             terminal::resize_terminal,
             terminal::kill_terminal,
             terminal::list_terminals,
+            terminal::set_color_scheme,
+            terminal::add_output_filter,
+            terminal::clear_output_filters,
+            terminal::reset_terminal,
+            terminal::subscribe_output,
+            terminal::get_capabilities,
+            terminal::set_startup_failure_threshold,
+            terminal::get_scrollback,
+            terminal::get_scrollback_from,
+            terminal::search_scrollback,
+            terminal::enable_scrollback_index,
+            terminal::get_scrollback_and_subscribe,
+            terminal::get_scrollback_range,
+            terminal::get_scrollback_info,
+            terminal::set_text_encoding,
+            terminal::get_text_encoding,
+            terminal::get_process_tree,
+            terminal::replay_recording,
+            terminal::pause_replay,
+            terminal::seek_replay,
+            terminal::stop_replay,
+            terminal::start_input_recording,
+            terminal::stop_input_recording,
+            terminal::add_trigger,
+            terminal::remove_trigger,
+            terminal::export_session,
+            terminal::import_session,
+            terminal::get_pty_name,
+            terminal::send_flow_resume,
+            terminal::get_shell_env,
+            terminal::check_paste_safety,
+            terminal::set_dangerous_commands,
+            terminal::get_dangerous_commands,
+            terminal::enable_timing,
+            terminal::get_timing_report,
+            terminal::set_answerback,
+            terminal::get_terminal_modes,
+            terminal::send_mouse,
+            terminal::feed_all_terminals,
+            terminal::resize_terminal_full,
+            terminal::get_session_uuid,
+            terminal::set_output_rate_limit,
+            terminal::get_line_discipline,
+            terminal::set_line_discipline,
+            terminal::spawn_in_repo,
+            terminal::set_output_burst_threshold,
+            terminal::prewarm_shells,
+            terminal::take_prewarmed,
+            terminal::set_echo,
+            terminal::get_banner,
+            terminal::detach_output,
+            terminal::attach_output,
+            terminal::force_redraw,
+            terminal::get_default_spawn_config,
+            terminal::apply_env_file,
+            terminal::set_exit_behavior,
+            terminal::apply_scrollback_limit_all,
+            terminal::is_at_prompt,
+            terminal::tee_output_to_pipe,
+            terminal::set_output_batching,
+            terminal::get_output_batching,
+            terminal::reset_terminal_subsystem,
+            terminal::set_session_metadata,
+            terminal::get_session_metadata,
+            terminal::kill_terminals_where,
+            terminal::link_input,
+            terminal::unlink_input,
+            terminal::enable_screen_model,
+            terminal::get_screen_text,
+            terminal::get_idle_time,
+            terminal::create_debug_bundle,
+            terminal::list_recent_exits,
+            terminal::set_global_init_command,
+            terminal::get_global_init_command,
+            terminal::which_in_session,
+            terminal::compact_memory,
+            terminal::get_last_command,
+            terminal::get_command_history,
+            terminal::set_command_history_depth,
+            terminal::set_auto_binary,
+            terminal::snapshot_all_sessions,
+            terminal::scrollback_contains,
+            terminal::resize_all_terminals,
+            terminal::resolve_shell,
+            terminal::set_command_timeout,
+            terminal::get_bytes_written,
+            terminal::query_device_attributes,
+            terminal::poll_cwd,
+            terminal::clear_session_links,
+            terminal::clear_all_links,
+            terminal::export_transcript,
+            terminal::normalize_env,
+            terminal::capture_between,
+            terminal::set_max_emit_size,
+            terminal::get_max_emit_size,
+            terminal::adopt_fd,
+            terminal::enable_ndjson_log,
+            terminal::disable_ndjson_log,
+            terminal::set_slow_startup_threshold,
+            terminal::export_grid_state,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn reading_the_tty_fails_promptly_without_one() {
+        let mut command = std::process::Command::new("/bin/sh");
+        command
+            .args(["-c", "read answer </dev/tty"])
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        without_controlling_tty(&mut command);
+        let started = Instant::now();
+        let mut child = command.spawn().unwrap();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if started.elapsed() > Duration::from_secs(5) {
+                let _ = child.kill();
+                let _ = child.wait();
+                panic!("reading /dev/tty blocked");
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(!status.success());
+    }
+}
@@ -1,237 +1,6097 @@
 // src-tauri/src/terminal.rs
 
-use parking_lot::Mutex;
-use portable_pty::{native_pty_system, CommandBuilder, PtySize};
-use std::collections::HashMap;
+#[cfg(unix)]
+mod adopted;
+mod ansi;
+mod burst;
+mod cgroup;
+mod command_history;
+#[cfg(unix)]
+mod control_socket;
+mod debug_bundle;
+mod device_attributes;
+mod dotenv;
+mod encoding;
+mod environment;
+mod error;
+mod exit;
+mod filter;
+mod input_recording;
+#[cfg(unix)]
+mod jobs;
+mod line_discipline;
+mod locale;
+mod modes;
+mod mouse;
+mod ndjson_log;
+#[cfg(unix)]
+mod output_pipe;
+mod paste;
+mod policy;
+mod process;
+mod rate_limit;
+mod replay;
+mod repo;
+mod rlimit;
+mod scanner;
+mod screen;
+mod scrollback;
+mod shell_capture;
+#[cfg(unix)]
+mod shell_integration;
+#[cfg(unix)]
+mod termios;
+mod timing;
+mod trigger;
+
+pub use error::TerminalError;
+pub use policy::TerminalPolicy;
+
+use ansi::ControlCharPolicy;
+use burst::{BurstChange, BurstDetector};
+#[cfg(target_os = "linux")]
+use cgroup::Cgroup;
+use cgroup::CgroupLimits;
+use command_history::{CommandHistory, CommandRecord, MAX_HISTORY_DEPTH};
+#[cfg(unix)]
+use control_socket::ControlSocket;
+use debug_bundle::DebugBundle;
+use encoding::{Decoded, OutputDecoder};
+use exit::{ExitBehavior, ExitReason};
+use filter::{OutputFilter, OutputFilterSpec};
+use input_recording::InputRecorder;
+#[cfg(unix)]
+use jobs::{JobChange, JobTracker};
+use line_discipline::LineDiscipline;
+use modes::TerminalModes;
+use mouse::{MouseAction, MouseButton, MouseEvent, MouseModifiers};
+use ndjson_log::NdjsonLog;
+#[cfg(unix)]
+use output_pipe::OutputPipe;
+use paste::PasteRisk;
+use process::ProcessInfo;
+use rate_limit::RateLimit;
+use replay::{Recording, Replay};
+use rlimit::RlimitSpec;
+use scanner::{ScanEvent, Scanner};
+use screen::{GridState, Screen};
+use scrollback::{CrHandling, Scrollback, DEFAULT_SCROLLBACK_BYTES};
+use shell_capture::{MarkerCapture, ShellCapture, MAX_CAPTURE};
+#[cfg(unix)]
+use shell_integration::IntegrationFiles;
+use timing::{Timing, TimingReport};
+use trigger::{Fired, TriggerAction, Triggers};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use parking_lot::{Mutex, RwLock};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, PtyPair, PtySize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Weak};
 use std::thread;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
 use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::oneshot;
 
 static SESSION_COUNTER: AtomicU32 = AtomicU32::new(0);
 
+/// Log target for everything the terminal backend logs. Records carry the
+/// session as a `session_id` key-value so multi-session logs can be
+/// filtered; it is repeated in the message for plain-text log output.
+const LOG_TARGET: &str = "karpi::pty";
+
+/// How long an incomplete line may wait for the rest of a filter match
+const FILTER_FLUSH_DELAY: Duration = Duration::from_millis(30);
+
+/// Defaults for `set_output_burst_threshold`
+const DEFAULT_BURST_BYTES: u64 = 1024 * 1024;
+const DEFAULT_BURST_WINDOW_MS: u64 = 500;
+
+/// How often a burst is checked for having ended while no output arrives
+const BURST_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often stopped jobs are checked for running again while no output
+/// arrives
+#[cfg_attr(not(unix), allow(dead_code))]
+const JOB_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Most shells `prewarm_shells` keeps waiting, and how long each may wait
+/// before it's killed, so the pool doesn't serve a shell that started with a
+/// long outdated environment
+const MAX_PREWARMED: usize = 8;
+const PREWARM_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Limits for `set_output_batching`; sessions start with batching off
+const DEFAULT_BATCH_BYTES: usize = 64 * 1024;
+const MAX_BATCH_BYTES: usize = 1024 * 1024;
+const MAX_BATCH_FLUSH_MS: u64 = 1000;
+
+/// Limits for `set_max_emit_size`
+const DEFAULT_MAX_EMIT_BYTES: usize = 64 * 1024;
+const MIN_MAX_EMIT_BYTES: usize = 1024;
+const MAX_MAX_EMIT_BYTES: usize = 4 * 1024 * 1024;
+
+/// Most output held back by `set_output_rate_limit` before it's skipped
+const MAX_THROTTLED: usize = 256 * 1024;
+
+/// How long `reset_terminal_subsystem` waits for sessions' threads to
+/// finish, and how often it checks
+const RESET_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+const RESET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Terminal size unless `spawn_terminal` is given one
+const DEFAULT_COLS: u16 = 80;
+const DEFAULT_ROWS: u16 = 24;
+
+/// Bytes requested per PTY read unless `read_buffer_size` says otherwise
+const DEFAULT_READ_BUFFER: usize = 4096;
+const MIN_READ_BUFFER: usize = 1024;
+const MAX_READ_BUFFER: usize = 1024 * 1024;
+
+/// Arguments the shell is started with; a login shell, for proper `PATH`
+const SHELL_ARGS: [&str; 1] = ["-l"];
+
+/// Set in every session's environment
+const DEFAULT_ENV: [(&str, &str); 2] = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor")];
+
+/// Default for `set_startup_failure_threshold`
+const DEFAULT_STARTUP_FAILURE_MS: u64 = 200;
+
+/// Default for `set_slow_startup_threshold`
+const DEFAULT_SLOW_STARTUP_MS: u64 = 2000;
+
+/// Most output kept for a `terminal-startup-failed` event
+const MAX_STARTUP_CAPTURE: usize = 8192;
+
+/// Largest `capture_banner_bytes` honored
+const MAX_BANNER: usize = 64 * 1024;
+
+/// How long `get_shell_env` and `which_in_session` wait for the shell to
+/// answer
+const SHELL_CAPTURE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long `query_device_attributes` waits for the frontend to answer
+const DEVICE_ATTRIBUTES_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the shell gets to exit after its terminal closes, and again
+/// after it's then killed, before the reader thread gives up on it
+const EXIT_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a shell must have run to be restarted by `ExitBehavior::Restart`,
+/// so one that can't start doesn't respawn in a loop
+const MIN_RESTART_UPTIME: Duration = Duration::from_secs(1);
+
+/// How long an exited session is listed by `list_recent_exits`, and at most
+/// how many are
+const RECENT_EXIT_GRACE: Duration = Duration::from_secs(10 * 60);
+const MAX_RECENT_EXITS: usize = 100;
+
+/// Shortest time between two resizes by `resize_all_terminals`; calls
+/// arriving sooner are coalesced into one at the end of it
+const RESIZE_ALL_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// How often `set_command_timeout`'s watchdog checks on the running
+/// command, and how long each signal it sends gets before the next
+#[cfg(unix)]
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+#[cfg(unix)]
+const COMMAND_TIMEOUT_GRACE: Duration = Duration::from_secs(3);
+
+/// Signals sent to a command over its timeout, in turn
+#[cfg(unix)]
+const TIMEOUT_SIGNALS: [(libc::c_int, &str); 3] = [
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGTERM, "SIGTERM"),
+    (libc::SIGKILL, "SIGKILL"),
+];
+
+/// How long `kill_terminal` lets the shell handle SIGHUP before killing it
+#[cfg(unix)]
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// How long `force_redraw` keeps the terminal one column narrower, so the
+/// program sees the first resize before the second
+const REDRAW_RESIZE_DELAY: Duration = Duration::from_millis(50);
+
+/// Consecutive failed output emits after which a session is detached
+const DETACH_AFTER_EMIT_FAILURES: u32 = 64;
+
+/// Software flow control characters (Ctrl-Q and Ctrl-S)
+const XON: u8 = 0x11;
+#[cfg(unix)]
+const XOFF: u8 = 0x13;
+
 pub struct PtySession {
-    writer: Box<dyn Write + Send>,
+    id: u32,
+    // Writer and master have their own locks, so commands on a session only
+    // need the session map's read lock
+    writer: Mutex<Box<dyn Write + Send>>,
     // We keep the master to prevent it from being dropped
     #[allow(dead_code)]
-    master: Box<dyn portable_pty::MasterPty + Send>,
+    master: Mutex<Box<dyn portable_pty::MasterPty + Send>>,
+    // The reader thread owns the child itself (to wait on it); this lets
+    // dropping the session terminate it
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    shared: Arc<SessionShared>,
+}
+
+impl Drop for PtySession {
+    /// Tear the session down deterministically: signal the child (SIGHUP on
+    /// Unix, like a closing terminal) while it is still running, then let
+    /// the writer and master close in field order. The reader thread then
+    /// sees EOF, reaps the child and emits `terminal-exit`.
+    fn drop(&mut self) {
+        let _reap = self.shared.reap_lock.lock();
+        if self.shared.alive.load(Ordering::SeqCst) {
+            if let Err(e) = self.killer.kill() {
+                log::warn!(
+                    target: LOG_TARGET, session_id = self.id;
+                    "Failed to signal child of terminal session {}: {}", self.id, e
+                );
+            }
+        }
+    }
+}
+
+/// Session state shared between the commands and the reader thread
+struct SessionShared {
+    // Cleared by the reader thread as soon as it sees EOF, before the session
+    // is removed from the map
+    alive: AtomicBool,
+    // Held to clear `alive`, and to signal the child while it's set, so the
+    // child isn't signalled once it may have been reaped and its PID reused
+    reap_lock: Mutex<()>,
+    // Globally unique id, for correlating the session outside this process
+    // (logs, recordings, exports) where the reusable numeric id won't do
+    uuid: String,
+    // The shell's PID, if the platform reports one
+    pid: Option<u32>,
+    // Working directory the shell was started in, if we chose one
+    cwd: Option<PathBuf>,
+    // `label_pty`, to name the session
+    label: Option<String>,
+    // Prefix of the names of its events, see `emit_event`
+    event_namespace: Option<String>,
+    // Slave device path, where the platform lets us look it up
+    pty_name: Option<String>,
+    // Output is stopped by an XOFF in the input, see `track_flow_control`
+    flow_paused: AtomicBool,
+    // Size of the banner kept for `get_banner`, 0 if none is
+    banner_limit: usize,
+    banner: Mutex<Vec<u8>>,
+    // The line discipline echoes input, as last seen by `track_echo`
+    echo: AtomicBool,
+    // Pending `get_shell_env` or `which_in_session`, taking its output out
+    // of the stream
+    shell_capture: Mutex<Option<ShellCapture>>,
+    // See `capture_between`
+    marker_captures: Mutex<Vec<MarkerCapture>>,
+    color_scheme: Mutex<Option<ColorScheme>>,
+    // Sent in reply to ENQ, see `set_answerback`; empty means no reply
+    answerback: Mutex<String>,
+    // The shell is at its prompt with nothing typed yet, going by shell
+    // integration marks (OSC 133); always false for shells without them
+    at_prompt: AtomicBool,
+    // Shell integration marks have been seen, and the last one started a
+    // command (OSC 133 C), see `is_at_prompt`
+    shell_integration: AtomicBool,
+    in_command: AtomicBool,
+    // Commands the shell marked running and finished, see `get_last_command`
+    commands: Mutex<CommandHistory>,
+    // See `set_command_timeout`; the watchdog thread runs while it's set
+    #[cfg(unix)]
+    command_timeout: Mutex<Option<Duration>>,
+    #[cfg(unix)]
+    watchdog_running: AtomicBool,
+    // Last state reported by `terminal-prompt-state`
+    prompt_state: AtomicBool,
+    // The shell's jobs, for `terminal-job-stopped`
+    #[cfg(unix)]
+    jobs: Mutex<JobTracker>,
+    // Waiting for the frontend's answer to `query_device_attributes`
+    device_attributes: Mutex<Option<oneshot::Sender<String>>>,
+    // Input written to the PTY, see `get_bytes_written`
+    bytes_written: AtomicU64,
+    // When input was last written, for `keepalive_secs`
+    last_input: Mutex<Instant>,
+    // When there was last input or output, see `get_idle_time`
+    last_activity: Mutex<Instant>,
+    // Input-related modes the program has set, see `get_terminal_modes`
+    modes: Mutex<TerminalModes>,
+    filters: Mutex<Vec<OutputFilter>>,
+    triggers: Mutex<Triggers>,
+    scrollback: Mutex<Scrollback>,
+    // Fed the same output as the scrollback, under its lock, while
+    // `enable_screen_model` is on
+    screen: Mutex<Option<Screen>>,
+    // Sequence number of the next output event; only advanced while holding
+    // the scrollback lock so the two always agree
+    next_seq: AtomicU64,
+    // Dedicated output stream registered via `subscribe_output`
+    output_channel: Mutex<Option<Channel<TerminalOutput>>>,
+    decoder: Mutex<OutputDecoder>,
+    // Chunks too garbled to decode go out as binary, see `set_auto_binary`
+    auto_binary: AtomicBool,
+    // Recording being played into the session's output, if any
+    replay: Mutex<Option<Arc<Replay>>>,
+    // Keystroke log started by `start_input_recording`
+    input_recording: Mutex<Option<InputRecorder>>,
+    // Structured output log started by `enable_ndjson_log`
+    ndjson_log: Mutex<Option<NdjsonLog>>,
+    // Cap on how fast output is emitted, see `set_output_rate_limit`
+    rate_limit: Mutex<Option<RateLimit>>,
+    // Reads coalesced before processing, see `set_output_batching`
+    batch_max_bytes: AtomicUsize,
+    batch_flush_ms: AtomicU64,
+    // Largest output event, see `set_max_emit_size`
+    max_emit_bytes: AtomicUsize,
+    // Read and write timestamps, collected while `enable_timing` is on
+    timing: Mutex<Option<Timing>>,
+    // Output emits that failed in a row, see `note_emit_result`
+    emit_failures: AtomicU32,
+    // No one is listening, or the frontend asked with `detach_output`:
+    // output only goes to the scrollback until it subscribes again
+    detached: AtomicBool,
+    // Tags set by the frontend, see `set_session_metadata`
+    metadata: Mutex<HashMap<String, String>>,
+    // See `set_exit_behavior`
+    exit_behavior: Mutex<ExitBehavior>,
+    // Exited but kept open by `ExitBehavior::Keep` until Enter is pressed
+    kept: AtomicBool,
+    // How to spawn it again for `ExitBehavior::Restart`; taken when it is
+    restart_options: Mutex<Option<SpawnOptions>>,
+    // Waiting in the `prewarm_shells` pool: emits no events and isn't listed
+    // until `take_prewarmed` hands it out
+    parked: AtomicBool,
+    #[cfg(unix)]
+    control_socket: Option<Arc<ControlSocket>>,
+    // FIFO the output is mirrored to, see `tee_output_to_pipe`
+    #[cfg(unix)]
+    output_pipe: Mutex<Option<OutputPipe>>,
+    // Holds the session's processes if spawned with `cgroup`; removed when
+    // the session is dropped
+    #[cfg(target_os = "linux")]
+    #[allow(dead_code)]
+    cgroup: Option<Cgroup>,
+    // Scripts the shell loads integration from, removed with the session
+    #[cfg(unix)]
+    #[allow(dead_code)]
+    integration_files: Option<IntegrationFiles>,
+}
+
+impl SessionShared {
+    /// State of a new session with `uuid`, everything at its default
+    fn new(uuid: String) -> Self {
+        Self {
+            alive: AtomicBool::new(true),
+            reap_lock: Mutex::new(()),
+            uuid,
+            pid: None,
+            cwd: None,
+            label: None,
+            event_namespace: None,
+            pty_name: None,
+            flow_paused: AtomicBool::new(false),
+            banner_limit: 0,
+            banner: Mutex::new(Vec::new()),
+            echo: AtomicBool::new(true),
+            shell_capture: Mutex::new(None),
+            marker_captures: Mutex::new(Vec::new()),
+            color_scheme: Mutex::new(None),
+            answerback: Mutex::new(String::new()),
+            at_prompt: AtomicBool::new(false),
+            shell_integration: AtomicBool::new(false),
+            in_command: AtomicBool::new(false),
+            commands: Mutex::new(CommandHistory::default()),
+            #[cfg(unix)]
+            command_timeout: Mutex::new(None),
+            #[cfg(unix)]
+            watchdog_running: AtomicBool::new(false),
+            prompt_state: AtomicBool::new(false),
+            #[cfg(unix)]
+            jobs: Mutex::new(JobTracker::default()),
+            device_attributes: Mutex::new(None),
+            bytes_written: AtomicU64::new(0),
+            last_input: Mutex::new(Instant::now()),
+            last_activity: Mutex::new(Instant::now()),
+            modes: Mutex::new(TerminalModes::default()),
+            filters: Mutex::new(Vec::new()),
+            triggers: Mutex::new(Triggers::default()),
+            scrollback: Mutex::new(Scrollback::new(DEFAULT_SCROLLBACK_BYTES)),
+            screen: Mutex::new(None),
+            next_seq: AtomicU64::new(0),
+            output_channel: Mutex::new(None),
+            decoder: Mutex::new(OutputDecoder::default()),
+            auto_binary: AtomicBool::new(false),
+            replay: Mutex::new(None),
+            input_recording: Mutex::new(None),
+            ndjson_log: Mutex::new(None),
+            rate_limit: Mutex::new(None),
+            batch_max_bytes: AtomicUsize::new(DEFAULT_BATCH_BYTES),
+            batch_flush_ms: AtomicU64::new(0),
+            max_emit_bytes: AtomicUsize::new(DEFAULT_MAX_EMIT_BYTES),
+            timing: Mutex::new(None),
+            emit_failures: AtomicU32::new(0),
+            detached: AtomicBool::new(false),
+            metadata: Mutex::new(HashMap::new()),
+            exit_behavior: Mutex::new(ExitBehavior::default()),
+            kept: AtomicBool::new(false),
+            restart_options: Mutex::new(None),
+            parked: AtomicBool::new(false),
+            #[cfg(unix)]
+            control_socket: None,
+            #[cfg(unix)]
+            output_pipe: Mutex::new(None),
+            #[cfg(target_os = "linux")]
+            cgroup: None,
+            #[cfg(unix)]
+            integration_files: None,
+        }
+    }
+}
+
+/// Light/dark hint for programs that pick their theme from the terminal
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    /// `COLORFGBG` value (`fg;bg` as ANSI color indices) read by vim, bat etc.
+    fn colorfgbg(self) -> &'static str {
+        match self {
+            Self::Light => "0;15",
+            Self::Dark => "15;0",
+        }
+    }
+
+    /// Reply to an OSC 10/11 foreground/background color query, or `None` if
+    /// the payload isn't one
+    fn query_reply(self, payload: &[u8], bel: bool) -> Option<Vec<u8>> {
+        let (fg, bg) = match self {
+            Self::Light => ("0000/0000/0000", "ffff/ffff/ffff"),
+            Self::Dark => ("ffff/ffff/ffff", "0000/0000/0000"),
+        };
+        let color = match payload {
+            b"10;?" => fg,
+            b"11;?" => bg,
+            _ => return None,
+        };
+        let code = &payload[..2];
+        let terminator: &[u8] = if bel { b"\x07" } else { b"\x1b\\" };
+        Some([b"\x1b]", code, b";rgb:", color.as_bytes(), terminator].concat())
+    }
+}
+
+/// `PtySession::write_input` on its own: write `data`, or fail with
+/// `SessionExited` once `alive` is cleared
+fn write_while_alive(
+    writer: &mut dyn Write,
+    alive: &AtomicBool,
+    session_id: u32,
+    data: &[u8],
+) -> Result<(), TerminalError> {
+    if !alive.load(Ordering::SeqCst) {
+        return Err(TerminalError::SessionExited(session_id));
+    }
+    writer
+        .write_all(data)
+        .and_then(|_| writer.flush())
+        .map_err(|e| {
+            // The child may have exited between the check and the write
+            if alive.load(Ordering::SeqCst) {
+                TerminalError::Pty(format!("Failed to write to terminal: {}", e))
+            } else {
+                TerminalError::SessionExited(session_id)
+            }
+        })
+}
+
+impl PtySession {
+    /// Write input to the PTY, refusing once the child has exited so callers
+    /// get `SessionExited` instead of a raw `EIO` from the dead PTY
+    fn write_input(&self, session_id: u32, data: &[u8]) -> Result<(), TerminalError> {
+        let mut writer = self.writer.lock();
+        write_while_alive(&mut **writer, &self.shared.alive, session_id, data)?;
+        let n = data.len() as u64;
+        self.shared.bytes_written.fetch_add(n, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether the line discipline has `ECHO` set
+    #[cfg(unix)]
+    fn echo(&self) -> Option<bool> {
+        let fd = self.master.lock().as_raw_fd()?;
+        let t = termios::get(fd).ok()?;
+        Some(t.c_lflag & libc::ECHO != 0)
+    }
+
+    /// Whether the shell is waiting at its prompt rather than running a
+    /// command, see `is_at_prompt`
+    fn at_prompt(&self) -> bool {
+        let integration = self.shared.shell_integration.load(Ordering::SeqCst);
+        if integration && self.shared.in_command.load(Ordering::SeqCst) {
+            return false;
+        }
+        // Also catches commands of shells that only mark the prompt: the
+        // shell itself is in the foreground unless running one
+        #[cfg(unix)]
+        {
+            let leader = self.master.lock().process_group_leader();
+            leader.is_some_and(|p| Some(p as u32) == self.shared.pid)
+        }
+        #[cfg(not(unix))]
+        integration
+    }
+
+    /// Whether the line discipline has `IXON` (XOFF/XON stop and restart
+    /// output) and `IXANY` (any input restarts it) set
+    #[cfg(unix)]
+    fn flow_control(&self) -> Option<(bool, bool)> {
+        let fd = self.master.lock().as_raw_fd()?;
+        let t = termios::get(fd).ok()?;
+        Some((t.c_iflag & libc::IXON != 0, t.c_iflag & libc::IXANY != 0))
+    }
 }
 
 pub struct TerminalState {
-    sessions: Mutex<HashMap<u32, PtySession>>,
+    // Write-locked only to add or remove sessions
+    sessions: RwLock<HashMap<u32, PtySession>>,
+    // Sessions whose child exits sooner than this are reported as failing
+    // to start
+    startup_failure_ms: AtomicU64,
+    // Shells printing nothing for longer than this are reported as slow to
+    // start, 0 disables
+    slow_startup_ms: AtomicU64,
+    // More output than this within the window is a burst, 0 disables
+    burst_bytes: AtomicU64,
+    burst_window_ms: AtomicU64,
+    // Scrollback cap of new sessions, see `apply_scrollback_limit_all`
+    scrollback_bytes: AtomicUsize,
+    policy: TerminalPolicy,
+    // Checked by `check_paste_safety`
+    dangerous_commands: Mutex<Vec<String>>,
+    // Parked sessions from `prewarm_shells`, oldest first
+    prewarmed: Mutex<VecDeque<u32>>,
+    // See `link_input`
+    input_links: Mutex<Vec<InputLink>>,
+    // Sessions that exited, oldest first, see `list_recent_exits`
+    recent_exits: Mutex<VecDeque<(Instant, TerminalExit)>>,
+    // Typed into every new session, see `set_global_init_command`
+    global_init_command: Mutex<Option<String>>,
+    // See `resize_all_terminals`
+    grid_resize: Mutex<GridResize>,
+}
+
+/// State of `resize_all_terminals`' debounce
+#[derive(Default)]
+struct GridResize {
+    // Size to apply at the end of the debounce, as `(cols, rows)`
+    pending: Option<(u16, u16)>,
+    last: Option<Instant>,
+    // A thread is waiting to apply `pending`
+    scheduled: bool,
+}
+
+/// What `resize_all_terminals` does with a call, see `GridResize::request`
+#[derive(Debug, PartialEq)]
+enum GridResizeStep {
+    /// Resize right away
+    Now,
+    /// Apply the size after waiting this long
+    Schedule(Duration),
+    /// A wait is already scheduled, and will apply this size instead
+    Coalesced,
+}
+
+impl GridResize {
+    /// Take a call for `size` at `now`: apply it unless the last resize was
+    /// less than `RESIZE_ALL_DEBOUNCE` ago, else keep it for the scheduled
+    /// resize, scheduling one if there's none yet
+    fn request(&mut self, size: (u16, u16), now: Instant) -> GridResizeStep {
+        let wait = self.last.map_or(Duration::ZERO, |last| {
+            RESIZE_ALL_DEBOUNCE.saturating_sub(now.saturating_duration_since(last))
+        });
+        if wait.is_zero() && !self.scheduled {
+            self.last = Some(now);
+            return GridResizeStep::Now;
+        }
+        self.pending = Some(size);
+        if self.scheduled {
+            return GridResizeStep::Coalesced;
+        }
+        self.scheduled = true;
+        GridResizeStep::Schedule(wait)
+    }
+
+    /// End the scheduled wait at `now`, returning the size to apply
+    fn fire(&mut self, now: Instant) -> Option<(u16, u16)> {
+        self.scheduled = false;
+        self.last = Some(now);
+        self.pending.take()
+    }
+}
+
+/// A session whose input is copied to another, see `link_input`. The UUIDs
+/// keep a link from carrying over to a later session reusing an id.
+struct InputLink {
+    source: u32,
+    source_uuid: String,
+    follower: u32,
+    follower_uuid: String,
 }
 
 impl Default for TerminalState {
     fn default() -> Self {
+        Self::with_policy(TerminalPolicy::default())
+    }
+}
+
+impl TerminalState {
+    pub fn with_policy(policy: TerminalPolicy) -> Self {
         Self {
-            sessions: Mutex::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            startup_failure_ms: AtomicU64::new(DEFAULT_STARTUP_FAILURE_MS),
+            slow_startup_ms: AtomicU64::new(DEFAULT_SLOW_STARTUP_MS),
+            burst_bytes: AtomicU64::new(DEFAULT_BURST_BYTES),
+            burst_window_ms: AtomicU64::new(DEFAULT_BURST_WINDOW_MS),
+            scrollback_bytes: AtomicUsize::new(DEFAULT_SCROLLBACK_BYTES),
+            policy,
+            dangerous_commands: Mutex::new(
+                paste::DEFAULT_DANGEROUS_COMMANDS
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+            ),
+            prewarmed: Mutex::new(VecDeque::new()),
+            input_links: Mutex::new(Vec::new()),
+            recent_exits: Mutex::new(VecDeque::new()),
+            global_init_command: Mutex::new(None),
+            grid_resize: Mutex::new(GridResize::default()),
         }
     }
 }
 
 #[derive(Clone, serde::Serialize)]
-struct TerminalOutput {
+pub struct TerminalOutput {
+    session_id: u32,
+    session_uuid: String,
+    /// Per-session sequence number, see `get_scrollback_and_subscribe`
+    seq: u64,
+    data: String,
+}
+
+/// Output of a session in raw mode, base64-encoded
+#[derive(Clone, serde::Serialize)]
+struct TerminalOutputBinary {
     session_id: u32,
+    session_uuid: String,
+    seq: u64,
     data: String,
 }
 
+/// Payload of `terminal-binary-detected`
+#[derive(Clone, serde::Serialize)]
+struct BinaryDetected {
+    session_id: u32,
+    session_uuid: String,
+    /// Of the first chunk emitted as binary
+    seq: u64,
+}
+
+/// Payload for events that only identify the session
+#[derive(Clone, serde::Serialize)]
+struct SessionEvent {
+    session_id: u32,
+    session_uuid: String,
+}
+
+impl SessionEvent {
+    fn new(session_id: u32, shared: &SessionShared) -> Self {
+        Self {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+        }
+    }
+}
+
+/// Emit one of a session's events, named `<namespace>:<event>` if it was
+/// spawned with an `event_namespace`
+fn emit_event<S: serde::Serialize + Clone>(
+    app: &AppHandle,
+    shared: &SessionShared,
+    event: &str,
+    payload: S,
+) -> tauri::Result<()> {
+    match &shared.event_namespace {
+        Some(namespace) => app.emit(&format!("{}:{}", namespace, event), payload),
+        None => app.emit(event, payload),
+    }
+}
+
 #[derive(Clone, serde::Serialize)]
-struct TerminalExit {
+pub struct TerminalExit {
     session_id: u32,
+    session_uuid: String,
+    /// The `label_pty` it was spawned with
+    label: Option<String>,
+    /// The process's exit code, or 1 if it didn't exit normally
     exit_code: Option<u32>,
+    /// In detail how it ended. Only a code on Windows.
+    reason: Option<ExitReason>,
+    /// Wall-clock time from spawning to the process being reaped
+    duration_ms: u64,
+}
+
+impl TerminalExit {
+    /// `terminal-exit` for a shell spawned at `spawned_at` that just ended
+    fn new(
+        session_id: u32,
+        shared: &SessionShared,
+        reason: Option<ExitReason>,
+        spawned_at: Instant,
+    ) -> Self {
+        Self {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+            label: shared.label.clone(),
+            exit_code: reason.map(ExitReason::exit_code),
+            reason,
+            duration_ms: spawned_at.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Sent alongside `terminal-exit` when the child exits right after spawning,
+/// with whatever it printed (e.g. an error from a broken rc file)
+#[derive(Clone, serde::Serialize)]
+struct TerminalStartupFailed {
+    session_id: u32,
+    session_uuid: String,
+    exit_code: Option<u32>,
+    captured_output: String,
+}
+
+/// Sent when a shell takes longer than `set_slow_startup_threshold` allows
+/// to print anything, once it does
+#[derive(Clone, serde::Serialize)]
+struct TerminalSlowStartup {
+    session_id: u32,
+    session_uuid: String,
+    elapsed_ms: u64,
+    /// Where to look for what's slowing it down
+    hint: String,
+}
+
+/// Spawn a new PTY shell session.
+///
+/// Every setting is a field of `options`, named in camelCase (e.g.
+/// `{ cols: 80, rows: 24, useHomeFallback: false }`), and all of them, like
+/// `options` itself, may be left out.
+///
+/// With `control_socket` (Unix only), the session is also exposed on a Unix
+/// domain socket at that path: bytes written to it go to the PTY and output is
+/// mirrored to every client. The socket is removed when the session exits.
+/// Only allowed with `TerminalPolicy::allow_control_socket`.
+///
+/// `read_buffer_size` tunes how many bytes each PTY read may return (default
+/// 4 KiB, clamped to 1 KiB..1 MiB). Larger buffers mean fewer syscalls and
+/// events for bulk output. The PTY's own kernel buffer can't be resized
+/// (`F_SETPIPE_SZ` only applies to pipes), so a single read still returns at
+/// most what the line discipline has queued.
+///
+/// `run_as` runs the shell as another user via `sudo -u` (or the escalation
+/// tool configured in `TerminalPolicy`), with any password prompt shown in
+/// the terminal. It's refused unless the policy allows it.
+///
+/// The working directory is resolved in this order:
+/// 1. `cwd`, if given; a relative path is taken from the app's own working
+///    directory
+/// 2. `$HOME`, unless `use_home_fallback` is false
+/// 3. the app's own working directory
+///
+/// `label_pty` names the session in the log line recording its PTY device
+/// (see `get_pty_name`), to tell sessions apart when debugging, and in
+/// `terminal-exit` and `list_recent_exits`.
+///
+/// `cgroup` (Linux only) caps the CPU and memory of the session's process
+/// tree by putting the shell in a transient cgroup v2 under the app's own
+/// cgroup. That needs the unified hierarchy mounted at `/sys/fs/cgroup` and
+/// the app's cgroup delegated to its user (e.g. a systemd unit or scope with
+/// `Delegate=yes`); otherwise the spawn fails with an unsupported or
+/// permission error. If the app's cgroup holds processes itself, the app
+/// first moves into a `karpi-app` leaf so controllers can be enabled. The
+/// shell is moved in right after it starts, and whatever is left inside
+/// when the session ends is killed.
+///
+/// `keepalive_secs` writes `keepalive_data` (default a NUL byte, which
+/// shells and most programs ignore) whenever the session has had no input
+/// for that many seconds, so e.g. an idle `ssh` connection isn't dropped by
+/// the remote end or a NAT in between. Keepalive writes count as input like
+/// any other, so anything watching the session for idleness sees it as
+/// active. Beware that some programs do act on NUL (vim's insert mode
+/// repeats the last insertion on Ctrl-@).
+///
+/// `capture_banner_bytes` keeps a copy of the shell's first output, up to
+/// that many bytes (at most 64 KiB), for `get_banner`.
+///
+/// `rlimits` (Unix only) bounds each process of the session with
+/// `setrlimit`, applied before the shell starts and inherited by everything
+/// it runs: `address_space` in bytes, `cpu_secs` of CPU time, and
+/// `open_files`. Unlike `cgroup` this needs no privileges or setup, but
+/// limits each process rather than the session as a whole. Each is set as
+/// both the soft and the hard limit, so programs in the session can't raise
+/// it again (only a privileged process can raise a hard limit), and they
+/// can't be above the app's own hard limits. A process over its CPU time is
+/// killed; allocations and opens beyond the others fail. macOS doesn't
+/// enforce `address_space`.
+///
+/// `inject_shell_integration` (Unix only, off by default) makes bash, zsh
+/// and fish send the shell integration marks themselves, without any change
+/// to the user's rc files: OSC 133 prompt, command start and command end
+/// (with its exit code), and OSC 7 with the working directory at each
+/// prompt. That's what `is_at_prompt` and `terminal-prompt-state` go by.
+/// The scripts are written to a private temporary directory that's removed
+/// with the session. Per shell:
+/// - bash is started as `bash --rcfile <script> -i` instead of `bash -l`,
+///   since a login shell ignores `--rcfile`. The script reads
+///   `/etc/profile` and the first of `~/.bash_profile`, `~/.bash_login`
+///   and `~/.profile` as a login shell would, then adds a hook to the front
+///   of `PROMPT_COMMAND` and the command mark to `PS0`. The shell doesn't
+///   count as a login shell (`shopt login_shell`), and bash before 4.4
+///   (macOS's `/bin/bash`) has no `PS0`, so no command start marks.
+/// - zsh gets `ZDOTDIR` pointing at the scripts. Each of `.zshenv`,
+///   `.zprofile`, `.zshrc` and `.zlogin` there loads the user's own (from
+///   their `ZDOTDIR`, or `$HOME`), `.zshrc` then adds `precmd` and
+///   `preexec` hooks and `.zlogin` puts `ZDOTDIR` back.
+/// - fish runs `source <script>` with `--init-command`, after its own
+///   configuration, adding handlers for its prompt and exec events.
+///
+/// A setup that replaces these hooks afterwards (e.g. assigns
+/// `PROMPT_COMMAND` or `precmd_functions` from a prompt theme loaded
+/// later) loses the marks. Other shells are spawned as usual, with a
+/// warning logged, and it's refused with `run_as`, since the other user
+/// can't read the scripts. Nested shells and remote ones over `ssh` don't
+/// get it. The working directory in OSC 7 isn't percent-encoded.
+///
+/// `event_namespace` prefixes the name of every event about the session,
+/// e.g. `myapp:terminal-output` instead of `terminal-output`, so frontends
+/// hosted side by side in one app each listen to their own sessions only.
+/// It may contain ASCII letters, digits, `-`, `/` and `_`, as event names
+/// can. Events not about one session, like `terminal-subsystem-reset`,
+/// keep their names. A restarted session's replacement keeps the namespace.
+///
+/// `scrollback_cr_handling: "collapse"` applies carriage returns to the
+/// scrollback, so a progress bar redrawing its line with `\r` leaves only
+/// its last frame rather than thousands of them. Text after a `\r` replaces
+/// the line so far instead of overwriting it column by column, which is
+/// what redrawing the whole line amounts to; a `\r` before a newline is
+/// kept. `"raw"` (the default) keeps output exactly as it came. Either way
+/// the output emitted live is untouched.
+///
+/// `path_prepend` puts directories, such as a project's `node_modules/.bin`
+/// or a toolchain's `bin`, in front of the `PATH` the shell inherits, in
+/// the order given. A login shell's profile may still change `PATH`
+/// afterwards: macOS's `path_helper` puts the system directories first,
+/// and some distributions' `/etc/profile` set it outright. A restarted
+/// session's replacement gets the same directories.
+///
+/// `env` sets variables in the shell's environment, over the app's own and
+/// `TERM`, `COLORTERM` and `COLORFGBG`. Names are checked and trimmed as
+/// `normalize_env` does, and any that are wrong fail the spawn with every
+/// problem listed.
+///
+/// `quiet` keeps the login banner out of a fresh pane, best-effort: the
+/// shell starts as an interactive shell (`-i`) rather than a login shell,
+/// so it skips the profiles (`/etc/profile`, `~/.profile`, bash's
+/// `~/.bash_profile`, zsh's `.zprofile` and `.zlogin`, fish's login-only
+/// config) where a message of the day and the like are printed. Its rc
+/// files (`~/.bashrc`, `.zshrc`, `config.fish`) still run and can still
+/// print, and whatever the profiles add to `PATH` is missing. fish also
+/// gets an empty `fish_greeting`, unless its config sets one. No
+/// `~/.hushlogin` is created; a banner printed by something checking for
+/// it only goes if it's there. With `inject_shell_integration` the shell
+/// stays a login shell, as the injected scripts load the login files
+/// themselves. Off by default.
+///
+/// `locale`, such as `de_DE.UTF-8`, sets `LANG`, `LC_ALL` and `LC_CTYPE`,
+/// over `env`. On Unix it must be one `locale -a` lists, though the
+/// codeset may be spelled differently (`UTF-8` for `utf8`), or the spawn
+/// fails with a few installed locales to pick from: a missing locale
+/// leaves programs falling back to `C` and mangling non-ASCII text. The
+/// list is read once, so a locale installed while the app runs is only
+/// accepted after a restart. Elsewhere it isn't checked.
+#[tauri::command]
+pub fn spawn_terminal(app: AppHandle, options: Option<SpawnOptions>) -> Result<u32, TerminalError> {
+    spawn_session(app, options.unwrap_or_default())
+}
+
+/// Check an environment for `spawn_terminal`'s `env`, e.g. to validate a
+/// form before submitting it: the variables with their names trimmed, or a
+/// message for each one the platform wouldn't allow, such as a name with
+/// `=` or NUL in it
+#[tauri::command]
+pub fn normalize_env(env: HashMap<String, String>) -> Result<HashMap<String, String>, Vec<String>> {
+    environment::normalize(env)
+}
+
+/// Make a session of an already open file descriptor rather than a new PTY
+/// and shell, e.g. a PTY master another process opened or a socket, to give
+/// any byte stream a session's scrollback, events and the rest. Output is
+/// read from the descriptor and input written to it; resizing sets its
+/// window size if it's a terminal. There's no child: the session ends, with
+/// `terminal-exit` and exit code 0, when the stream does or it's killed,
+/// and it's never restarted. Commands that type into a shell, like
+/// `get_shell_env`, assume one is reading, and the global init command
+/// isn't sent.
+///
+/// The session owns the descriptor from the call on: it's put in blocking
+/// mode and closed when the session ends, or straight away if adopting it
+/// fails after it was found open. So the caller mustn't use or close it
+/// afterwards; to keep a copy, pass a duplicate (`dup`), but note that the
+/// two share blocking mode. Only terminals and sockets are taken, and not
+/// one a session already has, such as another session's PTY master or a
+/// duplicate of it; anything else, or a descriptor that isn't open, is an
+/// error. As it can take over any descriptor in the app, it's only
+/// allowed with `TerminalPolicy::allow_adopt_fd`. Unix only.
+#[tauri::command]
+pub fn adopt_fd(
+    app: AppHandle,
+    raw_fd: i32,
+    cols: Option<u16>,
+    rows: Option<u16>,
+) -> Result<u32, TerminalError> {
+    #[cfg(unix)]
+    {
+        if !app.state::<TerminalState>().policy.allow_adopt_fd {
+            return Err(TerminalError::PermissionDenied(
+                "Adopting file descriptors is disabled by policy".to_string(),
+            ));
+        }
+        let identity = adopted::identity(raw_fd).map_err(|e| {
+            TerminalError::InvalidArgument(format!("Can't adopt file descriptor {}: {}", raw_fd, e))
+        })?;
+        if let Some(session_id) = session_with_fd(&app, &identity) {
+            return Err(TerminalError::InvalidArgument(format!(
+                "File descriptor {} belongs to terminal session {}",
+                raw_fd, session_id
+            )));
+        }
+        let options = SpawnOptions {
+            cols,
+            rows,
+            adopt_fd: Some(raw_fd),
+            ..Default::default()
+        };
+        spawn_session(app, options)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (app, raw_fd, cols, rows);
+        Err(TerminalError::Unsupported(
+            "Adopting file descriptors is only supported on Unix".to_string(),
+        ))
+    }
+}
+
+/// The session whose PTY master, or adopted descriptor, is `identity`
+#[cfg(unix)]
+fn session_with_fd(app: &AppHandle, identity: &adopted::Identity) -> Option<u32> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    sessions.values().find_map(|session| {
+        let fd = session.master.lock().as_raw_fd()?;
+        let same = adopted::identity(fd).is_ok_and(|other| other == *identity);
+        same.then_some(session.id)
+    })
 }
 
-/// Spawn a new PTY shell session
+/// Returned by `spawn_in_repo`
+#[derive(serde::Serialize)]
+pub struct RepoSession {
+    session_id: u32,
+    /// Top of the enclosing Git working tree, or the given directory if
+    /// there was none
+    root: String,
+    /// The repository, if one was found
+    git_dir: Option<String>,
+}
+
+/// Spawn a session at the top of the Git working tree enclosing `path` (a
+/// directory or a file in one), so e.g. a file's terminal opens at its
+/// project root. Linked worktrees and submodules, whose `.git` is a file,
+/// are followed to their repository. `GIT_DIR` and `GIT_WORK_TREE` are
+/// exported for the shell unless `export_git_env` is false; note that git
+/// then uses this repository even after `cd`ing into another one. Without
+/// an enclosing repository the session starts in `path` itself.
 #[tauri::command]
-pub fn spawn_terminal(
+pub fn spawn_in_repo(
     app: AppHandle,
+    path: String,
+    cols: Option<u16>,
+    rows: Option<u16>,
+    export_git_env: Option<bool>,
+) -> Result<RepoSession, TerminalError> {
+    let start = resolve_cwd(Some(path.clone()), false)
+        .and_then(|p| std::fs::canonicalize(p).ok())
+        .ok_or_else(|| TerminalError::InvalidArgument(format!("No such path: {}", path)))?;
+    let start = if start.is_dir() {
+        start
+    } else {
+        // Canonical, so a file always has a parent
+        start.parent().map(Path::to_path_buf).unwrap_or(start)
+    };
+
+    let repo = repo::find(&start);
+    let root = repo.as_ref().map_or(start.clone(), |r| r.root.clone());
+    let mut env = Vec::new();
+    if let Some(repo) = &repo {
+        if export_git_env.unwrap_or(true) {
+            env.push(("GIT_DIR", repo.git_dir.clone()));
+            env.push(("GIT_WORK_TREE", repo.root.clone()));
+        }
+    }
+    let options = SpawnOptions {
+        cols,
+        rows,
+        cwd: Some(root.display().to_string()),
+        env,
+        ..Default::default()
+    };
+    let session_id = spawn_session(app, options)?;
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Terminal session {} opened in {} (repository {})",
+        session_id,
+        root.display(),
+        if repo.is_some() { "found" } else { "not found" }
+    );
+    Ok(RepoSession {
+        session_id,
+        root: root.display().to_string(),
+        git_dir: repo.map(|r| r.git_dir.display().to_string()),
+    })
+}
+
+/// The options of `spawn_terminal`, plus state restored by `import_session`
+/// and the like, which the frontend can't set
+#[derive(Default, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SpawnOptions {
     cols: Option<u16>,
     rows: Option<u16>,
     cwd: Option<String>,
-) -> Result<u32, String> {
-    let pty_system = native_pty_system();
+    color_scheme: Option<ColorScheme>,
+    control_socket: Option<String>,
+    read_buffer_size: Option<usize>,
+    run_as: Option<String>,
+    use_home_fallback: Option<bool>,
+    label_pty: Option<String>,
+    cgroup: Option<CgroupLimits>,
+    keepalive_secs: Option<u64>,
+    keepalive_data: Option<String>,
+    capture_banner_bytes: Option<usize>,
+    rlimits: Option<RlimitSpec>,
+    inject_shell_integration: bool,
+    event_namespace: Option<String>,
+    scrollback_cr_handling: CrHandling,
+    path_prepend: Option<Vec<String>>,
+    /// `env` of `spawn_terminal`
+    #[serde(rename = "env")]
+    user_env: Option<HashMap<String, String>>,
+    quiet: bool,
+    locale: Option<String>,
+    /// Read and write this instead of a new PTY, for `adopt_fd`
+    #[cfg(unix)]
+    #[serde(skip)]
+    adopt_fd: Option<std::os::unix::io::RawFd>,
+    /// Output emitted, and kept as scrollback, before anything from the shell
+    #[serde(skip)]
+    preload: Vec<u8>,
+    #[serde(skip)]
+    decoder: OutputDecoder,
+    /// Set in the shell's environment after our own variables
+    #[serde(skip)]
+    env: Vec<(&'static str, PathBuf)>,
+    /// Start parked, for `prewarm_shells`
+    #[serde(skip)]
+    parked: bool,
+    /// Carried over when restarting: the id, so the frontend keeps its view,
+    /// and the behavior and output channel of the session it replaces
+    #[serde(skip)]
+    session_id: Option<u32>,
+    #[serde(skip)]
+    exit_behavior: ExitBehavior,
+    #[serde(skip)]
+    output_channel: Option<Channel<TerminalOutput>>,
+}
+
+impl SpawnOptions {
+    /// The same configuration, to spawn the session again on exit
+    fn for_restart(&self) -> Self {
+        Self {
+            cols: self.cols,
+            rows: self.rows,
+            cwd: self.cwd.clone(),
+            color_scheme: self.color_scheme,
+            control_socket: self.control_socket.clone(),
+            read_buffer_size: self.read_buffer_size,
+            run_as: self.run_as.clone(),
+            use_home_fallback: self.use_home_fallback,
+            label_pty: self.label_pty.clone(),
+            cgroup: self.cgroup.clone(),
+            keepalive_secs: self.keepalive_secs,
+            keepalive_data: self.keepalive_data.clone(),
+            capture_banner_bytes: self.capture_banner_bytes,
+            rlimits: self.rlimits.clone(),
+            inject_shell_integration: self.inject_shell_integration,
+            event_namespace: self.event_namespace.clone(),
+            scrollback_cr_handling: self.scrollback_cr_handling,
+            path_prepend: self.path_prepend.clone(),
+            user_env: self.user_env.clone(),
+            quiet: self.quiet,
+            locale: self.locale.clone(),
+            env: self.env.clone(),
+            ..Default::default()
+        }
+    }
+}
 
+fn spawn_session(app: AppHandle, options: SpawnOptions) -> Result<u32, TerminalError> {
+    let restart_options = options.for_restart();
+    let SpawnOptions {
+        cols,
+        rows,
+        cwd,
+        color_scheme,
+        control_socket,
+        read_buffer_size,
+        run_as,
+        use_home_fallback,
+        label_pty,
+        cgroup,
+        keepalive_secs,
+        keepalive_data,
+        capture_banner_bytes,
+        rlimits,
+        inject_shell_integration,
+        event_namespace,
+        scrollback_cr_handling,
+        path_prepend,
+        user_env,
+        quiet,
+        locale,
+        #[cfg(unix)]
+        adopt_fd,
+        preload,
+        decoder,
+        env,
+        parked,
+        session_id,
+        exit_behavior,
+        output_channel,
+    } = options;
+    if keepalive_secs == Some(0) {
+        return Err(TerminalError::InvalidArgument(
+            "Keepalive interval must be at least 1 second".to_string(),
+        ));
+    }
+    if capture_banner_bytes == Some(0) {
+        return Err(TerminalError::InvalidArgument(
+            "Banner size must be above 0".to_string(),
+        ));
+    }
+    let user_env = user_env
+        .map(environment::normalize)
+        .transpose()
+        .map_err(|problems| {
+            TerminalError::InvalidArgument(format!("Invalid environment: {}", problems.join("; ")))
+        })?;
+    if let Some(locale) = &locale {
+        locale::check(locale).map_err(TerminalError::InvalidArgument)?;
+    }
+    if let Some(namespace) = &event_namespace {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '/' | '_');
+        if namespace.is_empty() || !namespace.chars().all(valid) {
+            return Err(TerminalError::InvalidArgument(format!(
+                "Invalid event namespace {:?}",
+                namespace
+            )));
+        }
+    }
     let size = PtySize {
-        rows: rows.unwrap_or(24),
-        cols: cols.unwrap_or(80),
+        rows: rows.unwrap_or(DEFAULT_ROWS),
+        cols: cols.unwrap_or(DEFAULT_COLS),
         pixel_width: 0,
         pixel_height: 0,
     };
 
-    let pair = pty_system
-        .openpty(size)
-        .map_err(|e| format!("Failed to open PTY: {}", e))?;
+    #[cfg(unix)]
+    let pair = match adopt_fd {
+        Some(fd) => adopted::pair(fd, size).map_err(|e| {
+            TerminalError::InvalidArgument(format!("Can't adopt file descriptor {}: {}", fd, e))
+        })?,
+        None => open_pty(&app, size)?,
+    };
+    #[cfg(not(unix))]
+    let pair = open_pty(&app, size)?;
+
+    // Get reader for output and writer for input, before spawning so that
+    // failing here leaves no child to reap
+    let mut reader = pair
+        .master
+        .try_clone_reader()
+        .map_err(|e| TerminalError::Pty(format!("Failed to clone reader: {}", e)))?;
+    let writer = pair
+        .master
+        .take_writer()
+        .map_err(|e| TerminalError::Pty(format!("Failed to take writer: {}", e)))?;
+
+    let shell = default_shell();
+
+    let mut cmd = match &run_as {
+        Some(user) => app
+            .state::<TerminalState>()
+            .policy
+            .run_as_command(user, &shell)?,
+        None => {
+            let mut cmd = CommandBuilder::new(&shell);
+            cmd.args(SHELL_ARGS);
+            cmd
+        }
+    };
+    if quiet {
+        quiet_shell(&shell, &mut cmd, inject_shell_integration);
+    }
 
-    // Get the user's default shell
-    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+    #[cfg(unix)]
+    let integration_files = match (inject_shell_integration, &run_as) {
+        (false, _) => None,
+        (true, Some(_)) => {
+            return Err(TerminalError::InvalidArgument(
+                "Shell integration can't be injected into a run_as session".to_string(),
+            ))
+        }
+        (true, None) => {
+            let files = IntegrationFiles::inject(&shell, &mut cmd).map_err(|e| {
+                TerminalError::Pty(format!("Failed to write shell integration: {}", e))
+            })?;
+            if files.is_none() {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "No shell integration for {}, spawning it without", shell
+                );
+            }
+            files
+        }
+    };
+    #[cfg(not(unix))]
+    if inject_shell_integration {
+        return Err(TerminalError::Unsupported(
+            "Shell integration injection is only supported on Unix".to_string(),
+        ));
+    }
 
-    let mut cmd = CommandBuilder::new(&shell);
-    cmd.arg("-l"); // Login shell for proper PATH
+    #[cfg(unix)]
+    if let Some(rlimits) = &rlimits {
+        rlimits.validate()?;
+        rlimits.wrap(&mut cmd);
+    }
+    #[cfg(not(unix))]
+    if rlimits.is_some() {
+        return Err(TerminalError::Unsupported(
+            "Resource limits are only supported on Unix".to_string(),
+        ));
+    }
 
     // Set working directory
-    if let Some(dir) = cwd {
+    let cwd = resolve_cwd(cwd, use_home_fallback.unwrap_or(true));
+    if let Some(dir) = &cwd {
         cmd.cwd(dir);
-    } else if let Some(home) = std::env::var("HOME").ok() {
-        cmd.cwd(home);
     }
 
     // Set environment variables for better terminal experience
-    cmd.env("TERM", "xterm-256color");
-    cmd.env("COLORTERM", "truecolor");
+    for (key, value) in DEFAULT_ENV {
+        cmd.env(key, value);
+    }
+    if let Some(scheme) = color_scheme {
+        cmd.env("COLORFGBG", scheme.colorfgbg());
+    }
+    for (key, value) in user_env.into_iter().flatten() {
+        cmd.env(key, value);
+    }
+    if let Some(locale) = &locale {
+        for key in locale::LOCALE_VARS {
+            cmd.env(key, locale);
+        }
+    }
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    if let Some(dirs) = &path_prepend {
+        let path = prepend_path(dirs, cmd.get_env("PATH"))?;
+        cmd.env("PATH", path);
+    }
 
-    let mut child = pair
-        .slave
-        .spawn_command(cmd)
-        .map_err(|e| format!("Failed to spawn shell: {}", e))?;
+    // Set up before spawning so an unusable cgroup fails without a child
+    #[cfg(target_os = "linux")]
+    let cgroup = cgroup.map(|limits| Cgroup::create(&limits)).transpose()?;
+    #[cfg(not(target_os = "linux"))]
+    if cgroup.is_some() {
+        return Err(TerminalError::Unsupported(
+            "Cgroups are only supported on Linux".to_string(),
+        ));
+    }
 
-    let session_id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst);
+    // Bind before spawning so a bad path fails without leaving a child behind
+    if control_socket.is_some() && !app.state::<TerminalState>().policy.allow_control_socket {
+        return Err(TerminalError::PermissionDenied(
+            "Control sockets are disabled by policy".to_string(),
+        ));
+    }
+    #[cfg(unix)]
+    let control_socket = control_socket
+        .map(|path| {
+            ControlSocket::bind(&path).map_err(|e| {
+                TerminalError::InvalidArgument(format!(
+                    "Failed to bind control socket {}: {}",
+                    path, e
+                ))
+            })
+        })
+        .transpose()?;
+    #[cfg(not(unix))]
+    if control_socket.is_some() {
+        return Err(TerminalError::Unsupported(
+            "Control sockets are only supported on Unix".to_string(),
+        ));
+    }
 
-    // Get reader for output
-    let mut reader = pair
-        .master
-        .try_clone_reader()
-        .map_err(|e| format!("Failed to clone reader: {}", e))?;
+    // Mutable to kill it if it can't be put in its cgroup or stored
+    let mut child = pair.slave.spawn_command(cmd).map_err(|e| {
+        log::error!(target: LOG_TARGET, "Failed to spawn shell {}: {}", shell, e);
+        if is_fd_exhaustion(&*e) {
+            TerminalError::ResourceExhausted(format!(
+                "Out of file descriptors spawning shell: {}",
+                e
+            ))
+        } else {
+            TerminalError::Pty(format!("Failed to spawn shell: {}", e))
+        }
+    })?;
 
-    // Get writer for input
-    let writer = pair
-        .master
-        .take_writer()
-        .map_err(|e| format!("Failed to take writer: {}", e))?;
+    #[cfg(target_os = "linux")]
+    if let Some(cgroup) = &cgroup {
+        let added = match child.process_id() {
+            Some(pid) => cgroup.add(pid),
+            None => Err(TerminalError::Unsupported(
+                "The shell has no PID to move into a cgroup".to_string(),
+            )),
+        };
+        if let Err(e) = added {
+            // It must not run unconfined
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+    }
+
+    let spawned_at = Instant::now();
+    let session_id = session_id.unwrap_or_else(|| next_session_id(&app));
+    let session_uuid = uuid::Uuid::new_v4().to_string();
+
+    #[cfg(target_os = "linux")]
+    let pty_name = pair.master.as_raw_fd().and_then(termios::pts_name);
+    #[cfg(not(target_os = "linux"))]
+    let pty_name = None;
+    if let Some(name) = &pty_name {
+        match &label_pty {
+            Some(label) => log::info!(
+                target: LOG_TARGET, session_id = session_id;
+                "Terminal session {} ({}) uses {}", session_id, label, name
+            ),
+            None => log::debug!(
+                target: LOG_TARGET, session_id = session_id;
+                "Terminal session {} uses {}", session_id, name
+            ),
+        }
+    }
+
+    let scrollback_bytes = app
+        .state::<TerminalState>()
+        .scrollback_bytes
+        .load(Ordering::SeqCst);
+    let mut scrollback = Scrollback::new(scrollback_bytes);
+    scrollback.set_cr_handling(scrollback_cr_handling);
+    let shared = Arc::new(SessionShared {
+        pid: child.process_id(),
+        cwd,
+        label: label_pty,
+        event_namespace,
+        pty_name,
+        banner_limit: capture_banner_bytes.map_or(0, |n| n.min(MAX_BANNER)),
+        color_scheme: Mutex::new(color_scheme),
+        scrollback: Mutex::new(scrollback),
+        output_channel: Mutex::new(output_channel),
+        decoder: Mutex::new(decoder),
+        exit_behavior: Mutex::new(exit_behavior),
+        // An adopted descriptor can't be opened again
+        #[cfg(unix)]
+        restart_options: Mutex::new(adopt_fd.is_none().then_some(restart_options)),
+        #[cfg(not(unix))]
+        restart_options: Mutex::new(Some(restart_options)),
+        parked: AtomicBool::new(parked),
+        #[cfg(unix)]
+        control_socket: control_socket.as_ref().map(|(socket, _)| socket.clone()),
+        #[cfg(target_os = "linux")]
+        cgroup,
+        #[cfg(unix)]
+        integration_files,
+        ..SessionShared::new(session_uuid.clone())
+    });
 
-    // Store the session
+    // Store the session, never over another one: a spawn racing with
+    // `reset_terminal_subsystem` may have been given an id that's since been
+    // handed out again
     let state = app.state::<TerminalState>();
     {
-        let mut sessions = state.sessions.lock();
-        sessions.insert(
-            session_id,
-            PtySession {
-                writer,
-                master: pair.master,
-            },
-        );
+        let mut sessions = state.sessions.write();
+        let Entry::Vacant(entry) = sessions.entry(session_id) else {
+            drop(sessions);
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(TerminalError::Pty(format!(
+                "Terminal session id {} is already in use",
+                session_id
+            )));
+        };
+        entry.insert(PtySession {
+            id: session_id,
+            writer: Mutex::new(writer),
+            master: Mutex::new(pair.master),
+            killer: child.clone_killer(),
+            shared: shared.clone(),
+        });
     }
 
-    // Spawn thread to read PTY output and emit to frontend
-    let app_handle = app.clone();
+    #[cfg(unix)]
+    if let Some((socket, listener)) = control_socket {
+        let app_handle = app.clone();
+        socket.serve(listener, move |data| {
+            write_to_session(&app_handle, session_id, data).is_ok()
+        });
+    }
+
+    if let Some(secs) = keepalive_secs {
+        let data = keepalive_data.unwrap_or_else(|| "\0".to_string());
+        start_keepalive(&app, session_id, &shared, Duration::from_secs(secs), data);
+    }
+
+    // Blocking reads happen on their own thread so the output loop can also
+    // wake up on a timer, e.g. to flush output held back by filters
+    let (chunk_tx, chunk_rx) = mpsc::channel::<Vec<u8>>();
     let sid = session_id;
+    let read_buffer_size = read_buffer_size
+        .unwrap_or(DEFAULT_READ_BUFFER)
+        .clamp(MIN_READ_BUFFER, MAX_READ_BUFFER);
+    let reader_shared = shared.clone();
     thread::spawn(move || {
-        let mut buf = [0u8; 4096];
+        let mut buf = vec![0u8; read_buffer_size];
         loop {
             match reader.read(&mut buf) {
                 Ok(0) => break, // EOF
                 Ok(n) => {
-                    // Convert to string, replacing invalid UTF-8
-                    let data = String::from_utf8_lossy(&buf[..n]).to_string();
-                    let _ = app_handle.emit(
-                        "terminal-output",
-                        TerminalOutput {
-                            session_id: sid,
-                            data,
-                        },
-                    );
+                    if let Some(timing) = reader_shared.timing.lock().as_mut() {
+                        timing.record_read(n);
+                    }
+                    if chunk_tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
                 }
                 Err(e) => {
-                    log::error!("PTY read error: {}", e);
+                    log::error!(
+                        target: LOG_TARGET, session_id = sid;
+                        "PTY read error in terminal session {}: {}", sid, e
+                    );
                     break;
                 }
             }
         }
+    });
 
-        // Wait for child to exit and emit exit event
-        let exit_code = child.wait().ok().and_then(|s| {
-            if s.success() {
-                Some(0)
-            } else {
-                // portable_pty doesn't give us the actual exit code easily
-                Some(1)
-            }
-        });
+    let mut init_command = state.global_init_command.lock().clone();
+    // Checked for a slow start at its first output
+    let mut starting_shell = Some(shell.clone());
+    // No shell reads an adopted descriptor's input
+    #[cfg(unix)]
+    if adopt_fd.is_some() {
+        init_command = None;
+        starting_shell = None;
+    }
 
-        let _ = app_handle.emit(
-            "terminal-exit",
-            TerminalExit {
-                session_id: sid,
-                exit_code,
-            },
+    // Spawn thread to process PTY output and emit to frontend
+    let app_handle = app.clone();
+    thread::spawn(move || {
+        if !preload.is_empty() {
+            emit_output(&app_handle, sid, &shared, &preload);
+        }
+        let mut scanner = Scanner::default();
+        let mut scan_events = Vec::new();
+        let mut fired = Vec::new();
+        // Output held back from the filters until its line is complete
+        let mut pending: Vec<u8> = Vec::new();
+        // Output over the rate limit, waiting to be emitted
+        let mut throttled: Vec<u8> = Vec::new();
+        let mut burst = BurstDetector::default();
+        // Early output, reported if the child turns out to exit immediately
+        let startup_window = Duration::from_millis(
+            app_handle
+                .state::<TerminalState>()
+                .startup_failure_ms
+                .load(Ordering::Relaxed),
         );
+        let mut startup_output: Vec<u8> = Vec::new();
+        loop {
+            #[cfg(unix)]
+            let watching_jobs = shared.jobs.lock().has_stopped();
+            #[cfg(not(unix))]
+            let watching_jobs = false;
+            let wait = if !pending.is_empty() || !throttled.is_empty() {
+                Some(FILTER_FLUSH_DELAY)
+            } else if burst.bursting() {
+                Some(BURST_CHECK_INTERVAL)
+            } else if watching_jobs {
+                Some(JOB_CHECK_INTERVAL)
+            } else {
+                None
+            };
+            let chunk = match wait {
+                None => chunk_rx.recv().ok(),
+                Some(wait) => match chunk_rx.recv_timeout(wait) {
+                    Ok(chunk) => Some(chunk),
+                    Err(RecvTimeoutError::Timeout) => {
+                        let data = std::mem::take(&mut pending);
+                        emit_filtered(&app_handle, sid, &shared, &mut throttled, &data);
+                        track_burst(&app_handle, sid, &shared, &mut burst, 0);
+                        #[cfg(unix)]
+                        if watching_jobs {
+                            let state = app_handle.state::<TerminalState>();
+                            let sessions = state.sessions.read();
+                            if let Some(session) = sessions.get(&sid) {
+                                track_jobs(&app_handle, sid, session);
+                            }
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                },
+            };
+            let Some(mut chunk) = chunk else { break }; // EOF
+            batch_output(&shared, &chunk_rx, &mut chunk);
+            track_burst(&app_handle, sid, &shared, &mut burst, chunk.len());
 
-        // Clean up session
-        let state = app_handle.state::<TerminalState>();
-        let mut sessions = state.sessions.lock();
-        sessions.remove(&sid);
-    });
+            let chunk = {
+                let mut capture = shared.shell_capture.lock();
+                match capture.as_mut() {
+                    Some(c) => {
+                        let mut rest = Vec::new();
+                        if c.feed(&chunk, &mut rest) {
+                            *capture = None;
+                        }
+                        rest
+                    }
+                    None => chunk,
+                }
+            };
+            if chunk.is_empty() {
+                continue;
+            }
 
-    log::info!("Spawned terminal session {} with shell {}", session_id, shell);
-    Ok(session_id)
-}
+            // The shell is up once it prints something, normally its prompt.
+            // The leading space keeps it out of most shells' history.
+            if let Some(shell) = starting_shell.take() {
+                check_startup_time(&app_handle, sid, &shared, &shell, spawned_at.elapsed());
+            }
+            if let Some(command) = init_command.take() {
+                let input = format!(" {}\r", command);
+                let _ = write_to_session(&app_handle, sid, input.as_bytes());
+            }
 
-/// Write data to a terminal session
-#[tauri::command]
-pub fn write_terminal(app: AppHandle, session_id: u32, data: String) -> Result<(), String> {
-    let state = app.state::<TerminalState>();
-    let mut sessions = state.sessions.lock();
+            if startup_output.len() < MAX_STARTUP_CAPTURE && spawned_at.elapsed() < startup_window {
+                let room = MAX_STARTUP_CAPTURE - startup_output.len();
+                startup_output.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            }
+            {
+                let mut banner = shared.banner.lock();
+                let room = shared.banner_limit - banner.len();
+                banner.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            }
 
-    if let Some(session) = sessions.get_mut(&session_id) {
-        session
-            .writer
-            .write_all(data.as_bytes())
-            .map_err(|e| format!("Failed to write to terminal: {}", e))?;
-        session
-            .writer
-            .flush()
-            .map_err(|e| format!("Failed to flush terminal: {}", e))?;
-        Ok(())
-    } else {
-        Err(format!("Terminal session {} not found", session_id))
-    }
+            scanner.feed(&chunk, &mut scan_events);
+            for event in scan_events.drain(..) {
+                handle_scan_event(&app_handle, sid, &shared, event);
+            }
+            shared
+                .marker_captures
+                .lock()
+                .retain_mut(|capture| !capture.feed(&chunk));
+            shared.triggers.lock().feed(&chunk, &mut fired);
+            for trigger in fired.drain(..) {
+                run_trigger(&app_handle, sid, &shared, trigger);
+            }
+
+            // A program may have turned flow control off while paused or
+            // changed echo, and the shell may have returned to its prompt or
+            // started a command
+            {
+                let state = app_handle.state::<TerminalState>();
+                let sessions = state.sessions.read();
+                if let Some(session) = sessions.get(&sid) {
+                    #[cfg(unix)]
+                    {
+                        if shared.flow_paused.load(Ordering::SeqCst) {
+                            track_flow_control(&app_handle, sid, session, &[]);
+                        }
+                        // Programs change modes before prompting, so a
+                        // password prompt's output finds echo already off
+                        track_echo(&app_handle, sid, session);
+                        track_jobs(&app_handle, sid, session);
+                    }
+                    track_prompt_state(&app_handle, sid, session);
+                }
+            }
+
+            if shared.filters.lock().is_empty() && pending.is_empty() {
+                emit_throttled(&app_handle, sid, &shared, &mut throttled, &chunk);
+                continue;
+            }
+            pending.extend_from_slice(&chunk);
+            let cut = filter::holdback_point(&pending);
+            let rest = pending.split_off(cut);
+            let data = std::mem::replace(&mut pending, rest);
+            emit_filtered(&app_handle, sid, &shared, &mut throttled, &data);
+        }
+        if !pending.is_empty() {
+            let filtered = filter::apply_filters(&shared.filters.lock(), &pending);
+            throttled.extend_from_slice(&filtered);
+        }
+        // The last output is shown regardless of the rate limit
+        if !throttled.is_empty() {
+            emit_output(&app_handle, sid, &shared, &throttled);
+        }
+
+        // Refuse further writes while we wait for the child and clean up
+        {
+            let _reap = shared.reap_lock.lock();
+            shared.alive.store(false, Ordering::SeqCst);
+        }
+        #[cfg(unix)]
+        {
+            if let Some(socket) = &shared.control_socket {
+                socket.close();
+            }
+            shared.output_pipe.lock().take();
+        }
+        if let Some(replay) = shared.replay.lock().take() {
+            replay.stop();
+        }
+
+        // Wait for child to exit and emit exit event. On Unix we reap it
+        // ourselves, since portable-pty's status loses the signal number.
+        // Either way a child that won't exit is killed rather than blocking
+        // the cleanup below forever.
+        #[cfg(unix)]
+        let reason = match shared.pid {
+            Some(pid) => exit::wait(pid, EXIT_WAIT_TIMEOUT),
+            None => exit::wait_child(child, EXIT_WAIT_TIMEOUT),
+        };
+        #[cfg(not(unix))]
+        let reason = exit::wait_child(child, EXIT_WAIT_TIMEOUT);
+        let exit_code = reason.map(ExitReason::exit_code);
+        write_ndjson(&shared, |log| log.exit(exit_code));
+        shared.ndjson_log.lock().take();
+        match reason {
+            Some(ExitReason::Exited { .. }) | None => log::info!(
+                target: LOG_TARGET, session_id = sid;
+                "Terminal session {} exited with code {:?}", sid, exit_code
+            ),
+            Some(ExitReason::Signaled {
+                signal,
+                core_dumped,
+            }) => log::info!(
+                target: LOG_TARGET, session_id = sid;
+                "Terminal session {} was killed by signal {}{}",
+                sid, signal, if core_dumped { " (core dumped)" } else { "" }
+            ),
+            Some(ExitReason::Stopped { signal }) => log::warn!(
+                target: LOG_TARGET, session_id = sid;
+                "Terminal session {} was stopped by signal {} and has been killed",
+                sid, signal
+            ),
+        }
+
+        // The frontend never heard of a parked session
+        let parked = shared.parked.load(Ordering::SeqCst);
+        if !parked {
+            let exit = TerminalExit::new(sid, &shared, reason, spawned_at);
+            let _ = emit_event(&app_handle, &shared, "terminal-exit", exit.clone());
+            let state = app_handle.state::<TerminalState>();
+            let mut recent_exits = state.recent_exits.lock();
+            recent_exits.push_back((Instant::now(), exit));
+            if recent_exits.len() > MAX_RECENT_EXITS {
+                recent_exits.pop_front();
+            }
+        }
+
+        if !parked && spawned_at.elapsed() < startup_window {
+            let captured = filter::apply_filters(&shared.filters.lock(), &startup_output);
+            log::warn!(
+                target: LOG_TARGET, session_id = sid;
+                "Terminal session {} exited within {} ms of spawning",
+                sid,
+                startup_window.as_millis()
+            );
+            let _ = emit_event(
+                &app_handle,
+                &shared,
+                "terminal-startup-failed",
+                TerminalStartupFailed {
+                    session_id: sid,
+                    session_uuid: shared.uuid.clone(),
+                    exit_code,
+                    captured_output: shared.decoder.lock().decode_snapshot(&captured),
+                },
+            );
+        }
+
+        // Clean up session, unless it's kept open or restarted. It may be
+        // gone already (killed), or even replaced after a restart.
+        let state = app_handle.state::<TerminalState>();
+        state
+            .input_links
+            .lock()
+            .retain(|l| l.source_uuid != shared.uuid && l.follower_uuid != shared.uuid);
+        let mut sessions = state.sessions.write();
+        let ours = |s: &PtySession| Arc::ptr_eq(&s.shared, &shared);
+        let Some(session) = sessions.get(&sid).filter(|s| ours(s)) else {
+            return;
+        };
+        let behavior = *shared.exit_behavior.lock();
+        match behavior {
+            ExitBehavior::Keep => {
+                shared.kept.store(true, Ordering::SeqCst);
+                drop(sessions);
+                let notice = match exit_code {
+                    Some(code) => format!(
+                        "\r\n[Process exited with code {} \u{2014} press Enter to close]\r\n",
+                        code
+                    ),
+                    None => "\r\n[Process exited \u{2014} press Enter to close]\r\n".to_string(),
+                };
+                emit_output(&app_handle, sid, &shared, notice.as_bytes());
+            }
+            ExitBehavior::Restart if spawned_at.elapsed() >= MIN_RESTART_UPTIME => {
+                let size = session.master.lock().get_size().ok();
+                sessions.remove(&sid);
+                drop(sessions);
+                restart_session(&app_handle, sid, &shared, size);
+            }
+            _ => {
+                sessions.remove(&sid);
+            }
+        }
+    });
+
+    #[cfg(unix)]
+    if let Some(fd) = adopt_fd {
+        log::info!(
+            target: LOG_TARGET, session_id = session_id, session_uuid = session_uuid.as_str();
+            "Adopted file descriptor {} as terminal session {} ({})", fd, session_id, session_uuid
+        );
+        return Ok(session_id);
+    }
+    log::info!(
+        target: LOG_TARGET, session_id = session_id, session_uuid = session_uuid.as_str();
+        "Spawned terminal session {} ({}) with shell {}", session_id, session_uuid, shell
+    );
+    Ok(session_id)
+}
+
+/// Write `data` to the session whenever it's had no input for `interval`,
+/// until it exits
+fn start_keepalive(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &Arc<SessionShared>,
+    interval: Duration,
+    data: String,
+) {
+    let app = app.clone();
+    // Weak, so the keepalive doesn't hold the session's state after it exits
+    let shared: Weak<SessionShared> = Arc::downgrade(shared);
+    thread::spawn(move || {
+        while let Some(idle) = shared.upgrade().map(|s| s.last_input.lock().elapsed()) {
+            if idle < interval {
+                thread::sleep(interval - idle);
+            } else if write_to_session(&app, session_id, data.as_bytes()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Report a shell that took `elapsed` to first print something as slow to
+/// start, if that's longer than `set_slow_startup_threshold` allows
+fn check_startup_time(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    shell: &str,
+    elapsed: Duration,
+) {
+    let threshold = app
+        .state::<TerminalState>()
+        .slow_startup_ms
+        .load(Ordering::Relaxed);
+    if threshold == 0
+        || elapsed < Duration::from_millis(threshold)
+        || shared.parked.load(Ordering::SeqCst)
+    {
+        return;
+    }
+    let files = match Path::new(shell).file_name().and_then(|name| name.to_str()) {
+        Some("bash") => "~/.bash_profile and ~/.bashrc",
+        Some("zsh") => "~/.zshenv, ~/.zprofile and ~/.zshrc",
+        Some("fish") => "~/.config/fish/config.fish",
+        _ => "the shell's startup files",
+    };
+    let hint = format!(
+        "Check {} for slow commands, such as version managers or network calls",
+        files
+    );
+    log::warn!(
+        target: LOG_TARGET, session_id = session_id;
+        "Terminal session {} took {} ms to start. {}",
+        session_id,
+        elapsed.as_millis(),
+        hint
+    );
+    let _ = emit_event(
+        app,
+        shared,
+        "terminal-slow-startup",
+        TerminalSlowStartup {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+            elapsed_ms: elapsed.as_millis() as u64,
+            hint,
+        },
+    );
+}
+
+/// Start the shell without printing a login banner as far as possible,
+/// see `quiet` of `spawn_terminal`
+fn quiet_shell(shell: &str, cmd: &mut CommandBuilder, keep_login: bool) {
+    if !keep_login {
+        // Last after the shell itself, or `run_as`'s escalation command
+        if let Some(arg) = cmd.get_argv_mut().last_mut().filter(|arg| *arg == "-l") {
+            *arg = "-i".into();
+        }
+    }
+    let name = Path::new(shell).file_name();
+    if name.is_some_and(|name| name == "fish") {
+        cmd.env("fish_greeting", "");
+    }
+}
+
+/// `PATH` with `dirs` in front of the `inherited` one, in the order given
+fn prepend_path(
+    dirs: &[String],
+    inherited: Option<&std::ffi::OsStr>,
+) -> Result<std::ffi::OsString, TerminalError> {
+    if dirs.iter().any(|dir| dir.is_empty()) {
+        return Err(TerminalError::InvalidArgument(
+            "PATH directories must not be empty".to_string(),
+        ));
+    }
+    let inherited: Vec<PathBuf> = inherited
+        .map(|path| std::env::split_paths(path).collect())
+        .unwrap_or_default();
+    let dirs = dirs.iter().map(PathBuf::from).chain(inherited);
+    std::env::join_paths(dirs)
+        .map_err(|e| TerminalError::InvalidArgument(format!("Invalid PATH directory: {}", e)))
+}
+
+/// The next id from the counter that no session has, as a session spawned
+/// across `reset_terminal_subsystem` keeps an id from before the reset
+fn next_session_id(app: &AppHandle) -> u32 {
+    let state = app.state::<TerminalState>();
+    loop {
+        let id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst);
+        if !state.sessions.read().contains_key(&id) {
+            return id;
+        }
+    }
+}
+
+/// The user's default shell
+fn default_shell() -> String {
+    shell_path(None).0
+}
+
+/// The shell to run: `program` if given, else `$SHELL`, else `/bin/zsh`
+fn shell_path(program: Option<String>) -> (String, ShellSource) {
+    choose_shell(program, std::env::var("SHELL").ok())
+}
+
+/// `shell_path` with `$SHELL` given
+fn choose_shell(program: Option<String>, env_shell: Option<String>) -> (String, ShellSource) {
+    if let Some(program) = program.filter(|p| !p.is_empty()) {
+        return (program, ShellSource::Explicit);
+    }
+    match env_shell {
+        Some(shell) if !shell.is_empty() => (shell, ShellSource::Env),
+        _ => ("/bin/zsh".to_string(), ShellSource::Default),
+    }
+}
+
+/// Where `resolve_shell` found the shell
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellSource {
+    /// The `program` passed in
+    Explicit,
+    /// `$SHELL`
+    Env,
+    /// Neither was set
+    Default,
+}
+
+/// See `resolve_shell`
+#[derive(serde::Serialize)]
+pub struct ShellResolution {
+    path: String,
+    source: ShellSource,
+    /// A file exists at `path`, searched for on `PATH` if it's a bare name
+    exists: bool,
+    /// The file is executable by someone; always the same as `exists`
+    /// except on Unix
+    executable: bool,
+}
+
+/// Tell which shell `spawn_terminal` would start, and whether it can: the
+/// `program` given, else `$SHELL`, else `/bin/zsh`. `spawn_terminal` goes by
+/// the last two, so a UI can check `$SHELL` with no `program` and warn
+/// that it points to a missing binary before spawning fails, or check a
+/// shell it's about to offer.
+#[tauri::command]
+pub fn resolve_shell(program: Option<String>) -> ShellResolution {
+    let (path, source) = shell_path(program);
+    let file = locate_shell(&path, std::env::var_os("PATH").as_deref(), |file| {
+        file.is_file()
+    });
+    #[cfg(unix)]
+    let executable = file.as_ref().is_some_and(|file| {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(file).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+    });
+    #[cfg(not(unix))]
+    let executable = file.is_some();
+    ShellResolution {
+        path,
+        source,
+        exists: file.is_some(),
+        executable,
+    }
+}
+
+/// The file `path` names, checked directly if it has a separator and
+/// searched for in `search_path` if it's a bare name
+fn locate_shell(
+    path: &str,
+    search_path: Option<&std::ffi::OsStr>,
+    is_file: impl Fn(&Path) -> bool,
+) -> Option<PathBuf> {
+    if path.contains(std::path::MAIN_SEPARATOR) || path.contains('/') {
+        Some(PathBuf::from(path)).filter(|file| is_file(file))
+    } else {
+        search_path.and_then(|dirs| {
+            std::env::split_paths(dirs)
+                .map(|dir| dir.join(path))
+                .find(|file| is_file(file))
+        })
+    }
+}
+
+/// What `spawn_terminal` uses when given no arguments, see
+/// `get_default_spawn_config`
+#[derive(serde::Serialize)]
+pub struct SpawnConfig {
+    shell: String,
+    args: Vec<String>,
+    /// `None` when there's no `$HOME`, in which case portable-pty uses the
+    /// home directory from the user database
+    cwd: Option<String>,
+    /// Set on top of the app's own environment
+    env: HashMap<String, String>,
+    cols: u16,
+    rows: u16,
+}
+
+/// Report what `spawn_terminal` would use without arguments, as resolved
+/// right now, so a "new terminal" form can start from the real defaults
+#[tauri::command]
+pub fn get_default_spawn_config() -> SpawnConfig {
+    SpawnConfig {
+        shell: default_shell(),
+        args: SHELL_ARGS.iter().map(|a| a.to_string()).collect(),
+        cwd: resolve_cwd(None, true).map(|dir| dir.display().to_string()),
+        env: DEFAULT_ENV
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        cols: DEFAULT_COLS,
+        rows: DEFAULT_ROWS,
+    }
+}
+
+/// Working directory for a new session, see `spawn_terminal`. `None` leaves
+/// it to portable-pty, which then uses the home directory from the user
+/// database.
+fn resolve_cwd(cwd: Option<String>, use_home_fallback: bool) -> Option<PathBuf> {
+    cwd_from(
+        cwd,
+        use_home_fallback,
+        std::env::current_dir().ok(),
+        std::env::var_os("HOME"),
+    )
+}
+
+/// `resolve_cwd` with the app's own working directory and `$HOME` given
+fn cwd_from(
+    cwd: Option<String>,
+    use_home_fallback: bool,
+    inherited: Option<PathBuf>,
+    home: Option<std::ffi::OsString>,
+) -> Option<PathBuf> {
+    match cwd {
+        // Joining an absolute path yields it unchanged
+        Some(dir) => Some(match inherited {
+            Some(base) => base.join(dir),
+            None => PathBuf::from(dir),
+        }),
+        None if use_home_fallback => home.map(PathBuf::from),
+        // portable-pty would pick the home directory, so pass ours explicitly
+        None => inherited,
+    }
+}
+
+/// Open a PTY. If the process or system is out of file descriptors, sessions
+/// whose child has already exited are dropped, unless they're kept open (see
+/// `is_disposable`), and the open is retried once.
+fn open_pty(app: &AppHandle, size: PtySize) -> Result<PtyPair, TerminalError> {
+    let pty_system = native_pty_system();
+    let mut retried = false;
+    loop {
+        let e = match pty_system.openpty(size) {
+            Ok(pair) => return Ok(pair),
+            Err(e) => e,
+        };
+        if !is_fd_exhaustion(&*e) {
+            log::error!(target: LOG_TARGET, "Failed to open PTY: {}", e);
+            return Err(TerminalError::Pty(format!("Failed to open PTY: {}", e)));
+        }
+
+        let state = app.state::<TerminalState>();
+        let mut sessions = state.sessions.write();
+        if retried {
+            log::error!(
+                target: LOG_TARGET,
+                "Out of file descriptors opening a PTY with {} terminal sessions open",
+                sessions.len()
+            );
+            return Err(TerminalError::ResourceExhausted(format!(
+                "Out of file descriptors opening a terminal ({} sessions open); \
+                 close some terminals and try again",
+                sessions.len()
+            )));
+        }
+        let open = sessions.len();
+        sessions.retain(|_, session| !is_disposable(&session.shared));
+        log::warn!(
+            target: LOG_TARGET,
+            "Out of file descriptors opening a PTY with {} terminal sessions open; \
+             dropped {} exited sessions and retrying",
+            open,
+            open - sessions.len()
+        );
+        retried = true;
+    }
+}
+
+/// Whether `open_pty` may drop a session to free its descriptors: its child
+/// has exited and it isn't kept open for the user, nor about to be by its
+/// reader thread, which is still reaping the child if `alive` was only just
+/// cleared. Restarting needs the session too.
+fn is_disposable(shared: &SessionShared) -> bool {
+    !shared.alive.load(Ordering::SeqCst)
+        && !shared.kept.load(Ordering::SeqCst)
+        && *shared.exit_behavior.lock() == ExitBehavior::Close
+}
+
+/// Whether an error is `EMFILE`/`ENFILE` (too many open files)
+#[cfg(unix)]
+fn is_fd_exhaustion(err: &(dyn std::error::Error + 'static)) -> bool {
+    let codes = [libc::EMFILE, libc::ENFILE];
+    let mut source = Some(err);
+    while let Some(e) = source {
+        let io_error = e.downcast_ref::<std::io::Error>();
+        if let Some(code) = io_error.and_then(|e| e.raw_os_error()) {
+            return codes.contains(&code);
+        }
+        source = e.source();
+    }
+    // portable-pty reports some OS errors only in the message text
+    let message = format!("{:?}", err);
+    codes
+        .iter()
+        .any(|code| message.contains(&format!("code: {},", code)))
+}
+
+#[cfg(not(unix))]
+fn is_fd_exhaustion(_err: &(dyn std::error::Error + 'static)) -> bool {
+    false
+}
+
+/// Emit a chunk of output to the frontend, on the session's channel if it
+/// has one and as a global `terminal-output` event otherwise. In raw mode,
+/// or if `set_auto_binary` takes it for binary, it goes out as a
+/// `terminal-output-binary` event instead. Output longer than
+/// `set_max_emit_size` allows goes out in pieces, each with its own `seq`.
+fn emit_output(app: &AppHandle, session_id: u32, shared: &SessionShared, data: &[u8]) {
+    for (seq, piece) in record_pieces(shared, data) {
+        emit_output_piece(app, session_id, shared, seq, piece);
+    }
+}
+
+/// `data` cut into pieces of at most `max_emit_bytes`, each recorded as it's
+/// taken, with its sequence number
+fn record_pieces<'a>(
+    shared: &'a SessionShared,
+    data: &'a [u8],
+) -> impl Iterator<Item = (u64, &'a [u8])> + 'a {
+    let max_bytes = shared.max_emit_bytes.load(Ordering::Relaxed);
+    emit_pieces(data, max_bytes).map(move |piece| (record_output(shared, piece), piece))
+}
+
+/// `data` cut into pieces of at most `max_bytes`, in order
+fn emit_pieces(data: &[u8], max_bytes: usize) -> impl Iterator<Item = &[u8]> {
+    data.chunks(max_bytes.max(1))
+}
+
+fn emit_output_piece(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    seq: u64,
+    data: &[u8],
+) {
+    if shared.detached.load(Ordering::SeqCst) || shared.parked.load(Ordering::SeqCst) {
+        return;
+    }
+
+    // Decode in the session's encoding, replacing invalid sequences
+    let auto_binary = shared.auto_binary.load(Ordering::SeqCst);
+    let decoded = shared.decoder.lock().decode(data, auto_binary);
+    let data = match decoded {
+        Decoded::Text(text) => text,
+        Decoded::Binary { detected } => {
+            if detected {
+                log::info!(
+                    target: LOG_TARGET, session_id = session_id;
+                    "Terminal session {} output looks binary", session_id
+                );
+                let payload = BinaryDetected {
+                    session_id,
+                    session_uuid: shared.uuid.clone(),
+                    seq,
+                };
+                let _ = emit_event(app, shared, "terminal-binary-detected", payload);
+            }
+            let output = TerminalOutputBinary {
+                session_id,
+                session_uuid: shared.uuid.clone(),
+                seq,
+                data: BASE64.encode(data),
+            };
+            let result = emit_event(app, shared, "terminal-output-binary", output);
+            note_emit_result(app, session_id, shared, result.is_ok());
+            return;
+        }
+    };
+    let output = TerminalOutput {
+        session_id,
+        session_uuid: shared.uuid.clone(),
+        seq,
+        data,
+    };
+    send_output(app, session_id, shared, output);
+}
+
+/// Deliver output on the session's channel, or as a `terminal-output`
+/// event if it has none
+fn send_output(app: &AppHandle, session_id: u32, shared: &SessionShared, output: TerminalOutput) {
+    let mut channel = shared.output_channel.lock();
+    if let Some(ch) = channel.as_ref() {
+        if ch.send(output.clone()).is_ok() {
+            note_emit_result(app, session_id, shared, true);
+            return;
+        }
+        // The subscriber is gone (e.g. the page reloaded)
+        log::warn!(
+            target: LOG_TARGET, session_id = session_id;
+            "Output channel for terminal session {} closed", session_id
+        );
+        *channel = None;
+    }
+    let result = emit_event(app, shared, "terminal-output", output);
+    note_emit_result(app, session_id, shared, result.is_ok());
+}
+
+/// Count failed output emits. If they keep failing nothing is listening (e.g.
+/// the window was destroyed), so stop emitting and just keep the scrollback
+/// until `reattach` is called.
+fn note_emit_result(app: &AppHandle, session_id: u32, shared: &SessionShared, ok: bool) {
+    if ok {
+        shared.emit_failures.store(0, Ordering::SeqCst);
+        return;
+    }
+    let failures = shared.emit_failures.fetch_add(1, Ordering::SeqCst) + 1;
+    if failures == DETACH_AFTER_EMIT_FAILURES {
+        shared.detached.store(true, Ordering::SeqCst);
+        log::warn!(
+            target: LOG_TARGET, session_id = session_id;
+            "Terminal session {} detached after {} failed output events",
+            session_id, failures
+        );
+        let payload = SessionEvent::new(session_id, shared);
+        let _ = emit_event(app, shared, "terminal-detached", payload);
+    }
+}
+
+/// Resume emitting output for a session detached by `note_emit_result`
+fn reattach(session_id: u32, shared: &SessionShared) {
+    shared.emit_failures.store(0, Ordering::SeqCst);
+    if shared.detached.swap(false, Ordering::SeqCst) {
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Terminal session {} reattached", session_id
+        );
+    }
+}
+
+/// Add output to the scrollback, control socket and output pipe, returning
+/// its `seq`
+fn record_output(shared: &SessionShared, data: &[u8]) -> u64 {
+    let seq = {
+        let mut scrollback = shared.scrollback.lock();
+        scrollback.push(data);
+        if let Some(screen) = shared.screen.lock().as_mut() {
+            screen.feed(data);
+        }
+        shared.next_seq.fetch_add(1, Ordering::SeqCst)
+    };
+    *shared.last_activity.lock() = Instant::now();
+    #[cfg(unix)]
+    {
+        if let Some(socket) = &shared.control_socket {
+            socket.mirror(data);
+        }
+        if let Some(pipe) = shared.output_pipe.lock().as_mut() {
+            pipe.write(data);
+        }
+    }
+    write_ndjson(shared, |log| log.output(seq, data));
+    seq
+}
+
+/// Write a record to the session's NDJSON log, if any, stopping the log if
+/// that fails
+fn write_ndjson(shared: &SessionShared, write: impl FnOnce(&mut NdjsonLog) -> std::io::Result<()>) {
+    let mut ndjson_log = shared.ndjson_log.lock();
+    if let Some(log) = ndjson_log.as_mut() {
+        if let Err(e) = write(log) {
+            log::warn!(
+                target: LOG_TARGET, session_uuid = shared.uuid.as_str();
+                "Stopped NDJSON log of terminal session {}: {}", shared.uuid, e
+            );
+            *ndjson_log = None;
+        }
+    }
+}
+
+/// Add further reads to `chunk` as `set_output_batching` says, until the
+/// batch is full or the flush interval since its first read has passed
+fn batch_output(shared: &SessionShared, chunks: &mpsc::Receiver<Vec<u8>>, chunk: &mut Vec<u8>) {
+    let flush_ms = shared.batch_flush_ms.load(Ordering::Relaxed);
+    if flush_ms == 0 {
+        return;
+    }
+    let max_bytes = shared.batch_max_bytes.load(Ordering::Relaxed);
+    let deadline = Instant::now() + Duration::from_millis(flush_ms);
+    while chunk.len() < max_bytes {
+        let left = deadline.saturating_duration_since(Instant::now());
+        // Past the deadline or at EOF, which the next receive sees again
+        match chunks.recv_timeout(left) {
+            Ok(more) => chunk.extend_from_slice(&more),
+            Err(_) => break,
+        }
+    }
+}
+
+/// Run the session's output filters over `data` and emit the result, see
+/// `emit_throttled`
+fn emit_filtered(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    throttled: &mut Vec<u8>,
+    data: &[u8],
+) {
+    let filtered = if data.is_empty() {
+        Vec::new()
+    } else {
+        filter::apply_filters(&shared.filters.lock(), data)
+    };
+    emit_throttled(app, session_id, shared, throttled, &filtered);
+}
+
+/// Payload of `terminal-output-skipped`
+#[derive(Clone, serde::Serialize)]
+struct OutputSkipped {
+    session_id: u32,
+    session_uuid: String,
+    /// The output event that went to the scrollback without being emitted
+    seq: u64,
+    bytes: usize,
+}
+
+/// Emit `data` after any output still held back in `throttled`, as far as
+/// the session's rate limit allows; the rest waits in `throttled` for a
+/// later call. If too much piles up, it goes straight to the scrollback and
+/// `terminal-output-skipped` tells the frontend to catch up from there.
+fn emit_throttled(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    throttled: &mut Vec<u8>,
+    data: &[u8],
+) {
+    let allowed = {
+        let mut rate_limit = shared.rate_limit.lock();
+        match rate_limit.as_mut() {
+            Some(limit) => limit.take(throttled.len() + data.len()),
+            None => usize::MAX,
+        }
+    };
+    if throttled.is_empty() && allowed >= data.len() {
+        if !data.is_empty() {
+            emit_output(app, session_id, shared, data);
+        }
+        return;
+    }
+    throttled.extend_from_slice(data);
+    let now: Vec<u8> = throttled.drain(..allowed.min(throttled.len())).collect();
+    if !now.is_empty() {
+        emit_output(app, session_id, shared, &now);
+    }
+    if throttled.len() > MAX_THROTTLED {
+        let seq = record_output(shared, throttled);
+        let payload = OutputSkipped {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+            seq,
+            bytes: throttled.len(),
+        };
+        throttled.clear();
+        let _ = emit_event(app, shared, "terminal-output-skipped", payload);
+    }
+}
+
+/// Payload of `terminal-output-burst` and `terminal-output-burst-end`
+#[derive(Clone, serde::Serialize)]
+struct OutputBurst {
+    session_id: u32,
+    session_uuid: String,
+    bytes_in_window: u64,
+}
+
+/// Count `n` bytes of output (0 to only let the window slide) towards a
+/// burst, emitting its start or end
+fn track_burst(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    burst: &mut BurstDetector,
+    n: usize,
+) {
+    let state = app.state::<TerminalState>();
+    let threshold = state.burst_bytes.load(Ordering::Relaxed);
+    let window = Duration::from_millis(state.burst_window_ms.load(Ordering::Relaxed));
+    let (event, bytes_in_window) = match burst.record(n, threshold, window) {
+        Some(BurstChange::Started(bytes)) => ("terminal-output-burst", bytes),
+        Some(BurstChange::Ended(bytes)) => ("terminal-output-burst-end", bytes),
+        None => return,
+    };
+    let payload = OutputBurst {
+        session_id,
+        session_uuid: shared.uuid.clone(),
+        bytes_in_window,
+    };
+    let _ = emit_event(app, shared, event, payload);
+}
+
+/// React to an escape sequence seen in a session's output
+fn handle_scan_event(app: &AppHandle, session_id: u32, shared: &SessionShared, event: ScanEvent) {
+    match event {
+        ScanEvent::Osc { payload, bel } => {
+            // Prompt start (A) and end (B), command output start (C) and
+            // command finished (D)
+            if let Some((mark, options)) =
+                payload.strip_prefix(b"133;").and_then(|m| m.split_first())
+            {
+                shared.shell_integration.store(true, Ordering::SeqCst);
+                match mark {
+                    b'A' | b'B' => shared.at_prompt.store(true, Ordering::SeqCst),
+                    b'C' | b'D' => shared.at_prompt.store(false, Ordering::SeqCst),
+                    _ => {}
+                }
+                match mark {
+                    b'A' | b'B' | b'D' => shared.in_command.store(false, Ordering::SeqCst),
+                    b'C' => shared.in_command.store(true, Ordering::SeqCst),
+                    _ => {}
+                }
+                let mut commands = shared.commands.lock();
+                match mark {
+                    b'A' => commands.prompt(),
+                    b'C' => commands.started(command_history::command_in(options)),
+                    b'D' => commands.finished(command_history::exit_code_in(options)),
+                    _ => {}
+                }
+            }
+            // VS Code's command line mark, sent before the command starts
+            if let Some(text) = payload.strip_prefix(b"633;E;") {
+                let text = text.split(|&b| b == b';').next().unwrap_or_default();
+                let command = command_history::unescape_vscode(text);
+                shared.commands.lock().set_command(command);
+            }
+            let scheme = *shared.color_scheme.lock();
+            if let Some(reply) = scheme.and_then(|s| s.query_reply(&payload, bel)) {
+                reply_to_program(app, session_id, &reply);
+            }
+        }
+        ScanEvent::Enq => {
+            let answerback = shared.answerback.lock().clone();
+            if !answerback.is_empty() {
+                reply_to_program(app, session_id, answerback.as_bytes());
+            }
+        }
+        ScanEvent::PrivateModes { modes, set } => update_modes(app, session_id, shared, |m| {
+            let mut changed = false;
+            for mode in modes {
+                changed |= m.apply(mode, set);
+            }
+            changed
+        }),
+        ScanEvent::Reset => update_modes(app, session_id, shared, |m| {
+            std::mem::take(m) != TerminalModes::default()
+        }),
+    }
+}
+
+/// Payload of `terminal-mode-changed`
+#[derive(Clone, serde::Serialize)]
+struct TerminalModesChanged {
+    session_id: u32,
+    session_uuid: String,
+    modes: TerminalModes,
+}
+
+/// Payload of `terminal-wrap-changed`
+#[derive(Clone, serde::Serialize)]
+struct TerminalWrapChanged {
+    session_id: u32,
+    session_uuid: String,
+    enabled: bool,
+}
+
+/// Change a session's tracked modes with `update`, which returns whether it
+/// changed anything, and emit `terminal-mode-changed` if so. A change of
+/// autowrap, which a custom renderer must follow to wrap lines correctly, is
+/// also emitted on its own as `terminal-wrap-changed`.
+fn update_modes(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    update: impl FnOnce(&mut TerminalModes) -> bool,
+) {
+    let mut modes = shared.modes.lock();
+    let autowrap = modes.autowrap;
+    if update(&mut modes) && !shared.parked.load(Ordering::SeqCst) {
+        let payload = TerminalModesChanged {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+            modes: *modes,
+        };
+        let _ = emit_event(app, shared, "terminal-mode-changed", payload);
+        if modes.autowrap != autowrap {
+            let payload = TerminalWrapChanged {
+                session_id,
+                session_uuid: shared.uuid.clone(),
+                enabled: modes.autowrap,
+            };
+            let _ = emit_event(app, shared, "terminal-wrap-changed", payload);
+        }
+    }
+}
+
+/// Payload for a trigger's `emit` action
+#[derive(Clone, serde::Serialize)]
+struct TriggerEvent {
+    session_id: u32,
+    session_uuid: String,
+    trigger_id: u32,
+    /// The output that matched
+    text: String,
+}
+
+/// Carry out the action of a trigger that matched
+fn run_trigger(app: &AppHandle, session_id: u32, shared: &SessionShared, fired: Fired) {
+    match fired.action {
+        TriggerAction::Write { text } => {
+            if let Err(e) = write_to_session(app, session_id, text.as_bytes()) {
+                log::warn!(
+                    target: LOG_TARGET, session_id = session_id;
+                    "Trigger {} failed to write to terminal session {}: {}",
+                    fired.trigger_id, session_id, e
+                );
+            }
+        }
+        TriggerAction::Emit { event } => {
+            let payload = TriggerEvent {
+                session_id,
+                session_uuid: shared.uuid.clone(),
+                trigger_id: fired.trigger_id,
+                text: fired.text,
+            };
+            let _ = emit_event(app, shared, &event, payload);
+        }
+    }
+}
+
+/// Look up a session's shared state, so commands doing real work don't hold
+/// the session map lock while they do it
+fn session_shared(app: &AppHandle, session_id: u32) -> Result<Arc<SessionShared>, TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    sessions
+        .get(&session_id)
+        .map(|session| session.shared.clone())
+        .ok_or(TerminalError::NotFound(session_id))
+}
+
+/// Write a terminal-generated reply (e.g. to a query) back to the program
+fn reply_to_program(app: &AppHandle, session_id: u32, reply: &[u8]) {
+    if let Err(e) = write_to_session(app, session_id, reply) {
+        log::warn!(
+            target: LOG_TARGET, session_id = session_id;
+            "Failed to reply to terminal session {}: {}", session_id, e
+        );
+    }
+}
+
+/// Write input to a session's PTY
+fn write_to_session(app: &AppHandle, session_id: u32, data: &[u8]) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    if let Some(session) = sessions.get(&session_id) {
+        // Kept open after exiting: Enter closes it, anything else is dropped
+        if session.shared.kept.load(Ordering::SeqCst) {
+            if data.iter().any(|&b| b == b'\r' || b == b'\n') {
+                drop(sessions);
+                close_kept_session(app, session_id);
+            }
+            return Ok(());
+        }
+        // The answer to `query_device_attributes` isn't for the program
+        let rest = take_device_attributes(&session.shared, data);
+        let data = rest.as_deref().unwrap_or(data);
+        if data.is_empty() {
+            return Ok(());
+        }
+        // Before the input may stop it, e.g. with Ctrl-Z
+        #[cfg(unix)]
+        observe_foreground(session);
+        session.write_input(session_id, data)?;
+        // Whatever was typed, the prompt isn't empty any more
+        session.shared.at_prompt.store(false, Ordering::SeqCst);
+        *session.shared.last_input.lock() = Instant::now();
+        *session.shared.last_activity.lock() = Instant::now();
+        if let Some(timing) = session.shared.timing.lock().as_mut() {
+            timing.record_write();
+        }
+        #[cfg(unix)]
+        track_flow_control(app, session_id, session, data);
+        Ok(())
+    } else {
+        Err(TerminalError::NotFound(session_id))
+    }
+}
+
+/// Take the frontend's answer to a pending `query_device_attributes` out of
+/// its input, returning the rest of the input if there was one
+fn take_device_attributes(shared: &SessionShared, data: &[u8]) -> Option<Vec<u8>> {
+    let mut pending = shared.device_attributes.lock();
+    if pending.as_ref().map_or(true, |reply| reply.is_closed()) {
+        *pending = None;
+        return None;
+    }
+    let range = device_attributes::find_response(data)?;
+    if let Some(reply) = pending.take() {
+        let response = String::from_utf8_lossy(&data[range.clone()]).into_owned();
+        let _ = reply.send(response);
+    }
+    Some([&data[..range.start], &data[range.end..]].concat())
+}
+
+/// Follow XOFF/XON in a session's input, which stop and restart its output
+/// when the line discipline has `IXON` set, and emit `terminal-flow-paused`
+/// and `terminal-flow-resumed` so the UI can show why output stopped
+#[cfg(unix)]
+fn track_flow_control(app: &AppHandle, session_id: u32, session: &PtySession, data: &[u8]) {
+    let flow_paused = &session.shared.flow_paused;
+    let last = data.iter().rposition(|&b| b == XON || b == XOFF);
+    let paused = flow_paused.load(Ordering::SeqCst);
+    if last.is_none() && !paused {
+        return;
+    }
+    let Some((ixon, ixany)) = session.flow_control() else {
+        return;
+    };
+    let anything_after = |i: usize| i + 1 < data.len();
+    let now_paused = match last {
+        _ if !ixon => false,
+        // With IXANY, any input restarts output
+        Some(i) => data[i] == XOFF && !(ixany && anything_after(i)),
+        None => !ixany || data.is_empty(),
+    };
+    if paused != now_paused {
+        flow_paused.store(now_paused, Ordering::SeqCst);
+        let event = if now_paused {
+            "terminal-flow-paused"
+        } else {
+            "terminal-flow-resumed"
+        };
+        let payload = SessionEvent::new(session_id, &session.shared);
+        let _ = emit_event(app, &session.shared, event, payload);
+    }
+}
+
+/// Payload of `terminal-echo-changed`
+#[derive(Clone, serde::Serialize)]
+struct TerminalEchoChanged {
+    session_id: u32,
+    session_uuid: String,
+    echo: bool,
+}
+
+/// Emit `terminal-echo-changed` if the line discipline's `ECHO` flag changed
+/// since last time
+#[cfg(unix)]
+fn track_echo(app: &AppHandle, session_id: u32, session: &PtySession) {
+    let Some(echo) = session.echo() else {
+        return;
+    };
+    let shared = &session.shared;
+    if shared.echo.swap(echo, Ordering::SeqCst) != echo && !shared.parked.load(Ordering::SeqCst) {
+        let payload = TerminalEchoChanged {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+            echo,
+        };
+        let _ = emit_event(app, shared, "terminal-echo-changed", payload);
+    }
+}
+
+/// Payload of `terminal-job-stopped` and `terminal-job-continued`
+#[cfg(unix)]
+#[derive(Clone, serde::Serialize)]
+struct TerminalJobEvent {
+    session_id: u32,
+    session_uuid: String,
+    /// The job's process group, whose leader's PID it is
+    pgid: u32,
+}
+
+/// Note which job is in the foreground, see `JobTracker`
+#[cfg(unix)]
+fn observe_foreground(session: &PtySession) {
+    let Some(shell) = session.shared.pid else {
+        return;
+    };
+    let foreground = session.master.lock().process_group_leader();
+    let foreground = foreground.map(|p| p as u32);
+    session.shared.jobs.lock().observe(shell, foreground);
+}
+
+/// Emit `terminal-job-stopped` for jobs of the shell that have been stopped
+/// (Ctrl-Z, or a signal sent otherwise) and `terminal-job-continued` for
+/// those running again (`fg`, `bg` or `SIGCONT`) since last time. A job is
+/// only noticed once it's been in the foreground when input was written or
+/// output arrived, so one stopped while in the background, or stopped
+/// before either, goes unreported. Linux and macOS only; where process
+/// states can't be read, nothing is emitted.
+#[cfg(unix)]
+fn track_jobs(app: &AppHandle, session_id: u32, session: &PtySession) {
+    let Some(shell) = session.shared.pid else {
+        return;
+    };
+    let foreground = session.master.lock().process_group_leader();
+    let foreground = foreground.map(|p| p as u32);
+    let mut changes = Vec::new();
+    session
+        .shared
+        .jobs
+        .lock()
+        .update(shell, foreground, &mut changes);
+    if session.shared.parked.load(Ordering::SeqCst) {
+        return;
+    }
+    for change in changes {
+        let (event, pgid) = match change {
+            JobChange::Stopped(pgid) => ("terminal-job-stopped", pgid),
+            JobChange::Continued(pgid) => ("terminal-job-continued", pgid),
+        };
+        let payload = TerminalJobEvent {
+            session_id,
+            session_uuid: session.shared.uuid.clone(),
+            pgid,
+        };
+        let _ = emit_event(app, &session.shared, event, payload);
+    }
+}
+
+/// Payload of `terminal-command-timeout`
+#[cfg(unix)]
+#[derive(Clone, serde::Serialize)]
+struct CommandTimeout {
+    session_id: u32,
+    session_uuid: String,
+    /// The process group signalled
+    pgid: u32,
+    /// `SIGINT`, `SIGTERM` or `SIGKILL`
+    signal: &'static str,
+    running_secs: u64,
+}
+
+/// Interrupt a session's foreground command once it has run longer than
+/// `timeout`, see `set_command_timeout`. Stops once the timeout is cleared
+/// or the session is gone.
+#[cfg(unix)]
+fn start_command_watchdog(app: &AppHandle, session_id: u32, shared: &Arc<SessionShared>) {
+    let app = app.clone();
+    // Weak, so the watchdog doesn't hold the session's state after it exits
+    let shared: Weak<SessionShared> = Arc::downgrade(shared);
+    thread::spawn(move || {
+        // Without shell integration: the foreground process group, and when
+        // it was first seen there
+        let mut foreground_since: Option<(u32, Instant)> = None;
+        // For the command timed out: when it started, the last signal sent
+        // and when
+        let mut signalled: Option<(Instant, usize, Instant)> = None;
+        loop {
+            thread::sleep(WATCHDOG_INTERVAL);
+            let Some(shared) = shared.upgrade() else {
+                break;
+            };
+            let timeout = {
+                let timeout = shared.command_timeout.lock();
+                let Some(timeout) = *timeout else {
+                    shared.watchdog_running.store(false, Ordering::SeqCst);
+                    break;
+                };
+                timeout
+            };
+            let (Some(shell), true) = (shared.pid, shared.alive.load(Ordering::SeqCst)) else {
+                break;
+            };
+            let foreground = {
+                let state = app.state::<TerminalState>();
+                let sessions = state.sessions.read();
+                match sessions.get(&session_id) {
+                    // Not a later session reusing the id
+                    Some(session) if Arc::ptr_eq(&session.shared, &shared) => {
+                        session.master.lock().process_group_leader()
+                    }
+                    _ => break,
+                }
+            };
+            let Some(foreground) = foreground.map(|p| p as u32) else {
+                continue;
+            };
+
+            let started = if shared.shell_integration.load(Ordering::SeqCst) {
+                shared.commands.lock().running_since()
+            } else if foreground != shell {
+                let since = match foreground_since {
+                    Some((pgid, since)) if pgid == foreground => since,
+                    _ => Instant::now(),
+                };
+                foreground_since = Some((foreground, since));
+                Some(since)
+            } else {
+                foreground_since = None;
+                None
+            };
+            let Some(started) = started else {
+                signalled = None;
+                continue;
+            };
+            let next = match signalled {
+                Some((command, signal, at)) if command == started => {
+                    // Only the command's own processes are terminated, never
+                    // the shell
+                    if at.elapsed() < COMMAND_TIMEOUT_GRACE
+                        || signal + 1 == TIMEOUT_SIGNALS.len()
+                        || foreground == shell
+                    {
+                        continue;
+                    }
+                    signal + 1
+                }
+                _ if started.elapsed() >= timeout => 0,
+                _ => continue,
+            };
+
+            let (signal, name) = TIMEOUT_SIGNALS[next];
+            unsafe { libc::killpg(foreground as libc::pid_t, signal) };
+            signalled = Some((started, next, Instant::now()));
+            let running_secs = started.elapsed().as_secs();
+            log::warn!(
+                target: LOG_TARGET, session_id = session_id;
+                "Command in terminal session {} ran for {} s, sent {} to process group {}",
+                session_id, running_secs, name, foreground
+            );
+            if !shared.parked.load(Ordering::SeqCst) {
+                let payload = CommandTimeout {
+                    session_id,
+                    session_uuid: shared.uuid.clone(),
+                    pgid: foreground,
+                    signal: name,
+                    running_secs,
+                };
+                let _ = emit_event(&app, &shared, "terminal-command-timeout", payload);
+            }
+        }
+    });
+}
+
+/// Payload of `terminal-prompt-state`
+#[derive(Clone, serde::Serialize)]
+struct TerminalPromptState {
+    session_id: u32,
+    session_uuid: String,
+    at_prompt: bool,
+}
+
+/// Emit `terminal-prompt-state` if the session went to or left its prompt
+/// since last time
+fn track_prompt_state(app: &AppHandle, session_id: u32, session: &PtySession) {
+    let at_prompt = session.at_prompt();
+    let shared = &session.shared;
+    if shared.prompt_state.swap(at_prompt, Ordering::SeqCst) != at_prompt
+        && !shared.parked.load(Ordering::SeqCst)
+    {
+        let payload = TerminalPromptState {
+            session_id,
+            session_uuid: shared.uuid.clone(),
+            at_prompt,
+        };
+        let _ = emit_event(app, shared, "terminal-prompt-state", payload);
+    }
+}
+
+/// Whether a session's shell is waiting at its prompt rather than running a
+/// command, e.g. to check it's safe to type a command into it, typed input
+/// or not. On Unix it's whether the shell rather than a job of its own is in
+/// the terminal's foreground, and with shell integration (OSC 133 marks in
+/// the prompt) also that no command has started since the last prompt;
+/// without integration, a shell busy with a builtin such as `read`, or a
+/// nested shell, looks like it's at its prompt. Elsewhere it relies on shell
+/// integration entirely, and is always false for shells without it.
+///
+/// `terminal-prompt-state` `{ session_id, at_prompt }` is emitted on every
+/// change, noticed when the session next prints something.
+#[tauri::command]
+pub fn is_at_prompt(app: AppHandle, session_id: u32) -> Result<bool, TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    let session = sessions
+        .get(&session_id)
+        .ok_or(TerminalError::NotFound(session_id))?;
+    Ok(session.at_prompt())
+}
+
+/// Get the last command a session's shell finished, e.g. to re-run it or
+/// show its status. Goes by shell integration marks (OSC 133, see
+/// `inject_shell_integration`): the exit code comes from the command
+/// finished mark (`D`), which shells generally send, but the command line
+/// is only known if the shell reports it too, with kitty's `cmdline_url=`
+/// on the command start mark (`C`) or VS Code's OSC 633 `E`. The scripts
+/// `inject_shell_integration` loads don't. `None` if no command has
+/// finished yet, or the shell sends no marks.
+#[tauri::command]
+pub fn get_last_command(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<Option<CommandRecord>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let last = shared.commands.lock().last().cloned();
+    Ok(last)
+}
+
+/// Get the commands a session's shell finished, oldest first, as kept by
+/// `set_command_history_depth`. See `get_last_command` for where they
+/// come from.
+#[tauri::command]
+pub fn get_command_history(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<Vec<CommandRecord>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let records = shared.commands.lock().records().cloned().collect();
+    Ok(records)
+}
+
+/// Set how many finished commands a session keeps for
+/// `get_command_history` (20 by default, at most 1000), dropping the
+/// oldest beyond that. 0 stops keeping them.
+#[tauri::command]
+pub fn set_command_history_depth(
+    app: AppHandle,
+    session_id: u32,
+    depth: usize,
+) -> Result<(), TerminalError> {
+    if depth > MAX_HISTORY_DEPTH {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Command history depth must be at most {}",
+            MAX_HISTORY_DEPTH
+        )));
+    }
+    let shared = session_shared(&app, session_id)?;
+    shared.commands.lock().set_depth(depth);
+    Ok(())
+}
+
+/// Interrupt commands in a session that run longer than `secs`, e.g. to
+/// catch a hung command in an automated flow, or stop doing so with
+/// `None`. A command over its time gets SIGINT, in its process group, then
+/// SIGTERM and SIGKILL 3 s apart while it keeps running, with a
+/// `terminal-command-timeout` `{ session_id, session_uuid, pgid, signal,
+/// running_secs }` for each. Commands run by the shell itself, such as a
+/// loop of builtins, only get SIGINT, so the shell isn't killed.
+///
+/// Commands are timed from one shell integration mark to the next (OSC 133
+/// C, then D or the next prompt, see `inject_shell_integration`), so the
+/// timer starts over with every command. Without the marks, a command
+/// counts as running while a program other than the shell is in the
+/// terminal's foreground. Checked every 250 ms. Unix only.
+#[tauri::command]
+pub fn set_command_timeout(
+    app: AppHandle,
+    session_id: u32,
+    secs: Option<u64>,
+) -> Result<(), TerminalError> {
+    if secs == Some(0) {
+        return Err(TerminalError::InvalidArgument(
+            "Command timeout must be at least 1 second".to_string(),
+        ));
+    }
+    #[cfg(unix)]
+    {
+        let shared = session_shared(&app, session_id)?;
+        let mut timeout = shared.command_timeout.lock();
+        *timeout = secs.map(Duration::from_secs);
+        if timeout.is_some() && !shared.watchdog_running.swap(true, Ordering::SeqCst) {
+            start_command_watchdog(&app, session_id, &shared);
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (app, session_id);
+        Err(TerminalError::Unsupported(
+            "Command timeouts are only supported on Unix".to_string(),
+        ))
+    }
+}
+
+/// Turn the line discipline's echo on or off, e.g. to take a password in a
+/// masked field of the UI. Unix only.
+///
+/// `terminal-echo-changed` is emitted when echo changes, whether through
+/// this or the line discipline settings, or by a program itself (as `sudo`
+/// and `ssh` do for password prompts). Programs' changes are only noticed
+/// when they next print something, which prompts do, so treat that as a
+/// hint; this setter is the reliable way to be sure of the state. Programs
+/// that manage their own modes may turn echo back on.
+#[tauri::command]
+pub fn set_echo(app: AppHandle, session_id: u32, enabled: bool) -> Result<(), TerminalError> {
+    #[cfg(unix)]
+    {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        if let Some(fd) = session.master.lock().as_raw_fd() {
+            let mut t = termios::get(fd)
+                .map_err(|e| TerminalError::Pty(format!("Failed to read terminal modes: {}", e)))?;
+            if enabled {
+                t.c_lflag |= libc::ECHO;
+            } else {
+                t.c_lflag &= !libc::ECHO;
+            }
+            termios::set(fd, &t)
+                .map_err(|e| TerminalError::Pty(format!("Failed to set terminal modes: {}", e)))?;
+        }
+        track_echo(&app, session_id, session);
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (app, session_id, enabled);
+        Err(TerminalError::Unsupported(
+            "Changing echo is only supported on Unix".to_string(),
+        ))
+    }
+}
+
+/// Spawn an exited session again with the same configuration and id, for
+/// `ExitBehavior::Restart`
+fn restart_session(
+    app: &AppHandle,
+    session_id: u32,
+    shared: &SessionShared,
+    size: Option<PtySize>,
+) {
+    let Some(mut options) = shared.restart_options.lock().take() else {
+        return;
+    };
+    // As the session was last, not as it started
+    if let Some(size) = size {
+        options.cols = Some(size.cols);
+        options.rows = Some(size.rows);
+    }
+    options.color_scheme = *shared.color_scheme.lock();
+    options.decoder = OutputDecoder::from_label(shared.decoder.lock().name()).unwrap_or_default();
+    options.session_id = Some(session_id);
+    options.exit_behavior = ExitBehavior::Restart;
+    options.output_channel = shared.output_channel.lock().take();
+
+    match spawn_session(app.clone(), options) {
+        Ok(_) => {
+            if let Ok(new_shared) = session_shared(app, session_id) {
+                log::info!(
+                    target: LOG_TARGET, session_id = session_id;
+                    "Restarted terminal session {}", session_id
+                );
+                let payload = SessionEvent::new(session_id, &new_shared);
+                let _ = emit_event(app, &new_shared, "terminal-restarted", payload);
+            }
+        }
+        Err(e) => log::error!(
+            target: LOG_TARGET, session_id = session_id;
+            "Failed to restart terminal session {}: {}", session_id, e
+        ),
+    }
+}
+
+/// Remove a session kept open after exiting, when Enter is pressed in it
+fn close_kept_session(app: &AppHandle, session_id: u32) {
+    let state = app.state::<TerminalState>();
+    let session = {
+        let mut sessions = state.sessions.write();
+        match sessions.get(&session_id) {
+            Some(s) if s.shared.kept.load(Ordering::SeqCst) => sessions.remove(&session_id),
+            _ => None,
+        }
+    };
+    if let Some(session) = session {
+        let payload = SessionEvent::new(session_id, &session.shared);
+        let _ = emit_event(app, &session.shared, "terminal-closed", payload);
+    }
+}
+
+/// Set what happens when a session's shell exits: `close` removes the
+/// session (the default), `keep` leaves it open showing the exit code until
+/// Enter is pressed in it (then `terminal-closed` is emitted), and `restart`
+/// spawns the shell again with the same configuration, size and id,
+/// emitting `terminal-restarted` with its new UUID. The output channel
+/// carries over; the scrollback, filters and triggers start afresh. A shell
+/// that ran for less than a second isn't restarted, so a broken one doesn't
+/// respawn in a loop. `terminal-exit` is emitted in every case.
+#[tauri::command]
+pub fn set_exit_behavior(
+    app: AppHandle,
+    session_id: u32,
+    behavior: ExitBehavior,
+) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    *shared.exit_behavior.lock() = behavior;
+    Ok(())
+}
+
+/// Write data to a terminal session
+#[tauri::command]
+pub fn write_terminal(app: AppHandle, session_id: u32, data: String) -> Result<(), TerminalError> {
+    write_to_session(&app, session_id, data.as_bytes())?;
+    record_input(&app, session_id, &data);
+    mirror_input(&app, session_id, &data);
+    Ok(())
+}
+
+/// Write input from the frontend to the session's followers too, and on to
+/// theirs; `link_input` keeps the links free of cycles
+fn mirror_input(app: &AppHandle, session_id: u32, data: &str) {
+    let followers: Vec<(u32, String)> = {
+        let state = app.state::<TerminalState>();
+        let links = state.input_links.lock();
+        links
+            .iter()
+            .filter(|l| l.source == session_id)
+            .map(|l| (l.follower, l.follower_uuid.clone()))
+            .collect()
+    };
+    for (follower, uuid) in followers {
+        // Unlinked when it exits, but its id may already be someone else's
+        let live = session_shared(app, follower).is_ok_and(|shared| shared.uuid == uuid);
+        if live && write_to_session(app, follower, data.as_bytes()).is_ok() {
+            record_input(app, follower, data);
+            mirror_input(app, follower, data);
+        }
+    }
+}
+
+/// Copy everything the frontend writes to `source_id` (with
+/// `write_terminal`) to `follower_id` as well, from now until
+/// `unlink_input` or either session exits, e.g. to drive a pair's or
+/// students' terminals from one. Only input is mirrored: the follower's
+/// output is its own, so the sessions drift apart if they start out in
+/// different states. Followers can have followers of their own, but a link
+/// that would make input go round in a cycle is refused.
+#[tauri::command]
+pub fn link_input(app: AppHandle, source_id: u32, follower_id: u32) -> Result<(), TerminalError> {
+    let source_uuid = session_shared(&app, source_id)?.uuid.clone();
+    let follower_uuid = session_shared(&app, follower_id)?.uuid.clone();
+    let state = app.state::<TerminalState>();
+    let mut links = state.input_links.lock();
+    if links
+        .iter()
+        .any(|l| l.source == source_id && l.follower == follower_id)
+    {
+        return Ok(());
+    }
+    // Input to the follower must never make its way back to the source
+    let mut reached = vec![follower_id];
+    let mut next = 0;
+    while let Some(&id) = reached.get(next) {
+        if id == source_id {
+            return Err(TerminalError::InvalidArgument(format!(
+                "Linking terminal session {} to {} would make a cycle",
+                source_id, follower_id
+            )));
+        }
+        for link in links.iter().filter(|l| l.source == id) {
+            if !reached.contains(&link.follower) {
+                reached.push(link.follower);
+            }
+        }
+        next += 1;
+    }
+    links.push(InputLink {
+        source: source_id,
+        source_uuid,
+        follower: follower_id,
+        follower_uuid,
+    });
+    Ok(())
+}
+
+/// Stop copying `source_id`'s input to `follower_id`, see `link_input`.
+/// Does nothing if they weren't linked.
+#[tauri::command]
+pub fn unlink_input(app: AppHandle, source_id: u32, follower_id: u32) {
+    let state = app.state::<TerminalState>();
+    state
+        .input_links
+        .lock()
+        .retain(|l| !(l.source == source_id && l.follower == follower_id));
+}
+
+/// Payload of `terminal-links-changed`
+#[derive(Clone, serde::Serialize)]
+struct LinksChanged {
+    /// The session whose links were cleared, `None` for all of them
+    session_id: Option<u32>,
+    /// Its UUID, `None` too if it has exited since
+    session_uuid: Option<String>,
+    removed: usize,
+}
+
+/// Remove every input link `session_id` is in, as source or follower (see
+/// `link_input`), e.g. after rearranging panes so no stale link copies
+/// input somewhere unexpected. Returns how many links were removed and
+/// emits `terminal-links-changed` if there were any. Links are the only
+/// relationship between sessions; there are no broadcast groups to leave.
+#[tauri::command]
+pub fn clear_session_links(app: AppHandle, session_id: u32) -> usize {
+    clear_links(&app, Some(session_id))
+}
+
+/// Remove every input link between sessions, see `clear_session_links`
+#[tauri::command]
+pub fn clear_all_links(app: AppHandle) -> usize {
+    clear_links(&app, None)
+}
+
+fn clear_links(app: &AppHandle, session_id: Option<u32>) -> usize {
+    let removed = {
+        let state = app.state::<TerminalState>();
+        let mut links = state.input_links.lock();
+        let before = links.len();
+        match session_id {
+            Some(id) => links.retain(|l| l.source != id && l.follower != id),
+            None => links.clear(),
+        }
+        before - links.len()
+    };
+    if removed > 0 {
+        log::info!(target: LOG_TARGET, "Cleared {} input links", removed);
+        let session_uuid = session_id.and_then(|id| {
+            let state = app.state::<TerminalState>();
+            let sessions = state.sessions.read();
+            sessions.get(&id).map(|session| session.shared.uuid.clone())
+        });
+        let payload = LinksChanged {
+            session_id,
+            session_uuid,
+            removed,
+        };
+        let _ = app.emit("terminal-links-changed", payload);
+    }
+    removed
+}
+
+/// Show `data` in every running session as if the program had printed it,
+/// e.g. a notice that the app is about to restart. It goes to the
+/// scrollback and output events only; no shell sees it as input. Returns how
+/// many sessions it was shown in.
+#[tauri::command]
+pub fn feed_all_terminals(app: AppHandle, data: String) -> usize {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    let mut fed = 0;
+    for (&session_id, session) in sessions.iter() {
+        let shared = &session.shared;
+        if shared.alive.load(Ordering::SeqCst) && !shared.parked.load(Ordering::SeqCst) {
+            emit_output(&app, session_id, &session.shared, data.as_bytes());
+            fed += 1;
+        }
+    }
+    fed
+}
+
+/// Append input from the frontend to the session's input recording, if any
+fn record_input(app: &AppHandle, session_id: u32, data: &str) {
+    let Ok(shared) = session_shared(app, session_id) else {
+        return;
+    };
+    record_event(session_id, &shared, |recorder| recorder.record(data));
+}
+
+/// Write an event to the session's input recording, if any, stopping the
+/// recording if that fails
+fn record_event(
+    session_id: u32,
+    shared: &SessionShared,
+    write: impl FnOnce(&mut InputRecorder) -> std::io::Result<()>,
+) {
+    let mut recording = shared.input_recording.lock();
+    if let Some(recorder) = recording.as_mut() {
+        if let Err(e) = write(recorder) {
+            log::warn!(
+                target: LOG_TARGET, session_id = session_id;
+                "Stopped input recording for terminal session {}: {}", session_id, e
+            );
+            *recording = None;
+        }
+    }
+}
+
+/// Resize a terminal session
+#[tauri::command]
+pub fn resize_terminal(
+    app: AppHandle,
+    session_id: u32,
+    cols: u16,
+    rows: u16,
+) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    if let Some(session) = sessions.get(&session_id) {
+        resize_pty(session, cols, rows)
+    } else {
+        Err(TerminalError::NotFound(session_id))
+    }
+}
+
+/// Resize every session to the same size, for a layout where all panes
+/// share one, in a single call rather than one per pane. Sessions already at
+/// that size aren't resized again, so their programs get no SIGWINCH.
+/// While the window is dragged, calls within 50 ms of the last resize are
+/// coalesced: the latest size is applied once that time is up, to the
+/// sessions open then, and these calls return `deferred`. Otherwise
+/// `resized` has the ids of the sessions now at the size, leaving out any
+/// that failed to resize and shells parked by `prewarm_shells`.
+#[tauri::command]
+pub fn resize_all_terminals(app: AppHandle, cols: u16, rows: u16) -> GridResizeResult {
+    let state = app.state::<TerminalState>();
+    let now = Instant::now();
+    let step = state.grid_resize.lock().request((cols, rows), now);
+    match step {
+        GridResizeStep::Now => {
+            let resized = resize_all_now(&app, cols, rows);
+            return GridResizeResult {
+                deferred: false,
+                resized,
+            };
+        }
+        GridResizeStep::Schedule(wait) => {
+            let app = app.clone();
+            thread::spawn(move || {
+                thread::sleep(wait);
+                let state = app.state::<TerminalState>();
+                let size = state.grid_resize.lock().fire(Instant::now());
+                if let Some((cols, rows)) = size {
+                    resize_all_now(&app, cols, rows);
+                }
+            });
+        }
+        GridResizeStep::Coalesced => {}
+    }
+    GridResizeResult {
+        deferred: true,
+        resized: Vec::new(),
+    }
+}
+
+/// Returned by `resize_all_terminals`
+#[derive(serde::Serialize)]
+pub struct GridResizeResult {
+    /// The size is applied later, coalesced with the calls that follow
+    deferred: bool,
+    /// Sessions resized now, or already at the size
+    resized: Vec<u32>,
+}
+
+fn resize_all_now(app: &AppHandle, cols: u16, rows: u16) -> Vec<u32> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    let mut resized: Vec<u32> = sessions
+        .values()
+        .filter(|session| !session.shared.parked.load(Ordering::SeqCst))
+        .filter(|session| {
+            let size = session.master.lock().get_size();
+            if size.is_ok_and(|size| size.cols == cols && size.rows == rows) {
+                return true;
+            }
+            resize_pty(session, cols, rows).is_ok()
+        })
+        .map(|session| session.id)
+        .collect();
+    resized.sort_unstable();
+    resized
+}
+
+/// Resize a terminal session and, with `update_env`, also export `COLUMNS`
+/// and `LINES` in the shell for programs that only read the size from the
+/// environment. The export is typed into the shell, so it's only done when
+/// shell integration (OSC 133) shows it's waiting at an empty prompt.
+/// Returns whether the environment was updated.
+#[tauri::command]
+pub fn resize_terminal_full(
+    app: AppHandle,
+    session_id: u32,
+    cols: u16,
+    rows: u16,
+    update_env: bool,
+) -> Result<bool, TerminalError> {
+    let at_prompt = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        resize_pty(session, cols, rows)?;
+        session.shared.at_prompt.load(Ordering::SeqCst)
+    };
+    if !(update_env && at_prompt) {
+        return Ok(false);
+    }
+    // The leading space keeps it out of most shells' history
+    let export = format!(" export COLUMNS={} LINES={}\r", cols, rows);
+    write_to_session(&app, session_id, export.as_bytes())?;
+    Ok(true)
+}
+
+/// Set the variables of a dotenv file (`KEY=VALUE` lines, see
+/// `dotenv::parse`) in a running shell by typing an `export` for each, e.g.
+/// after editing a project's `.env`. Like `resize_terminal_full` this needs
+/// shell integration (OSC 133) to show the shell is waiting at an empty
+/// prompt, and fails otherwise; nothing is taken out of the output, so the
+/// exports are echoed. The file is checked in full first, with errors
+/// naming the offending line. Returns how many variables were set.
+#[tauri::command]
+pub fn apply_env_file(
+    app: AppHandle,
+    session_id: u32,
+    path: String,
+) -> Result<usize, TerminalError> {
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| TerminalError::InvalidArgument(format!("Failed to read {}: {}", path, e)))?;
+    let vars = dotenv::parse(&text)
+        .map_err(|e| TerminalError::InvalidArgument(format!("Invalid env file {}, {}", path, e)))?;
+    let shared = session_shared(&app, session_id)?;
+    if !shared.at_prompt.load(Ordering::SeqCst) {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Terminal session {} isn't waiting at a prompt",
+            session_id
+        )));
+    }
+    if vars.is_empty() {
+        return Ok(0);
+    }
+    // The leading spaces keep them out of most shells' history
+    let input: String = vars
+        .iter()
+        .map(|(key, value)| format!(" export {}={}\r", key, dotenv::shell_quote(value)))
+        .collect();
+    write_to_session(&app, session_id, input.as_bytes())?;
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Applied {} variables from {} to terminal session {}", vars.len(), path, session_id
+    );
+    Ok(vars.len())
+}
+
+/// Make a session's program redraw its screen, e.g. a TUI left garbled, by
+/// resizing the terminal one column narrower and back like a user fiddling
+/// with the window would. Programs redraw on the SIGWINCH each resize sends.
+#[tauri::command]
+pub async fn force_redraw(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    let size = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        let size = session.master.lock().get_size();
+        let size =
+            size.map_err(|e| TerminalError::Pty(format!("Failed to get terminal size: {}", e)))?;
+        if size.cols <= 1 {
+            return Err(TerminalError::InvalidArgument(
+                "Terminal is too narrow to redraw by resizing".to_string(),
+            ));
+        }
+        resize_pty(session, size.cols - 1, size.rows)?;
+        size
+    };
+    tokio::time::sleep(REDRAW_RESIZE_DELAY).await;
+
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    let session = sessions
+        .get(&session_id)
+        .ok_or(TerminalError::NotFound(session_id))?;
+    resize_pty(session, size.cols, size.rows)
+}
+
+fn resize_pty(session: &PtySession, cols: u16, rows: u16) -> Result<(), TerminalError> {
+    let session_id = session.id;
+    session
+        .master
+        .lock()
+        .resize(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|e| {
+            log::error!(
+                target: LOG_TARGET, session_id = session_id;
+                "Failed to resize terminal session {}: {}", session_id, e
+            );
+            TerminalError::Pty(format!("Failed to resize terminal: {}", e))
+        })?;
+    log::debug!(
+        target: LOG_TARGET, session_id = session_id;
+        "Resized terminal session {} to {}x{}", session_id, cols, rows
+    );
+    record_event(session_id, &session.shared, |recorder| {
+        recorder.record_resize(cols, rows)
+    });
+    write_ndjson(&session.shared, |log| log.resize(cols, rows));
+    if let Some(screen) = session.shared.screen.lock().as_mut() {
+        screen.resize(cols, rows);
+    }
+    Ok(())
+}
+
+/// Kill a terminal session. The shell gets SIGHUP like when a terminal
+/// window closes; on Unix it's killed if still running 2 s later.
+#[tauri::command]
+pub fn kill_terminal(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let session = state.sessions.write().remove(&session_id);
+    let session = session.ok_or(TerminalError::NotFound(session_id))?;
+    // Dropped outside the map lock
+    hang_up(session_id, session);
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Killed terminal session {}", session_id
+    );
+    Ok(())
+}
+
+/// Drop a session taken out of the map, which sends its shell SIGHUP; on
+/// Unix the shell is killed outright if it ignores that
+fn hang_up(session_id: u32, session: PtySession) {
+    #[cfg(unix)]
+    let shared = Arc::downgrade(&session.shared);
+    drop(session);
+    #[cfg(unix)]
+    thread::spawn(move || {
+        thread::sleep(KILL_GRACE_PERIOD);
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let _reap = shared.reap_lock.lock();
+        if let (true, Some(pid)) = (shared.alive.load(Ordering::SeqCst), shared.pid) {
+            log::warn!(
+                target: LOG_TARGET, session_id = session_id;
+                "Terminal session {} ignored SIGHUP, killing it", session_id
+            );
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+        }
+    });
+}
+
+/// Payload of `terminal-subsystem-reset`
+#[derive(Clone, serde::Serialize)]
+struct SubsystemReset {
+    sessions_killed: usize,
+}
+
+/// Start the terminal backend over, e.g. to recover from a wedged state:
+/// every session is killed, prewarmed shells included, and all of them are
+/// lost. Clears the pool and input links, restarts session ids from 0,
+/// skipping any a session spawned meanwhile has, waits up to 5 s for the
+/// sessions' threads to finish and emits `terminal-subsystem-reset`
+/// `{ sessions_killed }` so the frontend can clear its panes; the killed
+/// sessions emit no `terminal-exit`. Settings such as the startup failure
+/// threshold and dangerous commands are kept. Commands racing with the
+/// reset see their session gone, or act on one spawned after it. Returns
+/// how many sessions were killed, not counting prewarmed shells.
+#[tauri::command]
+pub async fn reset_terminal_subsystem(app: AppHandle) -> usize {
+    let state = app.state::<TerminalState>();
+    // Each lock is taken on its own, so this can't deadlock with a command
+    state.prewarmed.lock().clear();
+    state.input_links.lock().clear();
+    let sessions: Vec<(u32, PtySession)> = {
+        let mut sessions = state.sessions.write();
+        // Along with emptying the map, so ids are handed out again only
+        // once no session has them
+        SESSION_COUNTER.store(0, Ordering::SeqCst);
+        sessions.drain().collect()
+    };
+
+    let mut sessions_killed = 0;
+    let mut threads = Vec::new();
+    for (session_id, session) in sessions {
+        let shared = &session.shared;
+        // Parked sessions emit nothing, so this quiets their exit
+        if !shared.parked.swap(true, Ordering::SeqCst) {
+            sessions_killed += 1;
+        }
+        threads.push((session_id, Arc::downgrade(shared)));
+        hang_up(session_id, session);
+    }
+    // The session's threads hold it until they're done. Polled without
+    // blocking a thread, as the wait can take seconds.
+    let deadline = Instant::now() + RESET_JOIN_TIMEOUT;
+    for (session_id, shared) in threads {
+        while shared.strong_count() > 0 && Instant::now() < deadline {
+            tokio::time::sleep(RESET_POLL_INTERVAL).await;
+        }
+        if shared.strong_count() > 0 {
+            log::warn!(
+                target: LOG_TARGET, session_id = session_id;
+                "Threads of terminal session {} still running after reset", session_id
+            );
+        }
+    }
+
+    log::info!(
+        target: LOG_TARGET,
+        "Reset terminal subsystem, killing {} sessions", sessions_killed
+    );
+    let payload = SubsystemReset { sessions_killed };
+    let _ = app.emit("terminal-subsystem-reset", payload);
+    sessions_killed
+}
+
+/// Kill every session whose metadata has `key` set to `value` (see
+/// `set_session_metadata`), e.g. all terminals tagged `project=foo`, like
+/// `kill_terminal` does. They're taken out together, so a session tagged
+/// meanwhile is either killed or not touched at all. Returns the ids killed,
+/// which is empty if nothing matched.
+#[tauri::command]
+pub fn kill_terminals_where(app: AppHandle, key: String, value: String) -> Vec<u32> {
+    let state = app.state::<TerminalState>();
+    let killed: Vec<(u32, PtySession)> = {
+        let mut sessions = state.sessions.write();
+        let ids: Vec<u32> = sessions
+            .iter()
+            .filter(|(_, s)| s.shared.metadata.lock().get(&key) == Some(&value))
+            .map(|(&session_id, _)| session_id)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| sessions.remove(&id).map(|s| (id, s)))
+            .collect()
+    };
+
+    let mut ids = Vec::with_capacity(killed.len());
+    for (session_id, session) in killed {
+        hang_up(session_id, session);
+        ids.push(session_id);
+    }
+    ids.sort_unstable();
+    if !ids.is_empty() {
+        log::info!(
+            target: LOG_TARGET,
+            "Killed terminal sessions {:?} tagged {}={}", ids, key, value
+        );
+    }
+    ids
+}
+
+/// List active terminal sessions, not counting shells parked by
+/// `prewarm_shells`
+#[tauri::command]
+pub fn list_terminals(app: AppHandle) -> Vec<u32> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    sessions
+        .iter()
+        .filter(|(_, session)| !session.shared.parked.load(Ordering::SeqCst))
+        .map(|(&session_id, _)| session_id)
+        .collect()
+}
+
+/// Start up to `count` shells with the default configuration (that of
+/// `spawn_terminal` without arguments) and park them, so `take_prewarmed`
+/// can hand one out without waiting for a login shell to start. Parked
+/// shells run as usual, with their output kept in the scrollback, but emit
+/// no events and aren't listed. At most 8 are kept, each for up to 30
+/// minutes. Returns how many were started.
+#[tauri::command]
+pub fn prewarm_shells(app: AppHandle, count: usize) -> Result<usize, TerminalError> {
+    let state = app.state::<TerminalState>();
+    let room = MAX_PREWARMED.saturating_sub(state.prewarmed.lock().len());
+    let count = count.min(room);
+    for _ in 0..count {
+        let options = SpawnOptions {
+            parked: true,
+            ..Default::default()
+        };
+        let session_id = spawn_session(app.clone(), options)?;
+        state.prewarmed.lock().push_back(session_id);
+
+        let app = app.clone();
+        thread::spawn(move || {
+            thread::sleep(PREWARM_TTL);
+            let state = app.state::<TerminalState>();
+            let mut prewarmed = state.prewarmed.lock();
+            let Some(index) = prewarmed.iter().position(|&id| id == session_id) else {
+                return; // Taken already
+            };
+            prewarmed.remove(index);
+            drop(prewarmed);
+            log::info!(
+                target: LOG_TARGET, session_id = session_id;
+                "Prewarmed terminal session {} expired", session_id
+            );
+            let _ = kill_terminal(app.clone(), session_id);
+        });
+    }
+    Ok(count)
+}
+
+/// Hand out a shell parked by `prewarm_shells`, which from then on behaves
+/// like one from `spawn_terminal`. Its output so far (usually a prompt) is
+/// only in the scrollback; use `get_scrollback_and_subscribe` to show it
+/// without missing anything. Returns `None` if the pool is empty, after
+/// which the frontend should spawn normally, as it should for any
+/// non-default configuration. Resize the session to fit its view.
+#[tauri::command]
+pub fn take_prewarmed(app: AppHandle) -> Option<u32> {
+    let state = app.state::<TerminalState>();
+    let mut prewarmed = state.prewarmed.lock();
+    let sessions = state.sessions.read();
+    while let Some(session_id) = prewarmed.pop_front() {
+        // Skip shells that exited while parked
+        let Some(session) = sessions.get(&session_id) else {
+            continue;
+        };
+        if !session.shared.alive.load(Ordering::SeqCst) {
+            continue;
+        }
+        session.shared.parked.store(false, Ordering::SeqCst);
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Took prewarmed terminal session {}", session_id
+        );
+        return Some(session_id);
+    }
+    None
+}
+
+/// Set or clear the light/dark hint for a running session.
+///
+/// The environment of a running shell can't be changed, so this only affects
+/// replies to OSC 10/11 color queries from programs started afterwards.
+#[tauri::command]
+pub fn set_color_scheme(
+    app: AppHandle,
+    session_id: u32,
+    scheme: Option<ColorScheme>,
+) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    if let Some(session) = sessions.get(&session_id) {
+        *session.shared.color_scheme.lock() = scheme;
+        Ok(())
+    } else {
+        Err(TerminalError::NotFound(session_id))
+    }
+}
+
+/// Add an output filter to a session.
+///
+/// Filters run in the order they were added, each on the previous one's
+/// output, before anything is emitted. While a session has filters, an
+/// incomplete trailing line is held back briefly so a match split across
+/// reads is still caught.
+#[tauri::command]
+pub fn add_output_filter(
+    app: AppHandle,
+    session_id: u32,
+    filter: OutputFilterSpec,
+) -> Result<(), TerminalError> {
+    let filter = OutputFilter::compile(filter)?;
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    if let Some(session) = sessions.get(&session_id) {
+        session.shared.filters.lock().push(filter);
+        Ok(())
+    } else {
+        Err(TerminalError::NotFound(session_id))
+    }
+}
+
+/// Remove all output filters from a session
+#[tauri::command]
+pub fn clear_output_filters(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    if let Some(session) = sessions.get(&session_id) {
+        session.shared.filters.lock().clear();
+        Ok(())
+    } else {
+        Err(TerminalError::NotFound(session_id))
+    }
+}
+
+/// Full reset (RIS), soft reset (DECSTR) and show-cursor
+const RESET_SEQUENCE: &str = "\x1bc\x1b[!p\x1b[?25h";
+
+/// Reset a terminal left in a broken state by a crashed program.
+///
+/// The reset sequence is written straight to the PTY, so it gets there even
+/// when the shell is unresponsive; whatever runs in the session reads it as
+/// input. On Unix the PTY's line discipline is first restored to sane
+/// cooked-mode settings unless `reset_termios` is false.
+#[tauri::command]
+pub fn reset_terminal(
+    app: AppHandle,
+    session_id: u32,
+    reset_termios: Option<bool>,
+) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    let session = sessions
+        .get(&session_id)
+        .ok_or(TerminalError::NotFound(session_id))?;
+
+    let reset_termios = reset_termios.unwrap_or(true);
+    reset_pty(session, session_id, reset_termios)?;
+    #[cfg(unix)]
+    if reset_termios {
+        track_echo(&app, session_id, session);
+    }
+    update_modes(&app, session_id, &session.shared, |m| {
+        std::mem::take(m) != TerminalModes::default()
+    });
+    let payload = SessionEvent::new(session_id, &session.shared);
+    let _ = emit_event(&app, &session.shared, "terminal-reset", payload);
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Reset terminal session {}", session_id
+    );
+    Ok(())
+}
+
+/// `reset_terminal`'s work on the PTY: restore its line discipline if
+/// `reset_termios` (Unix only), then write the reset sequence
+fn reset_pty(
+    session: &PtySession,
+    session_id: u32,
+    reset_termios: bool,
+) -> Result<(), TerminalError> {
+    #[cfg(unix)]
+    if reset_termios {
+        if let Some(fd) = session.master.lock().as_raw_fd() {
+            termios::make_sane(fd).map_err(|e| {
+                TerminalError::Pty(format!("Failed to reset terminal modes: {}", e))
+            })?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = reset_termios;
+    session.write_input(session_id, RESET_SEQUENCE.as_bytes())
+}
+
+/// Stream a session's output over a dedicated channel.
+///
+/// Once subscribed, output for this session is sent only on `channel`, in
+/// order, instead of as global `terminal-output` events. If the channel
+/// closes, the session falls back to the global event.
+#[tauri::command]
+pub fn subscribe_output(
+    app: AppHandle,
+    session_id: u32,
+    channel: Channel<TerminalOutput>,
+) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+
+    if let Some(session) = sessions.get(&session_id) {
+        *session.shared.output_channel.lock() = Some(channel);
+        reattach(session_id, &session.shared);
+        Ok(())
+    } else {
+        Err(TerminalError::NotFound(session_id))
+    }
+}
+
+/// Set the answerback string written back to the program whenever it sends
+/// ENQ (`0x05`), as some legacy and mainframe-style tools expect. Empty (the
+/// default) means ENQ goes unanswered.
+#[tauri::command]
+pub fn set_answerback(app: AppHandle, session_id: u32, text: String) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    *shared.answerback.lock() = text;
+    Ok(())
+}
+
+/// Get the modes the session's program has set that affect input encoding
+/// (application cursor keys, bracketed paste and mouse reporting) or
+/// rendering (autowrap). Changes are also announced with
+/// `terminal-mode-changed`.
+#[tauri::command]
+pub fn get_terminal_modes(app: AppHandle, session_id: u32) -> Result<TerminalModes, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let modes = *shared.modes.lock();
+    Ok(modes)
+}
+
+/// Send a mouse event at zero-based cell `x`, `y` to the session's program,
+/// encoded for the mouse mode it enabled (X10, normal, button- or any-event
+/// tracking, in the SGR encoding if mode 1006 is set). Coordinates are
+/// clamped to the terminal size. Returns false, sending nothing, if the
+/// program didn't ask for this kind of event or the position can't be
+/// encoded.
+#[tauri::command]
+pub fn send_mouse(
+    app: AppHandle,
+    session_id: u32,
+    button: MouseButton,
+    x: u16,
+    y: u16,
+    action: MouseAction,
+    modifiers: Option<MouseModifiers>,
+) -> Result<bool, TerminalError> {
+    let event = MouseEvent {
+        button,
+        action,
+        modifiers: modifiers.unwrap_or_default(),
+        x,
+        y,
+    };
+    let encoded = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        let size = session
+            .master
+            .lock()
+            .get_size()
+            .map_err(|e| TerminalError::Pty(format!("Failed to get terminal size: {}", e)))?;
+        let modes = *session.shared.modes.lock();
+        mouse::encode(&event, &modes, size.cols, size.rows)
+    };
+    match encoded {
+        Some(data) => write_to_session(&app, session_id, &data).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Cap how fast a session's output is emitted, e.g. so a program flooding
+/// its terminal can't swamp the UI, or remove the cap with `None`. Output
+/// over the limit is held back and emitted as the rate allows; once more
+/// than 256 KiB is waiting it's moved to the scrollback unemitted and
+/// `terminal-output-skipped` is sent, after which the frontend should catch
+/// up with `get_scrollback_and_subscribe`.
+#[tauri::command]
+pub fn set_output_rate_limit(
+    app: AppHandle,
+    session_id: u32,
+    bytes_per_sec: Option<u64>,
+) -> Result<(), TerminalError> {
+    if bytes_per_sec == Some(0) {
+        return Err(TerminalError::InvalidArgument(
+            "Output rate limit must be above 0".to_string(),
+        ));
+    }
+    let shared = session_shared(&app, session_id)?;
+    *shared.rate_limit.lock() = bytes_per_sec.map(RateLimit::new);
+    Ok(())
+}
+
+/// Output batching settings of a session, see `set_output_batching`
+#[derive(serde::Serialize)]
+pub struct OutputBatching {
+    max_bytes: usize,
+    /// 0 when batching is off
+    flush_ms: u64,
+}
+
+/// Coalesce a session's PTY reads into batches of up to `max_bytes`
+/// (1 B to 1 MiB), each waiting at most `flush_ms` (up to 1000) after its
+/// first read for more before it's processed and emitted, trading latency
+/// for fewer, larger output events; a log firehose may want large batches,
+/// an interactive pane none. `flush_ms` 0 turns batching off, as sessions
+/// start. Takes effect from the next read.
+#[tauri::command]
+pub fn set_output_batching(
+    app: AppHandle,
+    session_id: u32,
+    max_bytes: usize,
+    flush_ms: u64,
+) -> Result<(), TerminalError> {
+    if !(1..=MAX_BATCH_BYTES).contains(&max_bytes) {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Batch size must be between 1 and {} bytes",
+            MAX_BATCH_BYTES
+        )));
+    }
+    if flush_ms > MAX_BATCH_FLUSH_MS {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Batch flush interval must be at most {} ms",
+            MAX_BATCH_FLUSH_MS
+        )));
+    }
+    let shared = session_shared(&app, session_id)?;
+    shared.batch_max_bytes.store(max_bytes, Ordering::Relaxed);
+    shared.batch_flush_ms.store(flush_ms, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Get a session's output batching settings
+#[tauri::command]
+pub fn get_output_batching(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<OutputBatching, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    Ok(OutputBatching {
+        max_bytes: shared.batch_max_bytes.load(Ordering::Relaxed),
+        flush_ms: shared.batch_flush_ms.load(Ordering::Relaxed),
+    })
+}
+
+/// Cap how much output is emitted in one output event for a session, from
+/// 1 KiB to 4 MiB (64 KiB as sessions start), so one huge read or batch
+/// doesn't make an IPC message that stalls the frontend. Larger output is
+/// split into consecutive events with consecutive `seq`s, in order. The cap
+/// is on the bytes read from the PTY: decoding can make text longer, and
+/// base64 makes `terminal-output-binary` events a third longer.
+#[tauri::command]
+pub fn set_max_emit_size(
+    app: AppHandle,
+    session_id: u32,
+    bytes: usize,
+) -> Result<(), TerminalError> {
+    if !(MIN_MAX_EMIT_BYTES..=MAX_MAX_EMIT_BYTES).contains(&bytes) {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Output event size must be between {} and {} bytes",
+            MIN_MAX_EMIT_BYTES, MAX_MAX_EMIT_BYTES
+        )));
+    }
+    let shared = session_shared(&app, session_id)?;
+    shared.max_emit_bytes.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Get the most output a session emits in one event, see
+/// `set_max_emit_size`
+#[tauri::command]
+pub fn get_max_emit_size(app: AppHandle, session_id: u32) -> Result<usize, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    Ok(shared.max_emit_bytes.load(Ordering::Relaxed))
+}
+
+/// Get a session's special characters (erase, werase, kill, intr, eof,
+/// susp) and the echo, icanon, isig and ixon flags, e.g. for a settings
+/// panel showing what Backspace and Ctrl-C do. Unix only.
+#[tauri::command]
+pub fn get_line_discipline(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<LineDiscipline, TerminalError> {
+    #[cfg(unix)]
+    {
+        let fd = session_pty_fd(&app, session_id)?;
+        let t = termios::get(fd)
+            .map_err(|e| TerminalError::Pty(format!("Failed to read terminal modes: {}", e)))?;
+        Ok(LineDiscipline::from_termios(&t))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (app, session_id);
+        Err(TerminalError::Unsupported(
+            "Line discipline settings are only supported on Unix".to_string(),
+        ))
+    }
+}
+
+/// Change the settings `get_line_discipline` reports; the rest of the
+/// session's termios is left alone. Programs that set their own modes (e.g.
+/// editors) override these while running. Unix only.
+#[tauri::command]
+pub fn set_line_discipline(
+    app: AppHandle,
+    session_id: u32,
+    settings: LineDiscipline,
+) -> Result<(), TerminalError> {
+    #[cfg(unix)]
+    {
+        let fd = session_pty_fd(&app, session_id)?;
+        let mut t = termios::get(fd)
+            .map_err(|e| TerminalError::Pty(format!("Failed to read terminal modes: {}", e)))?;
+        settings.apply(&mut t);
+        termios::set(fd, &t)
+            .map_err(|e| TerminalError::Pty(format!("Failed to set terminal modes: {}", e)))?;
+        let state = app.state::<TerminalState>();
+        if let Some(session) = state.sessions.read().get(&session_id) {
+            track_echo(&app, session_id, session);
+        }
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Changed line discipline of terminal session {}", session_id
+        );
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (app, session_id, settings);
+        Err(TerminalError::Unsupported(
+            "Line discipline settings are only supported on Unix".to_string(),
+        ))
+    }
+}
+
+#[cfg(unix)]
+fn session_pty_fd(
+    app: &AppHandle,
+    session_id: u32,
+) -> Result<std::os::unix::io::RawFd, TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    let session = sessions
+        .get(&session_id)
+        .ok_or(TerminalError::NotFound(session_id))?;
+    let fd = session.master.lock().as_raw_fd();
+    fd.ok_or_else(|| TerminalError::Pty("The terminal has no file descriptor".to_string()))
+}
+
+/// What the backend supports on this platform and build, so the UI can
+/// disable features instead of calling commands that would fail
+#[derive(serde::Serialize)]
+pub struct Capabilities {
+    version: &'static str,
+    /// Sending signals to the session's processes
+    supports_signals: bool,
+    /// Line discipline control (`get_line_discipline`, `reset_terminal`
+    /// restoring termios)
+    supports_termios: bool,
+    /// Pixel dimensions on resize, for sixel/image protocols
+    supports_pixel_size: bool,
+    /// Per-session CPU/memory statistics
+    supports_cpu_stats: bool,
+    /// The `control_socket` option of `spawn_terminal`, where the policy
+    /// allows it
+    supports_control_socket: bool,
+    /// The `cgroup` option of `spawn_terminal`: cgroups v2 is there and the
+    /// app's cgroup is delegated to its user, as checked on each call
+    supports_cgroups: bool,
+    /// `tee_output_to_pipe`
+    supports_output_pipe: bool,
+    /// The `rlimits` option of `spawn_terminal`
+    supports_rlimits: bool,
+    /// The `inject_shell_integration` option of `spawn_terminal`
+    supports_shell_integration_injection: bool,
+}
+
+/// Report backend capabilities, from the platform and build and, where
+/// they depend on it, the system and the policy
+#[tauri::command]
+pub fn get_capabilities(app: AppHandle) -> Capabilities {
+    let policy = &app.state::<TerminalState>().policy;
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        supports_signals: false,
+        supports_termios: cfg!(unix),
+        supports_pixel_size: false,
+        supports_cpu_stats: false,
+        supports_control_socket: cfg!(unix) && policy.allow_control_socket,
+        #[cfg(target_os = "linux")]
+        supports_cgroups: cgroup::delegated(),
+        #[cfg(not(target_os = "linux"))]
+        supports_cgroups: false,
+        supports_output_pipe: cfg!(unix),
+        supports_rlimits: cfg!(unix),
+        supports_shell_integration_injection: cfg!(unix),
+    }
+}
+
+/// Set how soon after spawning a child must exit to be reported with
+/// `terminal-startup-failed` (default 200 ms, 0 disables). Applies to
+/// sessions spawned afterwards.
+#[tauri::command]
+pub fn set_startup_failure_threshold(app: AppHandle, ms: u64) {
+    let state = app.state::<TerminalState>();
+    state.startup_failure_ms.store(ms, Ordering::Relaxed);
+}
+
+/// Set how long a shell may take from spawning to its first output, normally
+/// its prompt, before `terminal-slow-startup` is emitted and a warning logged
+/// (default 2000 ms, 0 disables). A shell that never prints anything isn't
+/// reported.
+#[tauri::command]
+pub fn set_slow_startup_threshold(app: AppHandle, ms: u64) {
+    let state = app.state::<TerminalState>();
+    state.slow_startup_ms.store(ms, Ordering::Relaxed);
+}
+
+/// Set how much output arriving within `window_ms` counts as a burst
+/// (default 1 MiB in 500 ms, `bytes` 0 disables). A session's output going
+/// over it emits `terminal-output-burst` once, so the UI can e.g. turn off
+/// ligatures and search indexing until `terminal-output-burst-end`, sent
+/// when less than half as much arrives within the window. Applies to
+/// running sessions too.
+#[tauri::command]
+pub fn set_output_burst_threshold(
+    app: AppHandle,
+    bytes: u64,
+    window_ms: u64,
+) -> Result<(), TerminalError> {
+    if window_ms == 0 {
+        return Err(TerminalError::InvalidArgument(
+            "Output burst window must be above 0 ms".to_string(),
+        ));
+    }
+    let state = app.state::<TerminalState>();
+    state.burst_bytes.store(bytes, Ordering::Relaxed);
+    state.burst_window_ms.store(window_ms, Ordering::Relaxed);
+    Ok(())
+}
+
+/// A `search_scrollback` hit
+#[derive(serde::Serialize)]
+pub struct ScrollbackMatch {
+    line: usize,
+    /// Character offset of the match within the line's text
+    column: usize,
+}
+
+/// Size of a session's scrollback, see `get_scrollback_info`
+#[derive(serde::Serialize)]
+pub struct ScrollbackInfo {
+    /// Bytes currently retained
+    total_bytes: usize,
+    total_lines: usize,
+    /// Most bytes the scrollback keeps
+    cap_bytes: usize,
+    /// Whether older output has already been dropped
+    truncated: bool,
+}
+
+/// Get the size of a session's scrollback without fetching it, e.g. to decide
+/// whether to page through it with `get_scrollback_range`
+#[tauri::command]
+pub fn get_scrollback_info(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<ScrollbackInfo, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let scrollback = shared.scrollback.lock();
+    Ok(ScrollbackInfo {
+        total_bytes: scrollback.len(),
+        total_lines: scrollback.line_count(),
+        cap_bytes: scrollback.cap(),
+        truncated: scrollback.is_truncated(),
+    })
+}
+
+/// Scrollback dropped from a session by `apply_scrollback_limit_all`
+#[derive(serde::Serialize)]
+pub struct ScrollbackTrim {
+    session_id: u32,
+    dropped_bytes: usize,
+}
+
+/// Change the scrollback cap of every session, and of those spawned from now
+/// on, e.g. when the setting changes. Each session's cap is changed and its
+/// excess output dropped under its scrollback lock, so output arriving
+/// meanwhile is never kept above the old cap or trimmed twice. Returns the
+/// bytes dropped per session, for sessions that lost any.
+#[tauri::command]
+pub fn apply_scrollback_limit_all(
+    app: AppHandle,
+    max_bytes: usize,
+) -> Result<Vec<ScrollbackTrim>, TerminalError> {
+    if max_bytes == 0 {
+        return Err(TerminalError::InvalidArgument(
+            "Scrollback limit must be at least 1 byte".to_string(),
+        ));
+    }
+    let state = app.state::<TerminalState>();
+    state.scrollback_bytes.store(max_bytes, Ordering::SeqCst);
+    let sessions = state.sessions.read();
+    let mut trims: Vec<ScrollbackTrim> = sessions
+        .iter()
+        .filter_map(|(&session_id, session)| {
+            let dropped_bytes = session.shared.scrollback.lock().set_cap(max_bytes);
+            (dropped_bytes > 0).then_some(ScrollbackTrim {
+                session_id,
+                dropped_bytes,
+            })
+        })
+        .collect();
+    trims.sort_by_key(|t| t.session_id);
+    Ok(trims)
+}
+
+/// Memory estimate of `compact_memory`, in bytes
+#[derive(serde::Serialize)]
+pub struct MemoryCompaction {
+    before_bytes: usize,
+    after_bytes: usize,
+}
+
+/// Release memory kept beyond what's in use, e.g. after a burst of sessions
+/// prewarmed and discarded or exiting quickly: the session map and pools are
+/// shrunk to what they hold, and every session's scrollback and banner to
+/// their length. Scrollback isn't compressed, so there are no cold segments
+/// to compress. Returns an estimate of the memory these take before and
+/// after; other allocations, such as screen models, aren't counted. The
+/// session map is write-locked only while it's shrunk, and each session is
+/// compacted on its own under its scrollback lock, so its output thread
+/// waits at most for one copy of that scrollback.
+#[tauri::command]
+pub fn compact_memory(app: AppHandle) -> MemoryCompaction {
+    let state = app.state::<TerminalState>();
+    let mut before_bytes = 0;
+    let mut after_bytes = 0;
+    let shared: Vec<Arc<SessionShared>> = {
+        let mut sessions = state.sessions.write();
+        let entry = std::mem::size_of::<(u32, PtySession)>();
+        before_bytes += sessions.capacity() * entry;
+        sessions.shrink_to_fit();
+        after_bytes += sessions.capacity() * entry;
+        sessions.values().map(|s| s.shared.clone()).collect()
+    };
+    {
+        let mut prewarmed = state.prewarmed.lock();
+        before_bytes += prewarmed.capacity() * std::mem::size_of::<u32>();
+        prewarmed.shrink_to_fit();
+        after_bytes += prewarmed.capacity() * std::mem::size_of::<u32>();
+    }
+    {
+        let mut links = state.input_links.lock();
+        before_bytes += links.capacity() * std::mem::size_of::<InputLink>();
+        links.shrink_to_fit();
+        after_bytes += links.capacity() * std::mem::size_of::<InputLink>();
+    }
+    {
+        let mut exits = state.recent_exits.lock();
+        exits.retain(|(at, _)| at.elapsed() < RECENT_EXIT_GRACE);
+        let entry = std::mem::size_of::<(Instant, TerminalExit)>();
+        before_bytes += exits.capacity() * entry;
+        exits.shrink_to_fit();
+        after_bytes += exits.capacity() * entry;
+    }
+
+    for session in shared {
+        {
+            let mut scrollback = session.scrollback.lock();
+            before_bytes += scrollback.allocated();
+            scrollback.shrink_to_fit();
+            after_bytes += scrollback.allocated();
+        }
+        let mut banner = session.banner.lock();
+        before_bytes += banner.capacity();
+        banner.shrink_to_fit();
+        after_bytes += banner.capacity();
+    }
+
+    log::info!(
+        target: LOG_TARGET,
+        "Compacted terminal memory from {} to {} bytes", before_bytes, after_bytes
+    );
+    MemoryCompaction {
+        before_bytes,
+        after_bytes,
+    }
+}
+
+/// Get a session's scrollback (raw output, including escape sequences)
+#[tauri::command]
+pub fn get_scrollback(app: AppHandle, session_id: u32) -> Result<String, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut scrollback = shared.scrollback.lock();
+    let text = shared.decoder.lock().decode_snapshot(scrollback.contents());
+    Ok(text)
+}
+
+/// Maintain a model of a session's screen, for `get_screen_text` and
+/// `export_grid_state`, or stop and discard it. Off by default, so output
+/// is otherwise only passed through. When turned on it starts from the
+/// scrollback replayed at the current size, which is exact unless the
+/// scrollback has been truncated or the session resized since.
+#[tauri::command]
+pub fn enable_screen_model(
+    app: AppHandle,
+    session_id: u32,
+    enabled: bool,
+) -> Result<(), TerminalError> {
+    let state = app.state::<TerminalState>();
+    let sessions = state.sessions.read();
+    let session = sessions
+        .get(&session_id)
+        .ok_or(TerminalError::NotFound(session_id))?;
+    let mut scrollback = session.shared.scrollback.lock();
+    let mut screen = session.shared.screen.lock();
+    match (enabled, screen.is_some()) {
+        (true, false) => {
+            let size = session.master.lock().get_size().unwrap_or_default();
+            let mut model = Screen::new(size.cols, size.rows);
+            model.feed(scrollback.contents());
+            *screen = Some(model);
+        }
+        (false, _) => *screen = None,
+        (true, true) => {}
+    }
+    Ok(())
+}
+
+/// Get the text a session's screen shows, one string per row with trailing
+/// blanks trimmed, e.g. for a screen reader or to check in end-to-end tests
+/// that a prompt is showing. Needs `enable_screen_model`; see `Screen` for
+/// what's modelled.
+#[tauri::command]
+pub fn get_screen_text(app: AppHandle, session_id: u32) -> Result<Vec<String>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let screen = shared.screen.lock();
+    match screen.as_ref() {
+        Some(screen) => Ok(screen.text()),
+        None => Err(TerminalError::InvalidArgument(format!(
+            "Screen model is not enabled for terminal session {}",
+            session_id
+        ))),
+    }
+}
+
+/// Get what a session's screen shows in full, for another renderer to draw
+/// the current frame from without replaying the scrollback, e.g. when
+/// handing a pane over from a preview to the full terminal view: each row
+/// with its colors and other attributes, the cursor, and the modes in
+/// effect. Needs `enable_screen_model`; see `Screen` for what's modelled.
+#[tauri::command]
+pub fn export_grid_state(app: AppHandle, session_id: u32) -> Result<GridState, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let modes = *shared.modes.lock();
+    let screen = shared.screen.lock();
+    match screen.as_ref() {
+        Some(screen) => Ok(screen.grid_state(modes)),
+        None => Err(TerminalError::InvalidArgument(format!(
+            "Screen model is not enabled for terminal session {}",
+            session_id
+        ))),
+    }
+}
+
+/// Get how many whole seconds it's been since a session last had input
+/// (including keepalive writes) or output, e.g. to sort tabs by recency or
+/// dim stale ones. A new session counts as active when spawned.
+#[tauri::command]
+pub fn get_idle_time(app: AppHandle, session_id: u32) -> Result<u64, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let idle = shared.last_activity.lock().elapsed();
+    Ok(idle.as_secs())
+}
+
+/// Get how many bytes of input a session's PTY has accepted so far, e.g. to
+/// check whether a paste arrived whole. Counts everything written:
+/// `write_terminal` and the other commands sending input, keepalives and
+/// replies to the program's terminal queries. Failed writes aren't
+/// counted, nor is input dropped while the session is kept open after
+/// exiting.
+#[tauri::command]
+pub fn get_bytes_written(app: AppHandle, session_id: u32) -> Result<u64, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    Ok(shared.bytes_written.load(Ordering::Relaxed))
+}
+
+/// Get the working directory of the process in the foreground of a
+/// session, e.g. to keep a tab's title current when the shell doesn't
+/// report it with OSC 7. Reads `/proc/<pid>/cwd` on Linux and asks
+/// `proc_pidinfo` on macOS, so it's polled rather than pushed: poll when
+/// output arrives or on a timer. Falls back to the shell if the foreground
+/// process group can't be read. `None` if the process is gone (or the
+/// session exited) or its directory can't be read, e.g. a process running
+/// as another user.
+#[tauri::command]
+pub fn poll_cwd(app: AppHandle, session_id: u32) -> Result<Option<String>, TerminalError> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        let foreground = {
+            let state = app.state::<TerminalState>();
+            let sessions = state.sessions.read();
+            let session = sessions
+                .get(&session_id)
+                .ok_or(TerminalError::NotFound(session_id))?;
+            if !session.shared.alive.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            let leader = session.master.lock().process_group_leader();
+            leader.map(|p| p as u32).or(session.shared.pid)
+        };
+        let cwd = foreground.and_then(process::cwd);
+        Ok(cwd.map(|dir| dir.display().to_string()))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = (app, session_id);
+        Err(TerminalError::Unsupported(
+            "Polling the working directory is only supported on Linux and macOS".to_string(),
+        ))
+    }
+}
+
+/// Lines of the app's log included in a debug bundle
+const DEBUG_BUNDLE_LOG_LINES: usize = 1000;
+
+/// A session's state and metadata, see `snapshot_all_sessions`; also
+/// written to debug bundles
+#[derive(serde::Serialize)]
+pub struct SessionInfo {
+    session_id: u32,
+    session_uuid: String,
+    /// The `label_pty` it was spawned with
+    label: Option<String>,
+    pid: Option<u32>,
+    cwd: Option<String>,
+    pty_name: Option<String>,
+    cols: u16,
+    rows: u16,
+    alive: bool,
+    parked: bool,
+    detached: bool,
+    text_encoding: &'static str,
+    color_scheme: Option<ColorScheme>,
+    modes: TerminalModes,
+    at_prompt: bool,
+    shell_integration: bool,
+    idle_secs: u64,
+    bytes_written: u64,
+    scrollback_bytes: usize,
+    scrollback_lines: usize,
+    /// Memory the scrollback takes, room for more output included
+    scrollback_allocated_bytes: usize,
+    metadata: HashMap<String, String>,
+}
+
+impl SessionInfo {
+    fn new(session: &PtySession) -> Self {
+        let shared = &session.shared;
+        let size = session.master.lock().get_size().unwrap_or_default();
+        let (scrollback_bytes, scrollback_lines, scrollback_allocated_bytes) = {
+            let scrollback = shared.scrollback.lock();
+            (
+                scrollback.len(),
+                scrollback.line_count(),
+                scrollback.allocated(),
+            )
+        };
+        Self {
+            session_id: session.id,
+            session_uuid: shared.uuid.clone(),
+            label: shared.label.clone(),
+            pid: shared.pid,
+            cwd: shared.cwd.as_ref().map(|dir| dir.display().to_string()),
+            pty_name: shared.pty_name.clone(),
+            cols: size.cols,
+            rows: size.rows,
+            alive: shared.alive.load(Ordering::SeqCst),
+            parked: shared.parked.load(Ordering::SeqCst),
+            detached: shared.detached.load(Ordering::SeqCst),
+            text_encoding: shared.decoder.lock().name(),
+            color_scheme: *shared.color_scheme.lock(),
+            modes: *shared.modes.lock(),
+            at_prompt: shared.at_prompt.load(Ordering::SeqCst),
+            shell_integration: shared.shell_integration.load(Ordering::SeqCst),
+            idle_secs: shared.last_activity.lock().elapsed().as_secs(),
+            bytes_written: shared.bytes_written.load(Ordering::Relaxed),
+            scrollback_bytes,
+            scrollback_lines,
+            scrollback_allocated_bytes,
+            metadata: shared.metadata.lock().clone(),
+        }
+    }
+}
+
+/// Every session at one point in time, see `snapshot_all_sessions`
+#[derive(serde::Serialize)]
+pub struct SessionsSnapshot {
+    sessions: Vec<SessionInfo>,
+    total_sessions: usize,
+    /// Of all their scrollback buffers, room for more output included
+    total_scrollback_bytes: usize,
+}
+
+/// Get the state and metadata of every session, for an overview window or
+/// debugging, with totals. They're all read under one lock on the session
+/// map, so no session is spawned or removed while the snapshot is taken,
+/// unlike calling `list_terminals` then querying each one. Shells parked by
+/// `prewarm_shells` are included, marked `parked`. Sorted by id.
+#[tauri::command]
+pub fn snapshot_all_sessions(app: AppHandle) -> SessionsSnapshot {
+    let state = app.state::<TerminalState>();
+    let mut sessions: Vec<SessionInfo> = {
+        let sessions = state.sessions.read();
+        sessions.values().map(SessionInfo::new).collect()
+    };
+    sessions.sort_by_key(|info| info.session_id);
+    SessionsSnapshot {
+        total_sessions: sessions.len(),
+        total_scrollback_bytes: sessions
+            .iter()
+            .map(|info| info.scrollback_allocated_bytes)
+            .sum(),
+        sessions,
+    }
+}
+
+/// Write what's useful for a bug report about a session to a new directory
+/// at `path`, readable by the current user only:
+/// - `session.json`: its state, size and metadata
+/// - `scrollback.raw` and `scrollback.txt`: the scrollback as received, and
+///   decoded with the escape sequences stripped
+/// - `timing.json`: the `get_timing_report`, if timing is enabled
+/// - `backend.json`: the backend's version and platform
+/// - `environment.json`: the environment sessions start with (the app's own
+///   plus the terminal variables we set)
+/// - `app.log`: the last lines of the app's log file, if there is one
+///
+/// Environment values whose names look like secrets (containing e.g.
+/// `TOKEN`, `SECRET`, `PASSWORD` or ending in `_KEY`) are redacted unless
+/// `include_secrets` is set. The scrollback is included as is, so it may
+/// still show secrets that were printed.
+#[tauri::command]
+pub fn create_debug_bundle(
+    app: AppHandle,
+    session_id: u32,
+    path: String,
+    include_secrets: Option<bool>,
+) -> Result<(), TerminalError> {
+    let (shared, info) = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        (session.shared.clone(), SessionInfo::new(session))
+    };
+    let raw = shared.scrollback.lock().contents().to_vec();
+    let plain = shared.decoder.lock().decode_snapshot(&ansi::strip(&raw));
+    let timing = shared.timing.lock().as_ref().map(|t| t.report());
+    let env = std::env::vars().chain(
+        DEFAULT_ENV
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+    );
+    let env = debug_bundle::redact_env(env, include_secrets.unwrap_or(false));
+    // Where tauri-plugin-log writes by default
+    let log_file = app
+        .path()
+        .app_log_dir()
+        .ok()
+        .map(|dir| dir.join(format!("{}.log", app.package_info().name)));
+    let log = log_file.and_then(|f| debug_bundle::tail_lines(&f, DEBUG_BUNDLE_LOG_LINES).ok());
+
+    let written = DebugBundle::create(Path::new(&path)).and_then(|bundle| {
+        bundle.write_json("session.json", &info)?;
+        bundle.write("scrollback.raw", &raw)?;
+        bundle.write("scrollback.txt", plain.as_bytes())?;
+        if let Some(timing) = &timing {
+            bundle.write_json("timing.json", timing)?;
+        }
+        bundle.write_json("backend.json", &super::backend_info())?;
+        bundle.write_json("environment.json", &env)?;
+        if let Some(log) = &log {
+            bundle.write("app.log", log.as_bytes())?;
+        }
+        Ok(())
+    });
+    written.map_err(|e| {
+        TerminalError::InvalidArgument(format!("Failed to write debug bundle {}: {}", path, e))
+    })?;
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Wrote debug bundle of terminal session {} to {}", session_id, path
+    );
+    Ok(())
+}
+
+/// List the sessions that exited in the last 10 minutes (at most 100),
+/// most recent first, as reported by their `terminal-exit` events, e.g. for
+/// a "recently closed" menu that reopens one with `spawn_terminal`. A
+/// session that's restarted or kept open on exit is listed as well.
+#[tauri::command]
+pub fn list_recent_exits(app: AppHandle) -> Vec<TerminalExit> {
+    let state = app.state::<TerminalState>();
+    let mut recent_exits = state.recent_exits.lock();
+    recent_exits.retain(|(at, _)| at.elapsed() < RECENT_EXIT_GRACE);
+    recent_exits
+        .iter()
+        .rev()
+        .map(|(_, exit)| exit.clone())
+        .collect()
+}
+
+/// Get a session's scrollback from the start of `line` on
+#[tauri::command]
+pub fn get_scrollback_from(
+    app: AppHandle,
+    session_id: u32,
+    line: usize,
+) -> Result<String, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut scrollback = shared.scrollback.lock();
+    let data = scrollback.since_line(line).ok_or_else(|| {
+        TerminalError::InvalidArgument(format!("Line {} is past the end of the scrollback", line))
+    })?;
+    let text = shared.decoder.lock().decode_snapshot(data);
+    Ok(text)
+}
+
+/// Find the first line at or after `from_line` (default 0) containing
+/// `query`, ignoring escape sequences. Pass the previous hit's line + 1 to
+/// find the next one.
+#[tauri::command]
+pub fn search_scrollback(
+    app: AppHandle,
+    session_id: u32,
+    query: String,
+    from_line: Option<usize>,
+) -> Result<Option<ScrollbackMatch>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut scrollback = shared.scrollback.lock();
+    Ok(scrollback
+        .search(query.as_bytes(), from_line.unwrap_or(0))
+        .map(|(line, column)| ScrollbackMatch { line, column }))
+}
+
+/// Check whether a session's scrollback has a line containing `pattern`
+/// right now, e.g. whether a build has printed `error` yet, without
+/// fetching it or setting up a trigger. Lines are matched one at a time
+/// with escape sequences stripped, stopping at the first match, so a
+/// pattern can't span lines. `pattern` is plain text unless `regex` is set,
+/// and matched case-sensitively unless `case_sensitive` is false.
+#[tauri::command]
+pub fn scrollback_contains(
+    app: AppHandle,
+    session_id: u32,
+    pattern: String,
+    regex: Option<bool>,
+    case_sensitive: Option<bool>,
+) -> Result<bool, TerminalError> {
+    if pattern.is_empty() {
+        return Err(TerminalError::InvalidArgument(
+            "Pattern must not be empty".to_string(),
+        ));
+    }
+    let pattern = if regex.unwrap_or(false) {
+        pattern
+    } else {
+        regex::escape(&pattern)
+    };
+    let pattern = regex::bytes::RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive.unwrap_or(true))
+        .build()
+        .map_err(|e| TerminalError::InvalidArgument(format!("Invalid pattern: {}", e)))?;
+    let shared = session_shared(&app, session_id)?;
+    let found = shared.scrollback.lock().contains(&pattern);
+    Ok(found)
+}
+
+/// Maintain a line index for a session's scrollback, so line-based lookups
+/// (`get_scrollback_from`, `search_scrollback`) skip straight to the line
+/// instead of scanning from the start. Costs 8 bytes per retained line.
+#[tauri::command]
+pub fn enable_scrollback_index(
+    app: AppHandle,
+    session_id: u32,
+    enabled: bool,
+) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    shared.scrollback.lock().set_indexed(enabled);
+    Ok(())
+}
+
+/// Get a session's scrollback together with the `seq` of the first output
+/// event it doesn't include, taken atomically. A reconnecting frontend
+/// renders the snapshot and then applies only live events with a `seq` at
+/// or above the returned one, so nothing is lost or duplicated in between.
+/// Also resumes emitting output if the session was detached after its
+/// output events kept failing (`terminal-detached`).
+#[tauri::command]
+pub fn get_scrollback_and_subscribe(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<(String, u64), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut scrollback = shared.scrollback.lock();
+    reattach(session_id, &shared);
+    let next_seq = shared.next_seq.load(Ordering::SeqCst);
+    let snapshot = shared.decoder.lock().decode_snapshot(scrollback.contents());
+    Ok((snapshot, next_seq))
+}
+
+/// Stop emitting a session's output, e.g. while its tab is in the
+/// background, without slowing the program down: output keeps going to the
+/// scrollback (and control socket) as usual. Other events are still sent.
+/// Resume with `attach_output`.
+#[tauri::command]
+pub fn detach_output(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    if !shared.detached.swap(true, Ordering::SeqCst) {
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Detached output of terminal session {}", session_id
+        );
+    }
+    Ok(())
+}
+
+/// Resume emitting output after `detach_output`, returning the scrollback
+/// and next `seq` to rehydrate the view from, as `get_scrollback_and_subscribe`
+/// does
+#[tauri::command]
+pub fn attach_output(app: AppHandle, session_id: u32) -> Result<(String, u64), TerminalError> {
+    get_scrollback_and_subscribe(app, session_id)
+}
+
+/// Get scrollback lines `start_line..=end_line`, e.g. to copy a selection,
+/// optionally with escape sequences stripped
+#[tauri::command]
+pub fn get_scrollback_range(
+    app: AppHandle,
+    session_id: u32,
+    start_line: usize,
+    end_line: usize,
+    strip_ansi: bool,
+) -> Result<String, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut scrollback = shared.scrollback.lock();
+    let line_count = scrollback.line_count();
+    let data = scrollback.line_range(start_line, end_line).ok_or_else(|| {
+        TerminalError::InvalidArgument(format!(
+            "Invalid line range {}..={} (scrollback has {} lines)",
+            start_line, end_line, line_count
+        ))
+    })?;
+    let data = if strip_ansi {
+        ansi::strip(data)
+    } else {
+        data.to_vec()
+    };
+    let text = shared.decoder.lock().decode_snapshot(&data);
+    Ok(text)
+}
+
+/// Get a session's scrollback as a plain-text transcript, e.g. to save or
+/// share it: escape sequences (colors, cursor movement) are removed, and
+/// of the control characters only those `control_chars` keeps are left,
+/// tabs, line feeds, form feeds and carriage returns by default. With
+/// `printable` carriage returns go too, so a progress line redrawn in
+/// place ends up as its updates run together.
+#[tauri::command]
+pub fn export_transcript(
+    app: AppHandle,
+    session_id: u32,
+    control_chars: Option<ControlCharPolicy>,
+) -> Result<String, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let policy = control_chars.unwrap_or_default();
+    let plain = ansi::strip_with(shared.scrollback.lock().contents(), policy);
+    let text = shared.decoder.lock().decode_snapshot(&plain);
+    Ok(text)
+}
+
+/// Set the character encoding a session's output is decoded from: any
+/// WHATWG label such as `utf-8` (the default), `latin1` or `shift_jis`.
+/// `raw` stops decoding and emits output as base64 `terminal-output-binary`
+/// events, for a frontend that decodes it itself. Scrollback snapshots are
+/// decoded with the same encoding (UTF-8 in raw mode).
+#[tauri::command]
+pub fn set_text_encoding(
+    app: AppHandle,
+    session_id: u32,
+    encoding: String,
+) -> Result<(), TerminalError> {
+    let decoder = OutputDecoder::from_label(&encoding)?;
+    let shared = session_shared(&app, session_id)?;
+    *shared.decoder.lock() = decoder;
+    Ok(())
+}
+
+/// Have a session's output emitted as base64 `terminal-output-binary`
+/// events, chunk by chunk, whenever it can't be decoded as text, e.g. when
+/// someone `cat`s a binary file, so it isn't mangled into replacement
+/// characters. A chunk counts as binary once at least 4 of its bytes, and
+/// at least 1 in 16, are invalid in the session's encoding even with
+/// characters split across reads put back together; fewer stray bytes are
+/// still replaced. `terminal-binary-detected` `{ session_id, session_uuid,
+/// seq }` is emitted before the first binary chunk after text. A character
+/// that's split between a text chunk and a binary one is lost. Off by
+/// default; has no effect in raw mode, where every chunk is binary.
+#[tauri::command]
+pub fn set_auto_binary(
+    app: AppHandle,
+    session_id: u32,
+    enabled: bool,
+) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    shared.auto_binary.store(enabled, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Get the canonical name of a session's output encoding, or `raw`
+#[tauri::command]
+pub fn get_text_encoding(app: AppHandle, session_id: u32) -> Result<String, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let name = shared.decoder.lock().name();
+    Ok(name.to_string())
+}
+
+/// List the session's shell and every process descended from it (e.g. a dev
+/// server and its workers), parents before children. Empty once the shell
+/// has exited.
+#[tauri::command]
+pub fn get_process_tree(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<Vec<ProcessInfo>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    match shared.pid {
+        Some(pid) if shared.alive.load(Ordering::SeqCst) => process::tree(pid),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Play an asciinema v2 recording (`.cast` file) into a session's output at
+/// its recorded timing, scaled by `speed` (2.0 plays twice as fast). Meant
+/// for a fresh session, as the shell's own output is interleaved otherwise.
+/// Starting a replay stops any previous one; `terminal-replay-finished` is
+/// emitted when it ends.
+#[tauri::command]
+pub fn replay_recording(
+    app: AppHandle,
+    session_id: u32,
+    path: String,
+    speed: f32,
+) -> Result<(), TerminalError> {
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Invalid replay speed: {}",
+            speed
+        )));
+    }
+    let contents = std::fs::read_to_string(&path).map_err(|e| {
+        TerminalError::InvalidArgument(format!("Failed to read recording {}: {}", path, e))
+    })?;
+    let replay = Arc::new(Replay::new(Recording::parse(&contents)?, speed as f64));
+    let shared = session_shared(&app, session_id)?;
+    if let Some(previous) = shared.replay.lock().replace(replay.clone()) {
+        previous.stop();
+    }
+
+    thread::spawn(move || {
+        replay.run(|data| {
+            if !shared.alive.load(Ordering::SeqCst) {
+                return false;
+            }
+            emit_output(&app, session_id, &shared, data);
+            true
+        });
+        let mut current = shared.replay.lock();
+        if current.as_ref().is_some_and(|r| Arc::ptr_eq(r, &replay)) {
+            *current = None;
+        }
+        drop(current);
+        let payload = SessionEvent::new(session_id, &shared);
+        let _ = emit_event(&app, &shared, "terminal-replay-finished", payload);
+    });
+    Ok(())
 }
 
-/// Resize a terminal session
+/// Look up the replay running in a session
+fn session_replay(app: &AppHandle, session_id: u32) -> Result<Arc<Replay>, TerminalError> {
+    let shared = session_shared(app, session_id)?;
+    let replay = shared.replay.lock().clone();
+    replay.ok_or_else(|| {
+        TerminalError::InvalidArgument(format!(
+            "No replay is running in terminal session {}",
+            session_id
+        ))
+    })
+}
+
+/// Pause or resume a session's replay
 #[tauri::command]
-pub fn resize_terminal(
+pub fn pause_replay(app: AppHandle, session_id: u32, paused: bool) -> Result<(), TerminalError> {
+    session_replay(&app, session_id)?.set_paused(paused);
+    Ok(())
+}
+
+/// Jump to `position` seconds into a session's replay. Seeking backwards
+/// resets the terminal and replays from the start without delays.
+#[tauri::command]
+pub fn seek_replay(app: AppHandle, session_id: u32, position: f64) -> Result<(), TerminalError> {
+    session_replay(&app, session_id)?.seek(position);
+    Ok(())
+}
+
+/// Stop a session's replay
+#[tauri::command]
+pub fn stop_replay(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    session_replay(&app, session_id)?.stop();
+    Ok(())
+}
+
+/// Record everything the frontend writes to a session to `path`, appending
+/// a header line with the session's UUID and size and then asciicast-style
+/// `[seconds, "i", data]` lines that can later drive an input replay, with
+/// a `[seconds, "r", "COLSxROWS"]` line wherever the session was resized so
+/// the replay can follow. Program-generated replies (e.g. to color queries)
+/// aren't recorded.
+///
+/// The log captures whatever is typed, passwords included, so it's refused
+/// unless `TerminalPolicy::allow_input_recording` is set, and the file is
+/// created readable by the current user only.
+#[tauri::command]
+pub fn start_input_recording(
     app: AppHandle,
     session_id: u32,
-    cols: u16,
-    rows: u16,
-) -> Result<(), String> {
-    let state = app.state::<TerminalState>();
-    let sessions = state.sessions.lock();
+    path: String,
+) -> Result<(), TerminalError> {
+    if !app.state::<TerminalState>().policy.allow_input_recording {
+        return Err(TerminalError::PermissionDenied(
+            "Input recording is disabled by policy".to_string(),
+        ));
+    }
+    let (shared, size) = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        let size = session.master.lock().get_size().unwrap_or_default();
+        (session.shared.clone(), size)
+    };
+    let recorder = InputRecorder::open(&path, &shared.uuid, size.cols, size.rows).map_err(|e| {
+        TerminalError::InvalidArgument(format!("Failed to open {} for recording: {}", path, e))
+    })?;
+    *shared.input_recording.lock() = Some(recorder);
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Started input recording for terminal session {} to {}", session_id, path
+    );
+    Ok(())
+}
 
-    if let Some(session) = sessions.get(&session_id) {
-        session
-            .master
-            .resize(PtySize {
-                rows,
-                cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            })
-            .map_err(|e| format!("Failed to resize terminal: {}", e))?;
+/// Log a session's output to `path` as newline-delimited JSON, for log
+/// pipelines or replay tools: one object per output event, with its `seq`
+/// and the data in base64 as it went to the scrollback, plus one for each
+/// resize and a last one when the session exits, all timestamped (see
+/// `NdjsonLog`). The file is appended to, created readable by the current
+/// user only, and each line is written whole. If a write fails the log
+/// stops, with a warning in the app's log. Replaces any previous log.
+#[tauri::command]
+pub fn enable_ndjson_log(
+    app: AppHandle,
+    session_id: u32,
+    path: String,
+) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let log = NdjsonLog::open(&path, &shared.uuid).map_err(|e| {
+        TerminalError::InvalidArgument(format!("Failed to open {} for logging: {}", path, e))
+    })?;
+    *shared.ndjson_log.lock() = Some(log);
+    log::info!(
+        target: LOG_TARGET, session_id = session_id;
+        "Logging output of terminal session {} to {}", session_id, path
+    );
+    Ok(())
+}
+
+/// Stop a session's NDJSON log, see `enable_ndjson_log`
+#[tauri::command]
+pub fn disable_ndjson_log(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    if shared.ndjson_log.lock().take().is_some() {
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Stopped NDJSON log of terminal session {}", session_id
+        );
+    }
+    Ok(())
+}
+
+/// Mirror a session's output, as it goes to the scrollback, to the named
+/// pipe at `path` so external tools can follow it live (e.g. `cat` it).
+/// The FIFO is created, readable by the current user only, if it doesn't
+/// exist, and then removed when the session exits; an existing one is left
+/// in place. Output is only written while something reads the pipe, and
+/// dropped while nothing does or the reader falls behind, so the session
+/// is never held up by it. Replaces any previous pipe. Unix only.
+#[tauri::command]
+pub fn tee_output_to_pipe(
+    app: AppHandle,
+    session_id: u32,
+    path: String,
+) -> Result<(), TerminalError> {
+    #[cfg(unix)]
+    {
+        let shared = session_shared(&app, session_id)?;
+        let pipe = OutputPipe::open(&path).map_err(|e| {
+            TerminalError::InvalidArgument(format!("Failed to open pipe {}: {}", path, e))
+        })?;
+        *shared.output_pipe.lock() = Some(pipe);
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Mirroring output of terminal session {} to {}", session_id, path
+        );
         Ok(())
-    } else {
-        Err(format!("Terminal session {} not found", session_id))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (app, session_id, path);
+        Err(TerminalError::Unsupported(
+            "Output pipes are only supported on Unix".to_string(),
+        ))
     }
 }
 
-/// Kill a terminal session
+/// Stop a session's input recording
 #[tauri::command]
-pub fn kill_terminal(app: AppHandle, session_id: u32) -> Result<(), String> {
-    let state = app.state::<TerminalState>();
-    let mut sessions = state.sessions.lock();
+pub fn stop_input_recording(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    if shared.input_recording.lock().take().is_some() {
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Stopped input recording for terminal session {}", session_id
+        );
+    }
+    Ok(())
+}
+
+/// Run `action` whenever a line of the session's output matches `pattern`
+/// (a regex, matched against the output with escape sequences stripped,
+/// one line at a time). `action` is `{ kind: "write", text }` to send input
+/// or `{ kind: "emit", event }` to emit a frontend event carrying
+/// `{ session_id, trigger_id, text }`. With `once`, the trigger is removed
+/// after firing.
+///
+/// Triggers see output before output filters. They are evaluated in the
+/// order they were added, at most 32 per session. Returns the trigger's id.
+#[tauri::command]
+pub fn add_trigger(
+    app: AppHandle,
+    session_id: u32,
+    pattern: String,
+    action: TriggerAction,
+    once: Option<bool>,
+) -> Result<u32, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let id = shared
+        .triggers
+        .lock()
+        .add(&pattern, action, once.unwrap_or(false))?;
+    Ok(id)
+}
 
-    if sessions.remove(&session_id).is_some() {
-        log::info!("Killed terminal session {}", session_id);
+/// Remove a trigger from a session
+#[tauri::command]
+pub fn remove_trigger(
+    app: AppHandle,
+    session_id: u32,
+    trigger_id: u32,
+) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    if shared.triggers.lock().remove(trigger_id) {
         Ok(())
     } else {
-        Err(format!("Terminal session {} not found", session_id))
+        Err(TerminalError::InvalidArgument(format!(
+            "Trigger {} not found in terminal session {}",
+            trigger_id, session_id
+        )))
     }
 }
 
-/// List active terminal sessions
+/// Version of the `SessionExport` format written by this build
+const SESSION_EXPORT_VERSION: u32 = 2;
+
+/// A session's context as saved by `export_session`. Fields added in later
+/// versions must have defaults, so older exports keep importing. Unknown
+/// fields are ignored.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SessionExport {
+    version: u32,
+    cols: u16,
+    rows: u16,
+    cwd: Option<String>,
+    color_scheme: Option<ColorScheme>,
+    /// As accepted by `set_text_encoding`
+    text_encoding: String,
+    /// Raw output including escape sequences, base64-encoded
+    scrollback: String,
+    /// UUID of the exported session (since version 2). The imported session
+    /// gets its own, so this only links the two.
+    #[serde(default)]
+    session_uuid: Option<String>,
+}
+
+/// Save a session's context to recreate it elsewhere with `import_session`:
+/// its size, starting directory, color scheme, text encoding and scrollback.
+/// The running processes, output filters, triggers, replays, recordings,
+/// control socket and `run_as` user are not exported.
 #[tauri::command]
-pub fn list_terminals(app: AppHandle) -> Vec<u32> {
+pub fn export_session(app: AppHandle, session_id: u32) -> Result<SessionExport, TerminalError> {
+    let (size, shared) = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        let size = session.master.lock().get_size();
+        (size, session.shared.clone())
+    };
+    let size =
+        size.map_err(|e| TerminalError::Pty(format!("Failed to get terminal size: {}", e)))?;
+
+    let scrollback = BASE64.encode(shared.scrollback.lock().contents());
+    let text_encoding = shared.decoder.lock().name().to_string();
+    let color_scheme = *shared.color_scheme.lock();
+    Ok(SessionExport {
+        version: SESSION_EXPORT_VERSION,
+        cols: size.cols,
+        rows: size.rows,
+        cwd: shared.cwd.as_ref().map(|dir| dir.display().to_string()),
+        color_scheme,
+        text_encoding,
+        scrollback,
+        session_uuid: Some(shared.uuid.clone()),
+    })
+}
+
+/// Start a new shell with the context of an exported session. Its scrollback
+/// is emitted as output before anything from the new shell, so the terminal
+/// shows the old session's screen with a fresh prompt below it. Returns the
+/// new session's id.
+#[tauri::command]
+pub fn import_session(app: AppHandle, export: SessionExport) -> Result<u32, TerminalError> {
+    if export.version > SESSION_EXPORT_VERSION {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Session export version {} is newer than supported ({})",
+            export.version, SESSION_EXPORT_VERSION
+        )));
+    }
+    let preload = BASE64.decode(&export.scrollback).map_err(|e| {
+        TerminalError::InvalidArgument(format!("Invalid scrollback in session export: {}", e))
+    })?;
+    let options = SpawnOptions {
+        cols: Some(export.cols),
+        rows: Some(export.rows),
+        cwd: export.cwd,
+        color_scheme: export.color_scheme,
+        preload,
+        decoder: OutputDecoder::from_label(&export.text_encoding)?,
+        ..Default::default()
+    };
+    let session_id = spawn_session(app, options)?;
+    if let Some(source) = &export.session_uuid {
+        log::info!(
+            target: LOG_TARGET, session_id = session_id;
+            "Imported terminal session {} from session {}", session_id, source
+        );
+    }
+    Ok(session_id)
+}
+
+/// Get the start of a session's output, as kept by `capture_banner_bytes`
+/// (usually a login message and the first prompt), e.g. for a collapsed
+/// preview of the session. Escape sequences are left in; output filters
+/// apply. `None` if the session wasn't spawned with a banner size.
+#[tauri::command]
+pub fn get_banner(app: AppHandle, session_id: u32) -> Result<Option<String>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    if shared.banner_limit == 0 {
+        return Ok(None);
+    }
+    let banner = filter::apply_filters(&shared.filters.lock(), &shared.banner.lock());
+    let banner = shared.decoder.lock().decode_snapshot(&banner);
+    Ok(Some(banner))
+}
+
+/// Get a session's UUID, which unlike the numeric id is never reused and is
+/// also carried by its lifecycle events, input recordings and exports. Output
+/// events only carry the numeric id, to keep them small.
+#[tauri::command]
+pub fn get_session_uuid(app: AppHandle, session_id: u32) -> Result<String, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    Ok(shared.uuid.clone())
+}
+
+/// Tag a session with `key` set to `value`, or remove the tag with `None`,
+/// e.g. with the project or pane it belongs to so `kill_terminals_where`
+/// can act on a group of sessions. Only the frontend uses these.
+#[tauri::command]
+pub fn set_session_metadata(
+    app: AppHandle,
+    session_id: u32,
+    key: String,
+    value: Option<String>,
+) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut metadata = shared.metadata.lock();
+    match value {
+        Some(value) => metadata.insert(key, value),
+        None => metadata.remove(&key),
+    };
+    Ok(())
+}
+
+/// Get a session's tags, see `set_session_metadata`
+#[tauri::command]
+pub fn get_session_metadata(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<HashMap<String, String>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let metadata = shared.metadata.lock().clone();
+    Ok(metadata)
+}
+
+/// Get the path of a session's PTY slave device (e.g. `/dev/pts/7`), for
+/// attaching external tools while debugging. `None` where unsupported
+/// (currently everywhere but Linux).
+#[tauri::command]
+pub fn get_pty_name(app: AppHandle, session_id: u32) -> Result<Option<String>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    Ok(shared.pty_name.clone())
+}
+
+/// Write XON (Ctrl-Q) to a session, restarting output stopped by an XOFF
+#[tauri::command]
+pub fn send_flow_resume(app: AppHandle, session_id: u32) -> Result<(), TerminalError> {
+    write_to_session(&app, session_id, &[XON])
+}
+
+/// Get the live environment of a session's shell, after its rc files ran
+/// and anything the user exported. This types an `env` command into the
+/// shell and takes its output out of the stream (the command line itself is
+/// still echoed), so it only works while a POSIX-compatible shell is waiting
+/// at a prompt; otherwise it times out after 3 seconds.
+#[tauri::command]
+pub async fn get_shell_env(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<HashMap<String, String>, TerminalError> {
+    let output = capture_shell_output(&app, session_id, "env", "print its environment").await?;
+    Ok(shell_capture::parse_env(&output))
+}
+
+/// Look up what `program` runs in a session's shell, going by its live
+/// `PATH` after rc files ran rather than the app's own, by typing a
+/// `command -v` into it the way `get_shell_env` types `env` (its output is
+/// taken out of the stream, the command line is still echoed). Returns the
+/// path, or for aliases, functions and builtins whatever the shell says
+/// about them (e.g. `cd`, or `alias ll='ls -l'`), and `None` if it's not
+/// found. Refused unless the shell is at a prompt (see `is_at_prompt`), and
+/// times out after 3 seconds if it doesn't answer anyway.
+#[tauri::command]
+pub async fn which_in_session(
+    app: AppHandle,
+    session_id: u32,
+    program: String,
+) -> Result<Option<String>, TerminalError> {
+    if program.is_empty() || program.chars().any(char::is_control) {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Invalid program name {:?}",
+            program
+        )));
+    }
+    let at_prompt = {
+        let state = app.state::<TerminalState>();
+        let sessions = state.sessions.read();
+        let session = sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        session.at_prompt()
+    };
+    if !at_prompt {
+        return Err(TerminalError::InvalidArgument(format!(
+            "Terminal session {} isn't waiting at a prompt",
+            session_id
+        )));
+    }
+    let body = format!("command -v {}", dotenv::shell_quote(&program));
+    let output = capture_shell_output(&app, session_id, &body, "look it up").await?;
+    let found = output.trim();
+    Ok((!found.is_empty()).then(|| found.to_string()))
+}
+
+/// Type `body` into a session's shell and take its output out of the
+/// stream, see `ShellCapture`. `what` the shell was asked to do goes in the
+/// error if it doesn't.
+async fn capture_shell_output(
+    app: &AppHandle,
+    session_id: u32,
+    body: &str,
+    what: &str,
+) -> Result<String, TerminalError> {
+    let shared = session_shared(app, session_id)?;
+    let (capture, reply) = ShellCapture::new(body);
+    let command = capture.command();
+    {
+        let mut slot = shared.shell_capture.lock();
+        if slot.is_some() {
+            return Err(TerminalError::InvalidArgument(format!(
+                "Terminal session {} is already answering another query",
+                session_id
+            )));
+        }
+        *slot = Some(capture);
+    }
+    if let Err(e) = write_to_session(app, session_id, command.as_bytes()) {
+        *shared.shell_capture.lock() = None;
+        return Err(e);
+    }
+
+    match tokio::time::timeout(SHELL_CAPTURE_TIMEOUT, reply).await {
+        Ok(Ok(output)) => Ok(output),
+        // The capture was abandoned, e.g. the output was implausibly large
+        Ok(Err(_)) => Err(TerminalError::Pty(format!(
+            "Failed to read the output of terminal session {}",
+            session_id
+        ))),
+        // Dropping `reply` makes the capture pass output through again
+        Err(_) => Err(TerminalError::TimedOut(format!(
+            "Terminal session {} didn't {}; is the shell at a prompt?",
+            session_id, what
+        ))),
+    }
+}
+
+/// Wait for `start_marker` in a session's output and return what it prints
+/// from there to `end_marker`, markers excluded, e.g. for a script to
+/// echo a unique sentinel, run a command, echo another and take just the
+/// command's output. Only output from now on is looked at, as the program
+/// printed it: escape sequences and line breaks are kept, and output
+/// filters don't apply. Note that a shell echoes the command line typed,
+/// so print a marker without it appearing in the line, e.g. `printf
+/// '%s\n' STA RT`, or the echo is matched instead. The output is left in
+/// the stream as usual. `None` if both markers haven't been seen within
+/// `timeout_ms`; more than 1 MiB between them is an error.
+#[tauri::command]
+pub async fn capture_between(
+    app: AppHandle,
+    session_id: u32,
+    start_marker: String,
+    end_marker: String,
+    timeout_ms: u64,
+) -> Result<Option<String>, TerminalError> {
+    if start_marker.is_empty() || end_marker.is_empty() {
+        return Err(TerminalError::InvalidArgument(
+            "Capture markers must not be empty".to_string(),
+        ));
+    }
+    if timeout_ms == 0 {
+        return Err(TerminalError::InvalidArgument(
+            "Capture timeout must be above 0".to_string(),
+        ));
+    }
+    let shared = session_shared(&app, session_id)?;
+    let (capture, reply) = MarkerCapture::new(start_marker.as_bytes(), end_marker.as_bytes());
+    shared.marker_captures.lock().push(capture);
+
+    // Dropping `reply` on timeout has the capture removed with the next
+    // output
+    match tokio::time::timeout(Duration::from_millis(timeout_ms), reply).await {
+        Ok(Ok(captured)) => Ok(Some(shared.decoder.lock().decode_snapshot(&captured))),
+        Ok(Err(_)) => Err(TerminalError::Pty(format!(
+            "Output between the markers in terminal session {} is over {} bytes",
+            session_id, MAX_CAPTURE
+        ))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Ask the frontend's terminal which features it supports, with a Primary
+/// Device Attributes request (`ESC [ c`), and return its answer as sent,
+/// e.g. `ESC [ ? 1 ; 2 c`. That's the answer the session's programs get
+/// when they ask, which helps debug a TUI misbehaving. The request goes out
+/// like output, but isn't kept in the scrollback, and the answer coming
+/// back as input is taken out before it reaches the program. `None` if the
+/// frontend doesn't answer within a second, or isn't listening (see
+/// `detach_output`). An answer to a request the program makes meanwhile
+/// may be taken instead.
+#[tauri::command]
+pub async fn query_device_attributes(
+    app: AppHandle,
+    session_id: u32,
+) -> Result<Option<String>, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    if shared.detached.load(Ordering::SeqCst) || shared.parked.load(Ordering::SeqCst) {
+        return Ok(None);
+    }
+    let (reply_tx, reply) = oneshot::channel();
+    {
+        let mut pending = shared.device_attributes.lock();
+        if pending.as_ref().is_some_and(|reply| !reply.is_closed()) {
+            return Err(TerminalError::InvalidArgument(format!(
+                "Terminal session {} is already querying device attributes",
+                session_id
+            )));
+        }
+        *pending = Some(reply_tx);
+    }
+    // Numbered in order with the output, but not in the scrollback, so
+    // restoring it doesn't ask again
+    let seq = {
+        let _scrollback = shared.scrollback.lock();
+        shared.next_seq.fetch_add(1, Ordering::SeqCst)
+    };
+    let output = TerminalOutput {
+        session_id,
+        session_uuid: shared.uuid.clone(),
+        seq,
+        data: String::from_utf8_lossy(device_attributes::PRIMARY_DA_QUERY).into_owned(),
+    };
+    send_output(&app, session_id, &shared, output);
+
+    match tokio::time::timeout(DEVICE_ATTRIBUTES_TIMEOUT, reply).await {
+        Ok(Ok(response)) => Ok(Some(response)),
+        _ => {
+            *shared.device_attributes.lock() = None;
+            Ok(None)
+        }
+    }
+}
+
+/// Assess text the user is about to paste, so the frontend can ask for
+/// confirmation first: newlines (which run it immediately), control
+/// characters, and commands from the dangerous-command list (matched as
+/// whole words anywhere in the text)
+#[tauri::command]
+pub fn check_paste_safety(app: AppHandle, data: String) -> PasteRisk {
+    let state = app.state::<TerminalState>();
+    let dangerous_commands = state.dangerous_commands.lock();
+    paste::assess(&data, &dangerous_commands)
+}
+
+/// Replace the list of commands `check_paste_safety` flags (by default
+/// `sudo`, `rm -rf`, `mkfs`, `| sh` and the like)
+#[tauri::command]
+pub fn set_dangerous_commands(app: AppHandle, commands: Vec<String>) {
+    let state = app.state::<TerminalState>();
+    *state.dangerous_commands.lock() = commands;
+}
+
+/// Get the list of commands `check_paste_safety` flags
+#[tauri::command]
+pub fn get_dangerous_commands(app: AppHandle) -> Vec<String> {
+    let state = app.state::<TerminalState>();
+    let commands = state.dangerous_commands.lock();
+    commands.clone()
+}
+
+/// Set a command to run in every session spawned from now on, e.g. to
+/// define a team's aliases or source a shared profile; `None` or an empty
+/// command stops it. It's typed into the shell followed by Enter once the
+/// shell first prints anything (normally its prompt), so it needs an
+/// interactive shell reading its input, and is echoed like anything typed.
+/// Input the frontend writes before then is typed ahead of it. Sessions
+/// restarted, imported or prewarmed run it too; running sessions don't.
+#[tauri::command]
+pub fn set_global_init_command(
+    app: AppHandle,
+    command: Option<String>,
+) -> Result<(), TerminalError> {
+    let command = command.filter(|c| !c.trim().is_empty());
+    if command
+        .as_ref()
+        .is_some_and(|c| c.chars().any(char::is_control))
+    {
+        return Err(TerminalError::InvalidArgument(
+            "Init command must be a single line without control characters".to_string(),
+        ));
+    }
+    let state = app.state::<TerminalState>();
+    *state.global_init_command.lock() = command;
+    Ok(())
+}
+
+/// Get the command set with `set_global_init_command`
+#[tauri::command]
+pub fn get_global_init_command(app: AppHandle) -> Option<String> {
     let state = app.state::<TerminalState>();
-    let sessions = state.sessions.lock();
-    sessions.keys().cloned().collect()
+    let command = state.global_init_command.lock();
+    command.clone()
+}
+
+/// Turn on (or off, discarding the samples) recording of when a session's
+/// PTY reads happen and how many bytes each returns, and when input is
+/// written, for `get_timing_report`. Off by default; the most recent 4096
+/// reads and writes are kept.
+#[tauri::command]
+pub fn enable_timing(app: AppHandle, session_id: u32, enabled: bool) -> Result<(), TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let mut timing = shared.timing.lock();
+    match (enabled, timing.is_some()) {
+        (true, false) => *timing = Some(Timing::default()),
+        (false, _) => *timing = None,
+        (true, true) => {}
+    }
+    Ok(())
+}
+
+/// Summarize the samples collected since `enable_timing`: percentiles of
+/// read sizes, of the intervals between reads and of the delay from a write
+/// to the next read. This is the PTY side only, so comparing it with what
+/// the frontend sees separates PTY latency from IPC and rendering.
+#[tauri::command]
+pub fn get_timing_report(app: AppHandle, session_id: u32) -> Result<TimingReport, TerminalError> {
+    let shared = session_shared(&app, session_id)?;
+    let timing = shared.timing.lock();
+    match timing.as_ref() {
+        Some(timing) => Ok(timing.report()),
+        None => Err(TerminalError::InvalidArgument(format!(
+            "Timing is not enabled for terminal session {}",
+            session_id
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_output_is_emitted_in_order_in_pieces() {
+        let data: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        let pieces: Vec<&[u8]> = emit_pieces(&data, DEFAULT_MAX_EMIT_BYTES).collect();
+        let sizes: Vec<usize> = pieces.iter().map(|piece| piece.len()).collect();
+        assert_eq!(sizes, [DEFAULT_MAX_EMIT_BYTES; 4]);
+        assert_eq!(pieces.concat(), data);
+    }
+
+    #[test]
+    fn emit_pieces_keep_the_remainder_last() {
+        let data = vec![b'x'; 2 * MIN_MAX_EMIT_BYTES + 1];
+        let sizes: Vec<usize> = emit_pieces(&data, MIN_MAX_EMIT_BYTES)
+            .map(|piece| piece.len())
+            .collect();
+        assert_eq!(sizes, [MIN_MAX_EMIT_BYTES, MIN_MAX_EMIT_BYTES, 1]);
+    }
+
+    #[test]
+    fn small_output_is_emitted_whole() {
+        let pieces: Vec<&[u8]> = emit_pieces(b"hello", DEFAULT_MAX_EMIT_BYTES).collect();
+        assert_eq!(pieces, [b"hello"]);
+    }
+
+    #[test]
+    fn large_output_is_recorded_as_numbered_pieces() {
+        let shared = SessionShared::new("uuid".to_string());
+        shared.next_seq.store(10, Ordering::SeqCst);
+        let data: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        let recorded: Vec<(u64, &[u8])> = record_pieces(&shared, &data).collect();
+        let seqs: Vec<u64> = recorded.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, [10, 11, 12, 13]);
+        for (_, piece) in &recorded {
+            assert_eq!(piece.len(), DEFAULT_MAX_EMIT_BYTES);
+        }
+        assert_eq!(shared.next_seq.load(Ordering::SeqCst), 14);
+        assert_eq!(shared.scrollback.lock().contents(), data);
+    }
+
+    #[test]
+    fn recording_follows_the_emit_size_limit() {
+        let shared = SessionShared::new("uuid".to_string());
+        let max_bytes = &shared.max_emit_bytes;
+        max_bytes.store(MIN_MAX_EMIT_BYTES, Ordering::Relaxed);
+        let data = vec![b'x'; 2 * MIN_MAX_EMIT_BYTES + 1];
+        let recorded: Vec<(u64, usize)> = record_pieces(&shared, &data)
+            .map(|(seq, piece)| (seq, piece.len()))
+            .collect();
+        let expected = [(0, MIN_MAX_EMIT_BYTES), (1, MIN_MAX_EMIT_BYTES), (2, 1)];
+        assert_eq!(recorded, expected);
+    }
+
+    fn dirs(dirs: &[&str]) -> Vec<String> {
+        dirs.iter().map(|dir| dir.to_string()).collect()
+    }
+
+    #[test]
+    fn path_prepend_goes_in_front_in_order() {
+        let inherited = std::env::join_paths(["/usr/bin", "/bin"]).unwrap();
+        let path = prepend_path(&dirs(&["/opt/a/bin", "/opt/b/bin"]), Some(&inherited)).unwrap();
+        let order: Vec<PathBuf> = std::env::split_paths(&path).collect();
+        let expected = ["/opt/a/bin", "/opt/b/bin", "/usr/bin", "/bin"];
+        assert_eq!(order, expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn path_prepend_without_inherited_path() {
+        let path = prepend_path(&dirs(&["/opt/a/bin"]), None).unwrap();
+        assert_eq!(path, "/opt/a/bin");
+    }
+
+    #[test]
+    fn path_prepend_rejects_unusable_dirs() {
+        assert!(prepend_path(&dirs(&["/opt/a/bin", ""]), None).is_err());
+        #[cfg(unix)]
+        assert!(prepend_path(&dirs(&["/opt/a:b"]), None).is_err());
+    }
+
+    #[test]
+    fn explicit_program_wins_over_shell_env() {
+        let (path, source) = choose_shell(Some("/bin/bash".into()), Some("/bin/fish".into()));
+        assert_eq!(path, "/bin/bash");
+        assert!(matches!(source, ShellSource::Explicit));
+    }
+
+    #[test]
+    fn empty_program_falls_back_to_shell_env() {
+        let (path, source) = choose_shell(Some(String::new()), Some("/bin/fish".into()));
+        assert_eq!(path, "/bin/fish");
+        assert!(matches!(source, ShellSource::Env));
+    }
+
+    #[test]
+    fn missing_or_empty_shell_env_falls_back_to_zsh() {
+        for env_shell in [None, Some(String::new())] {
+            let (path, source) = choose_shell(None, env_shell);
+            assert_eq!(path, "/bin/zsh");
+            assert!(matches!(source, ShellSource::Default));
+        }
+    }
+
+    #[test]
+    fn shell_with_a_separator_is_checked_directly() {
+        let search_path = std::ffi::OsString::from("/usr/bin");
+        let exists = |file: &Path| file == Path::new("/opt/shells/zsh");
+        assert_eq!(
+            locate_shell("/opt/shells/zsh", Some(&search_path), exists),
+            Some(PathBuf::from("/opt/shells/zsh"))
+        );
+        assert!(locate_shell("/usr/bin/zsh", Some(&search_path), exists).is_none());
+    }
+
+    #[test]
+    fn bare_shell_name_is_searched_for_in_path_order() {
+        let search_path = std::env::join_paths(["/first", "/second", "/third"]).unwrap();
+        let exists = |file: &Path| file.starts_with("/second") || file.starts_with("/third");
+        assert_eq!(
+            locate_shell("fish", Some(&search_path), exists),
+            Some(PathBuf::from("/second/fish"))
+        );
+        assert_eq!(locate_shell("fish", Some(&search_path), |_| false), None);
+        assert_eq!(locate_shell("fish", None, |_| true), None);
+    }
+
+    #[test]
+    fn cwd_is_joined_onto_the_inherited_directory() {
+        let inherited = Some(PathBuf::from("/work"));
+        assert_eq!(
+            cwd_from(Some("project".into()), true, inherited.clone(), None),
+            Some(PathBuf::from("/work/project"))
+        );
+        assert_eq!(
+            cwd_from(Some("/tmp".into()), true, inherited, None),
+            Some(PathBuf::from("/tmp"))
+        );
+    }
+
+    #[test]
+    fn cwd_is_used_as_given_without_an_inherited_directory() {
+        assert_eq!(
+            cwd_from(Some("project".into()), false, None, None),
+            Some(PathBuf::from("project"))
+        );
+    }
+
+    #[test]
+    fn no_cwd_falls_back_to_home() {
+        let home = Some(std::ffi::OsString::from("/home/user"));
+        assert_eq!(
+            cwd_from(None, true, Some(PathBuf::from("/work")), home),
+            Some(PathBuf::from("/home/user"))
+        );
+        assert!(cwd_from(None, true, Some(PathBuf::from("/work")), None).is_none());
+    }
+
+    #[test]
+    fn no_cwd_without_home_fallback_keeps_the_inherited_directory() {
+        let home = Some(std::ffi::OsString::from("/home/user"));
+        assert_eq!(
+            cwd_from(None, false, Some(PathBuf::from("/work")), home.clone()),
+            Some(PathBuf::from("/work"))
+        );
+        assert_eq!(cwd_from(None, false, None, home), None);
+    }
+
+    #[test]
+    fn writes_racing_an_immediate_exit_fail_cleanly() {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let cmd = CommandBuilder::new("true");
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let mut writer = pair.master.take_writer().unwrap();
+        let alive = Arc::new(AtomicBool::new(true));
+        let writes = {
+            let alive = alive.clone();
+            std::thread::spawn(move || loop {
+                match write_while_alive(&mut *writer, &alive, 1, b"x") {
+                    Ok(()) => std::thread::yield_now(),
+                    Err(e) => return e,
+                }
+            })
+        };
+        // As the reader thread does: clear `alive` once output ends
+        let mut buf = [0u8; 64];
+        while reader.read(&mut buf).is_ok_and(|n| n > 0) {}
+        alive.store(false, Ordering::SeqCst);
+        let error = writes.join().unwrap();
+        assert!(
+            matches!(error, TerminalError::SessionExited(1)),
+            "write failed with {:?}",
+            error
+        );
+        child.wait().unwrap();
+    }
+
+    /// A session around `master` as `spawn_terminal` stores it, with every
+    /// optional feature off
+    fn test_session(
+        master: Box<dyn portable_pty::MasterPty + Send>,
+        child: &dyn portable_pty::Child,
+    ) -> PtySession {
+        let shared = SessionShared {
+            pid: child.process_id(),
+            ..SessionShared::new(uuid::Uuid::new_v4().to_string())
+        };
+        PtySession {
+            id: 1,
+            writer: Mutex::new(master.take_writer().unwrap()),
+            master: Mutex::new(master),
+            killer: child.clone_killer(),
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Our open descriptors on the PTY numbered `index`, going by the
+    /// `tty-index` Linux reports for PTY masters
+    #[cfg(target_os = "linux")]
+    fn master_fds(index: &str) -> Vec<String> {
+        let wanted = format!("tty-index:\t{}\n", index);
+        std::fs::read_dir("/proc/self/fd")
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|fd| {
+                std::fs::read_to_string(format!("/proc/self/fdinfo/{}", fd))
+                    .is_ok_and(|info| info.contains(&wanted))
+            })
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn dropping_a_session_releases_its_descriptors_and_child() {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("60");
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        let pid = child.process_id().unwrap();
+        let name = pair.master.as_raw_fd().and_then(termios::pts_name).unwrap();
+        let index = name.trim_start_matches("/dev/pts/");
+        drop(pair.slave);
+        let session = test_session(pair.master, &*child);
+        // The master and the writer's duplicate of it
+        let fds = master_fds(index);
+        assert!(fds.len() >= 2, "only {:?} open", fds);
+
+        drop(session);
+        // Another test may reuse the numbers, but not for the same PTY
+        let left = master_fds(index);
+        assert!(
+            fds.iter().all(|fd| !left.contains(fd)),
+            "{:?} still open",
+            left
+        );
+        let reason = exit::wait(pid, Duration::from_secs(5));
+        assert!(matches!(
+            reason,
+            Some(ExitReason::Signaled {
+                signal: libc::SIGHUP,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_duration_covers_the_time_the_shell_ran() {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("0.2");
+        let spawned_at = Instant::now();
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        let session = test_session(pair.master, &*child);
+        let reason = exit::wait(child.process_id().unwrap(), Duration::from_secs(5));
+        let exit = TerminalExit::new(1, &session.shared, reason, spawned_at);
+        assert_eq!(exit.exit_code, Some(0));
+        assert!(exit.duration_ms >= 200, "took {} ms", exit.duration_ms);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bytes_written_counts_the_accepted_payload() {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("60");
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        let session = test_session(pair.master, &*child);
+        let payload = "echo héllo\r".as_bytes();
+        session.write_input(1, payload).unwrap();
+        session.write_input(1, payload).unwrap();
+        let written = || session.shared.bytes_written.load(Ordering::Relaxed);
+        assert_eq!(written(), 2 * payload.len() as u64);
+
+        // Refused input isn't counted
+        session.shared.alive.store(false, Ordering::SeqCst);
+        assert!(session.write_input(1, payload).is_err());
+        assert_eq!(written(), 2 * payload.len() as u64);
+        // Dropping it no longer signals the child, as it seems to have exited
+        session.killer.clone_killer().kill().unwrap();
+        drop(session);
+        exit::wait(child.process_id().unwrap(), Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_exited_sessions_nobody_keeps_are_disposable() {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let cmd = CommandBuilder::new("true");
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        let session = test_session(pair.master, &*child);
+        let shared = &session.shared;
+        assert!(!is_disposable(shared));
+
+        shared.alive.store(false, Ordering::SeqCst);
+        assert!(is_disposable(shared));
+        // Still being reaped, then kept open until Enter is pressed
+        *shared.exit_behavior.lock() = ExitBehavior::Keep;
+        assert!(!is_disposable(shared));
+        shared.kept.store(true, Ordering::SeqCst);
+        assert!(!is_disposable(shared));
+        *shared.exit_behavior.lock() = ExitBehavior::Restart;
+        shared.kept.store(false, Ordering::SeqCst);
+        assert!(!is_disposable(shared));
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn grid_resizes_within_the_debounce_are_coalesced() {
+        let mut grid = GridResize::default();
+        let start = Instant::now();
+        assert_eq!(grid.request((80, 24), start), GridResizeStep::Now);
+
+        let soon = start + RESIZE_ALL_DEBOUNCE / 5;
+        let step = grid.request((100, 30), soon);
+        assert_eq!(step, GridResizeStep::Schedule(RESIZE_ALL_DEBOUNCE * 4 / 5));
+        let sooner = soon + RESIZE_ALL_DEBOUNCE / 5;
+        assert_eq!(grid.request((120, 40), sooner), GridResizeStep::Coalesced);
+
+        // Only the latest size is applied
+        let fired = start + RESIZE_ALL_DEBOUNCE;
+        assert_eq!(grid.fire(fired), Some((120, 40)));
+        let later = fired + RESIZE_ALL_DEBOUNCE;
+        assert_eq!(grid.request((90, 20), later), GridResizeStep::Now);
+    }
+
+    #[test]
+    fn a_grid_resize_while_one_is_scheduled_waits_for_it() {
+        let mut grid = GridResize::default();
+        let start = Instant::now();
+        grid.request((80, 24), start);
+        grid.request((100, 30), start);
+        // Past the debounce, but the scheduled resize hasn't run yet
+        let late = start + RESIZE_ALL_DEBOUNCE * 2;
+        assert_eq!(grid.request((120, 40), late), GridResizeStep::Coalesced);
+        assert_eq!(grid.fire(late), Some((120, 40)));
+    }
+
+    #[test]
+    fn spawn_options_are_read_in_camel_case() {
+        let options: SpawnOptions = serde_json::from_value(serde_json::json!({
+            "cols": 100,
+            "useHomeFallback": false,
+            "scrollbackCrHandling": "collapse",
+            "env": { "EDITOR": "vim" },
+        }))
+        .unwrap();
+        assert_eq!(options.cols, Some(100));
+        assert_eq!(options.rows, None);
+        assert_eq!(options.use_home_fallback, Some(false));
+        assert!(options.scrollback_cr_handling == CrHandling::Collapse);
+        let user_env = options.user_env.unwrap();
+        assert_eq!(user_env.get("EDITOR").map(String::as_str), Some("vim"));
+        assert!(options.env.is_empty());
+    }
+
+    #[test]
+    fn spawn_options_leave_internal_state_alone() {
+        let options: SpawnOptions = serde_json::from_value(serde_json::json!({
+            "parked": true,
+            "sessionId": 7,
+            "preload": [104, 105],
+        }))
+        .unwrap();
+        assert!(!options.parked);
+        assert_eq!(options.session_id, None);
+        assert!(options.preload.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reset_writes_the_sequence_to_the_pty_in_cooked_mode() {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new("/bin/sh");
+        // Raw mode as a crashed program might leave it, then print the line
+        // that arrives once it's cooked again
+        let script =
+            "stty raw -echo; echo ready; IFS= read -r line; printf '%s' \"$line\" | od -An -tx1";
+        cmd.args(["-c", script]);
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let session = test_session(pair.master, &*child);
+        let mut output = Vec::new();
+        let mut buf = [0u8; 256];
+        while !String::from_utf8_lossy(&output).contains("ready") {
+            let n = reader.read(&mut buf).unwrap();
+            output.extend_from_slice(&buf[..n]);
+        }
+
+        reset_pty(&session, 1, true).unwrap();
+        let fd = session.master.lock().as_raw_fd().unwrap();
+        let t = termios::get(fd).unwrap();
+        assert!(t.c_lflag & libc::ICANON != 0);
+        session.write_input(1, b"\n").unwrap();
+        output.clear();
+        let _ = reader.read_to_end(&mut output);
+        let hex: String = RESET_SEQUENCE
+            .bytes()
+            .map(|b| format!(" {:02x}", b))
+            .collect();
+        let output = String::from_utf8_lossy(&output).replace(['\r', '\n'], "");
+        assert!(output.contains(&hex), "{:?}", output);
+        child.wait().unwrap();
+    }
 }
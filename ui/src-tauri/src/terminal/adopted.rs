@@ -0,0 +1,234 @@
+// src-tauri/src/terminal/adopted.rs
+
+use parking_lot::Mutex;
+use portable_pty::{
+    Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, SlavePty,
+};
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::net::Shutdown;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+
+/// What an open descriptor refers to, the same for its duplicates
+#[derive(PartialEq, Eq)]
+pub struct Identity {
+    dev: u64,
+    ino: u64,
+    rdev: u64,
+    // PTY masters all have the same inode on Linux, but each its own slave
+    pts_name: Option<String>,
+}
+
+pub fn identity(fd: RawFd) -> io::Result<Identity> {
+    let stat = fstat(fd)?;
+    #[cfg(target_os = "linux")]
+    let pts_name = super::termios::pts_name(fd);
+    #[cfg(not(target_os = "linux"))]
+    let pts_name = None;
+    Ok(Identity {
+        dev: stat.st_dev as u64,
+        ino: stat.st_ino as u64,
+        rdev: stat.st_rdev as u64,
+        pts_name,
+    })
+}
+
+fn fstat(fd: RawFd) -> io::Result<libc::stat> {
+    // SAFETY: `stat` is plain data, filled in by the call
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    // SAFETY: only queries the descriptor
+    if unsafe { libc::fstat(fd, &mut stat) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+/// Stand in for a PTY pair with an open file descriptor, for `adopt_fd`:
+/// output is read from it and input written to it. Nothing can be spawned
+/// into it; the child `spawn_command` returns is only there to end the
+/// session, by waking the reader, when it's killed. Only terminals and
+/// sockets are taken, the byte streams a session makes sense for. Takes the
+/// descriptor over once it's been found valid, and puts it in blocking
+/// mode.
+pub fn pair(fd: RawFd, size: PtySize) -> io::Result<PtyPair> {
+    let is_socket = fstat(fd)?.st_mode & libc::S_IFMT == libc::S_IFSOCK;
+    // SAFETY: only queries the descriptor
+    if !is_socket && unsafe { libc::isatty(fd) } != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a terminal or socket",
+        ));
+    }
+    // SAFETY: only queries and sets the descriptor's flags
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: as above
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor is open, and the caller hands it over
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let (wake, woken) = UnixStream::pair()?;
+    let wake = Arc::new(wake);
+    Ok(PtyPair {
+        slave: Box::new(AdoptedSlave { wake: wake.clone() }),
+        master: Box::new(AdoptedMaster {
+            fd,
+            size: Mutex::new(size),
+            woken,
+        }),
+    })
+}
+
+struct AdoptedMaster {
+    fd: OwnedFd,
+    // What it was last resized to, as a descriptor that isn't a terminal
+    // has no size of its own
+    size: Mutex<PtySize>,
+    // Readable once the session is killed
+    woken: UnixStream,
+}
+
+impl AdoptedMaster {
+    fn is_tty(&self) -> bool {
+        // SAFETY: the descriptor is open for as long as `self`
+        unsafe { libc::isatty(self.fd.as_raw_fd()) == 1 }
+    }
+}
+
+impl MasterPty for AdoptedMaster {
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        if self.is_tty() {
+            let winsize = libc::winsize {
+                ws_row: size.rows,
+                ws_col: size.cols,
+                ws_xpixel: size.pixel_width,
+                ws_ypixel: size.pixel_height,
+            };
+            // SAFETY: `winsize` is valid for the call
+            let set = unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::TIOCSWINSZ as _, &winsize) };
+            if set != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        *self.size.lock() = size;
+        Ok(())
+    }
+
+    fn get_size(&self) -> anyhow::Result<PtySize> {
+        Ok(*self.size.lock())
+    }
+
+    fn try_clone_reader(&self) -> anyhow::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(AdoptedReader {
+            file: File::from(self.fd.try_clone()?),
+            woken: self.woken.try_clone()?,
+        }))
+    }
+
+    fn take_writer(&self) -> anyhow::Result<Box<dyn io::Write + Send>> {
+        Ok(Box::new(File::from(self.fd.try_clone()?)))
+    }
+
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        if !self.is_tty() {
+            return None;
+        }
+        // SAFETY: the descriptor is open for as long as `self`
+        let pgid = unsafe { libc::tcgetpgrp(self.fd.as_raw_fd()) };
+        (pgid > 0).then_some(pgid)
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        Some(self.fd.as_raw_fd())
+    }
+}
+
+/// Reads the descriptor until the session is killed, which a blocking read
+/// wouldn't notice
+struct AdoptedReader {
+    file: File,
+    woken: UnixStream,
+}
+
+impl Read for AdoptedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fds = [
+            libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.woken.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        loop {
+            // SAFETY: `fds` is valid for its length
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } != -1 {
+                break;
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+        if fds[1].revents != 0 {
+            return Ok(0);
+        }
+        self.file.read(buf)
+    }
+}
+
+struct AdoptedSlave {
+    wake: Arc<UnixStream>,
+}
+
+impl SlavePty for AdoptedSlave {
+    fn spawn_command(&self, _cmd: CommandBuilder) -> anyhow::Result<Box<dyn Child + Send + Sync>> {
+        Ok(Box::new(NoChild {
+            wake: self.wake.clone(),
+        }))
+    }
+}
+
+/// In place of the child of an adopted session, which has none
+#[derive(Debug)]
+struct NoChild {
+    wake: Arc<UnixStream>,
+}
+
+impl ChildKiller for NoChild {
+    fn kill(&mut self) -> io::Result<()> {
+        // Fails if shut down already, which is just as good
+        let _ = self.wake.shutdown(Shutdown::Both);
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(NoChild {
+            wake: self.wake.clone(),
+        })
+    }
+}
+
+impl Child for NoChild {
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Ok(Some(ExitStatus::with_exit_code(0)))
+    }
+
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        Ok(ExitStatus::with_exit_code(0))
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
+}
@@ -0,0 +1,127 @@
+// src-tauri/src/terminal/ansi.rs
+
+/// A piece of PTY output: plain text or one whole escape sequence
+pub enum Segment<'a> {
+    Text(&'a [u8]),
+    Escape(&'a [u8]),
+    /// An escape sequence cut off by the end of the data
+    Incomplete(&'a [u8]),
+}
+
+/// Split output into text runs and escape sequences
+pub fn segments(data: &[u8]) -> Segments<'_> {
+    Segments { data }
+}
+
+pub struct Segments<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Segment<'a>> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let (segment, len) = if self.data[0] == 0x1b {
+            match escape_len(self.data) {
+                Some(len) => (Segment::Escape(&self.data[..len]), len),
+                None => (Segment::Incomplete(self.data), self.data.len()),
+            }
+        } else {
+            let len = self
+                .data
+                .iter()
+                .position(|&b| b == 0x1b)
+                .unwrap_or(self.data.len());
+            (Segment::Text(&self.data[..len]), len)
+        };
+        self.data = &self.data[len..];
+        Some(segment)
+    }
+}
+
+/// Which control characters `strip_with` keeps along with the text
+#[derive(Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlCharPolicy {
+    /// Line feeds only, so lines survive but nothing else does
+    Printable,
+    /// Tabs, line feeds, form feeds and carriage returns
+    #[default]
+    Whitespace,
+    /// Every C0 control (other than ESC starting a sequence) and DEL
+    AllC0,
+}
+
+impl ControlCharPolicy {
+    fn keeps(self, byte: u8) -> bool {
+        if !byte.is_ascii_control() {
+            return true;
+        }
+        match self {
+            Self::Printable => byte == b'\n',
+            Self::Whitespace => byte.is_ascii_whitespace(),
+            Self::AllC0 => true,
+        }
+    }
+}
+
+/// Output with all escape sequences removed
+pub fn strip(data: &[u8]) -> Vec<u8> {
+    strip_with(data, ControlCharPolicy::AllC0)
+}
+
+/// Output with all escape sequences and the control characters `policy`
+/// doesn't keep removed
+pub fn strip_with(data: &[u8], policy: ControlCharPolicy) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for segment in segments(data) {
+        if let Segment::Text(text) = segment {
+            out.extend(text.iter().filter(|&&b| policy.keeps(b)));
+        }
+    }
+    out
+}
+
+/// Length of the escape sequence at the start of `data` (which must begin
+/// with ESC), or `None` if the data ends before the sequence does
+fn escape_len(data: &[u8]) -> Option<usize> {
+    match *data.get(1)? {
+        // CSI: parameters and intermediates, then a final byte
+        b'[' => {
+            for (i, &b) in data.iter().enumerate().skip(2) {
+                match b {
+                    0x20..=0x3f => continue,
+                    0x40..=0x7e => return Some(i + 1),
+                    // Malformed; end the sequence before the stray byte
+                    _ => return Some(i),
+                }
+            }
+            None
+        }
+        // OSC, DCS, SOS, PM, APC: a string terminated by BEL or ST
+        b']' | b'P' | b'X' | b'^' | b'_' => {
+            for i in 2..data.len() {
+                match data[i] {
+                    0x07 => return Some(i + 1),
+                    0x1b if data.get(i + 1) == Some(&b'\\') => return Some(i + 2),
+                    0x1b if i + 1 == data.len() => return None,
+                    _ => {}
+                }
+            }
+            None
+        }
+        // nF sequences: intermediates, then a final byte
+        0x20..=0x2f => {
+            for (i, &b) in data.iter().enumerate().skip(2) {
+                if !(0x20..=0x2f).contains(&b) {
+                    return Some(i + 1);
+                }
+            }
+            None
+        }
+        _ => Some(2),
+    }
+}
@@ -0,0 +1,56 @@
+// src-tauri/src/terminal/burst.rs
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A change reported by `BurstDetector::record`
+pub enum BurstChange {
+    /// This many bytes arrived within the window
+    Started(u64),
+    /// The rate fell back, with this many bytes left in the window
+    Ended(u64),
+}
+
+/// Spots output arriving faster than `set_output_burst_threshold` allows,
+/// over a sliding window. A burst ends once the window holds less than half
+/// the threshold, so output hovering around it doesn't flap.
+#[derive(Default)]
+pub struct BurstDetector {
+    // Arrival time and size of each read still in the window
+    reads: VecDeque<(Instant, usize)>,
+    bytes: u64,
+    bursting: bool,
+}
+
+impl BurstDetector {
+    /// Note `n` bytes arriving now (0 just to let the window slide), with a
+    /// `threshold` of 0 meaning detection is off
+    pub fn record(&mut self, n: usize, threshold: u64, window: Duration) -> Option<BurstChange> {
+        let now = Instant::now();
+        if n > 0 {
+            self.reads.push_back((now, n));
+            self.bytes += n as u64;
+        }
+        while let Some(&(at, size)) = self.reads.front() {
+            if now.duration_since(at) <= window {
+                break;
+            }
+            self.reads.pop_front();
+            self.bytes -= size as u64;
+        }
+
+        if !self.bursting && threshold > 0 && self.bytes > threshold {
+            self.bursting = true;
+            Some(BurstChange::Started(self.bytes))
+        } else if self.bursting && (threshold == 0 || self.bytes < threshold / 2) {
+            self.bursting = false;
+            Some(BurstChange::Ended(self.bytes))
+        } else {
+            None
+        }
+    }
+
+    pub fn bursting(&self) -> bool {
+        self.bursting
+    }
+}
@@ -0,0 +1,192 @@
+// src-tauri/src/terminal/cgroup.rs
+
+use super::TerminalError;
+#[cfg(target_os = "linux")]
+use super::LOG_TARGET;
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+/// Resource limits for a session's process tree, see `spawn_terminal`
+#[derive(Clone, serde::Deserialize)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct CgroupLimits {
+    /// CPU time as a number of CPUs, e.g. 0.5 for half of one
+    cpus: Option<f64>,
+    /// Memory limit in bytes, beyond which the kernel reclaims and finally
+    /// OOM-kills within the session
+    memory_max: Option<u64>,
+}
+
+impl CgroupLimits {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn validate(&self) -> Result<(), TerminalError> {
+        if let Some(cpus) = self.cpus {
+            if !(cpus.is_finite() && cpus > 0.0) {
+                return Err(TerminalError::InvalidArgument(format!(
+                    "Invalid CPU limit: {}",
+                    cpus
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Leaf the app moves itself into, since a cgroup with processes of its
+/// own can't pass controllers on to child cgroups
+#[cfg(target_os = "linux")]
+const APP_LEAF: &str = "karpi-app";
+
+#[cfg(target_os = "linux")]
+const CPU_PERIOD_US: u64 = 100_000;
+
+#[cfg(target_os = "linux")]
+static CGROUP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A transient cgroup v2 holding one session's processes. Dropping it
+/// kills whatever is still inside and removes it.
+#[cfg(target_os = "linux")]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+impl Cgroup {
+    /// Create a cgroup with `limits` under the app's own cgroup, which
+    /// must be writable by the app's user (e.g. a systemd unit with
+    /// `Delegate=yes`)
+    pub fn create(limits: &CgroupLimits) -> Result<Self, TerminalError> {
+        limits.validate()?;
+        let parent = parent_cgroup()?;
+        let mut controllers = Vec::new();
+        if limits.cpus.is_some() {
+            controllers.push("+cpu");
+        }
+        if limits.memory_max.is_some() {
+            controllers.push("+memory");
+        }
+        if !controllers.is_empty() {
+            enable_controllers(&parent, &controllers.join(" "))?;
+        }
+
+        let id = CGROUP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = parent.join(format!("karpi-{}-{}", std::process::id(), id));
+        std::fs::create_dir(&path).map_err(|e| setup_error("create a cgroup", e))?;
+        // From here on, dropping it removes the directory again
+        let cgroup = Self { path };
+        if let Some(cpus) = limits.cpus {
+            let quota = ((cpus * CPU_PERIOD_US as f64) as u64).max(1000);
+            cgroup.write("cpu.max", &format!("{} {}", quota, CPU_PERIOD_US))?;
+        }
+        if let Some(bytes) = limits.memory_max {
+            cgroup.write("memory.max", &bytes.to_string())?;
+        }
+        Ok(cgroup)
+    }
+
+    /// Move a process into the cgroup; its future children follow
+    pub fn add(&self, pid: u32) -> Result<(), TerminalError> {
+        self.write("cgroup.procs", &pid.to_string())
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), TerminalError> {
+        std::fs::write(self.path.join(file), value)
+            .map_err(|e| setup_error(&format!("write {}", file), e))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // A cgroup can only be removed once it's empty. `cgroup.kill`
+        // needs Linux 5.14; on older kernels leftovers keep it around.
+        let _ = std::fs::write(self.path.join("cgroup.kill"), "1");
+        for _ in 0..50 {
+            match std::fs::remove_dir(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                _ => return,
+            }
+        }
+        log::warn!(
+            target: LOG_TARGET,
+            "Failed to remove cgroup {}", self.path.display()
+        );
+    }
+}
+
+/// Whether `Cgroup::create` can work here: cgroups v2 is mounted and the
+/// app's user may enable controllers in its cgroup. Only checks, without
+/// writing anything.
+#[cfg(target_os = "linux")]
+pub fn delegated() -> bool {
+    parent_cgroup().is_ok_and(|parent| {
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(parent.join("cgroup.subtree_control"))
+            .is_ok()
+    })
+}
+
+/// The cgroup session cgroups are created in: the one the app was
+/// started in
+#[cfg(target_os = "linux")]
+fn parent_cgroup() -> Result<PathBuf, TerminalError> {
+    let unavailable = || TerminalError::Unsupported("cgroups v2 isn't available".to_string());
+    if !Path::new(CGROUP_ROOT).join("cgroup.controllers").exists() {
+        return Err(unavailable());
+    }
+    // The unified hierarchy's entry reads `0::/path`
+    let membership = std::fs::read_to_string("/proc/self/cgroup").map_err(|_| unavailable())?;
+    let own = membership
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(unavailable)?;
+    let own = Path::new(CGROUP_ROOT).join(own.trim_start_matches('/'));
+    match own.file_name() {
+        Some(name) if name == APP_LEAF => Ok(own.parent().unwrap_or(&own).to_path_buf()),
+        _ => Ok(own),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn enable_controllers(parent: &Path, controllers: &str) -> Result<(), TerminalError> {
+    let subtree_control = parent.join("cgroup.subtree_control");
+    match std::fs::write(&subtree_control, controllers) {
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+            // The app still lives in `parent` itself
+            let leaf = parent.join(APP_LEAF);
+            if let Err(e) = std::fs::create_dir(&leaf) {
+                if e.kind() != io::ErrorKind::AlreadyExists {
+                    return Err(setup_error("create a cgroup", e));
+                }
+            }
+            std::fs::write(leaf.join("cgroup.procs"), std::process::id().to_string())
+                .map_err(|e| setup_error("move the app into its own cgroup", e))?;
+            std::fs::write(&subtree_control, controllers)
+                .map_err(|e| setup_error("enable cgroup controllers", e))
+        }
+        result => result.map_err(|e| setup_error("enable cgroup controllers", e)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn setup_error(what: &str, e: io::Error) -> TerminalError {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => TerminalError::PermissionDenied(format!(
+            "Not permitted to {} (the app's cgroup must be delegated to its user): {}",
+            what, e
+        )),
+        _ => TerminalError::Unsupported(format!("Failed to {}: {}", what, e)),
+    }
+}
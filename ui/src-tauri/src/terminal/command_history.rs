@@ -0,0 +1,173 @@
+// src-tauri/src/terminal/command_history.rs
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Commands kept per session unless `set_command_history_depth` says
+/// otherwise
+pub const DEFAULT_HISTORY_DEPTH: usize = 20;
+
+/// Largest depth `set_command_history_depth` accepts
+pub const MAX_HISTORY_DEPTH: usize = 1000;
+
+/// A command the shell ran, going by its shell integration marks
+#[derive(Clone, serde::Serialize)]
+pub struct CommandRecord {
+    /// The command line, if the shell reported it
+    pub command: Option<String>,
+    /// From the command finished mark (OSC 133 D), if it carried one
+    pub exit_code: Option<i32>,
+    /// From the command starting (OSC 133 C) to it finishing
+    pub duration_ms: u64,
+}
+
+struct RunningCommand {
+    command: Option<String>,
+    started: Instant,
+}
+
+/// The last commands a session's shell ran, oldest first
+pub struct CommandHistory {
+    depth: usize,
+    records: VecDeque<CommandRecord>,
+    running: Option<RunningCommand>,
+    // Reported ahead of the command starting, as VS Code's marks do
+    next_command: Option<String>,
+}
+
+impl Default for CommandHistory {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_HISTORY_DEPTH,
+            records: VecDeque::new(),
+            running: None,
+            next_command: None,
+        }
+    }
+}
+
+impl CommandHistory {
+    /// The shell reported the command line, before or after it started
+    pub fn set_command(&mut self, command: String) {
+        match &mut self.running {
+            Some(running) if running.command.is_none() => running.command = Some(command),
+            _ => self.next_command = Some(command),
+        }
+    }
+
+    /// A command started (OSC 133 C)
+    pub fn started(&mut self, command: Option<String>) {
+        let command = command.or_else(|| self.next_command.take());
+        self.running = Some(RunningCommand {
+            command,
+            started: Instant::now(),
+        });
+    }
+
+    /// The running command finished (OSC 133 D). Ignored if none started,
+    /// as shells mark every prompt, empty command lines included.
+    pub fn finished(&mut self, exit_code: Option<i32>) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        if self.depth == 0 {
+            return;
+        }
+        if self.records.len() == self.depth {
+            self.records.pop_front();
+        }
+        self.records.push_back(CommandRecord {
+            command: running.command,
+            exit_code,
+            duration_ms: running.started.elapsed().as_millis() as u64,
+        });
+    }
+
+    /// A prompt was drawn (OSC 133 A): a command the shell didn't mark
+    /// finished is recorded without an exit code
+    pub fn prompt(&mut self) {
+        self.finished(None);
+        self.next_command = None;
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        let excess = self.records.len().saturating_sub(depth);
+        self.records.drain(..excess);
+    }
+
+    /// When the command the shell marked running started, if one is
+    pub fn running_since(&self) -> Option<Instant> {
+        self.running.as_ref().map(|running| running.started)
+    }
+
+    pub fn last(&self) -> Option<&CommandRecord> {
+        self.records.back()
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &CommandRecord> {
+        self.records.iter()
+    }
+}
+
+/// The exit code in the options of an OSC 133 D mark, e.g. `;0` or
+/// `;1;aid=...`
+pub fn exit_code_in(options: &[u8]) -> Option<i32> {
+    let options = options.strip_prefix(b";")?;
+    let code = options.split(|&b| b == b';').next()?;
+    std::str::from_utf8(code).ok()?.parse().ok()
+}
+
+/// The command line in the options of an OSC 133 C mark, as kitty's
+/// `cmdline_url=` sends it, percent-encoded
+pub fn command_in(options: &[u8]) -> Option<String> {
+    let encoded = options
+        .split(|&b| b == b';')
+        .find_map(|option| option.strip_prefix(b"cmdline_url="))
+        .filter(|encoded| !encoded.is_empty())?;
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (encoded[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// The command line of VS Code's OSC 633 E mark, which escapes backslashes
+/// and control characters as `\\` and `\xHH`
+pub fn unescape_vscode(text: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == b'\\' && text.get(i + 1) == Some(&b'\\') {
+            decoded.push(b'\\');
+            i += 2;
+            continue;
+        }
+        if text[i] == b'\\' && text.get(i + 1) == Some(&b'x') {
+            let hex = text
+                .get(i + 2..i + 4)
+                .and_then(|h| std::str::from_utf8(h).ok());
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                decoded.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        decoded.push(text[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
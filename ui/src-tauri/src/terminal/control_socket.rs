@@ -0,0 +1,179 @@
+// src-tauri/src/terminal/control_socket.rs
+
+use parking_lot::Mutex;
+use std::fs::{self, DirBuilder};
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A client that stops reading is dropped rather than stalling output
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A Unix domain socket exposing a session to external tools: bytes read
+/// from clients are written to the PTY, and PTY output is mirrored to every
+/// connected client.
+pub struct ControlSocket {
+    path: PathBuf,
+    clients: Mutex<Vec<UnixStream>>,
+    closed: AtomicBool,
+}
+
+impl ControlSocket {
+    /// Bind the socket, readable and writable by the current user only.
+    /// Fails if something already exists at `path`.
+    ///
+    /// It's bound in a directory only the current user can enter, and
+    /// linked to `path` once its permissions are set, so no one else can
+    /// connect while it still has the umask's.
+    pub fn bind(path: &str) -> io::Result<(Arc<Self>, UnixListener)> {
+        static NEXT_DIR: AtomicU32 = AtomicU32::new(0);
+        let parent = match Path::new(path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let dir = parent.join(format!(
+            ".karpi-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let bound = dir.join("s");
+        let listener = UnixListener::bind(&bound).and_then(|listener| {
+            fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+            fs::hard_link(&bound, path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_file(&bound);
+        let _ = fs::remove_dir(&dir);
+        let listener = listener?;
+        let socket = Arc::new(Self {
+            path: PathBuf::from(path),
+            clients: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+        });
+        Ok((socket, listener))
+    }
+
+    /// Accept clients on a background thread, passing their input to `sink`
+    pub fn serve<F>(self: &Arc<Self>, listener: UnixListener, sink: F)
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        let socket = self.clone();
+        let sink = Arc::new(sink);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if socket.closed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let Ok(mut input) = stream.try_clone() else {
+                    continue;
+                };
+                let _ = stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT));
+                socket.clients.lock().push(stream);
+
+                let sink = sink.clone();
+                thread::spawn(move || {
+                    let mut buf = [0u8; 4096];
+                    while let Ok(n) = input.read(&mut buf) {
+                        if n == 0 || !sink(&buf[..n]) {
+                            break;
+                        }
+                    }
+                    let _ = input.shutdown(Shutdown::Both);
+                });
+            }
+        });
+    }
+
+    /// Send output to every connected client, dropping those that fail
+    pub fn mirror(&self, data: &[u8]) {
+        self.clients
+            .lock()
+            .retain_mut(|client| client.write_all(data).is_ok());
+    }
+
+    /// Disconnect all clients, stop accepting and remove the socket file
+    pub fn close(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for client in self.clients.lock().drain(..) {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+        // Wake the accept loop so it sees `closed`
+        let _ = UnixStream::connect(&self.path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Closing on drop removes the file when the session fails to spawn after
+// binding: until `serve` holds on to the socket, nothing else would
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("karpi-{}-{}.sock", name, std::process::id()))
+    }
+
+    #[test]
+    fn dropping_before_serve_removes_the_file() {
+        let path = socket_path("drop");
+        let path_str = path.to_str().unwrap();
+        let bound = ControlSocket::bind(path_str).unwrap();
+        assert!(path.exists());
+        drop(bound);
+        assert!(!path.exists());
+        // Binding the same path again works, as nothing was left behind
+        let bound = ControlSocket::bind(path_str).unwrap();
+        drop(bound);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn bound_socket_is_private_and_reachable() {
+        let path = socket_path("private");
+        let path_str = path.to_str().unwrap();
+        let (socket, listener) = ControlSocket::bind(path_str).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Nothing is left of the directory it was bound in
+        let parent = path.parent().unwrap();
+        let prefix = format!(".karpi-{}-", std::process::id());
+        let leftover = fs::read_dir(parent)
+            .unwrap()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix));
+        assert!(!leftover);
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        let (mut accepted, _) = listener.accept().unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        accepted.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        drop(socket);
+    }
+
+    #[test]
+    fn binding_over_an_existing_file_fails() {
+        let path = socket_path("existing");
+        fs::write(&path, b"").unwrap();
+        assert!(ControlSocket::bind(path.to_str().unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+// src-tauri/src/terminal/debug_bundle.rs
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Replaces the values of variables that look like secrets
+pub const REDACTED: &str = "<redacted>";
+
+/// Parts of variable names that suggest a secret value, matched
+/// case-insensitively
+const SECRET_KEY_PARTS: [&str; 9] = [
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "AUTH",
+    "COOKIE",
+    "PRIVATE",
+    "API_KEY",
+];
+
+/// A directory of diagnostics files, see `create_debug_bundle`
+pub struct DebugBundle {
+    dir: PathBuf,
+}
+
+impl DebugBundle {
+    /// Create the directory at `path`, which mustn't exist yet, readable by
+    /// the current user only
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(path)?;
+        Ok(Self {
+            dir: path.to_path_buf(),
+        })
+    }
+
+    pub fn write(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::write(self.dir.join(name), data)
+    }
+
+    pub fn write_json<T: serde::Serialize>(&self, name: &str, value: &T) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(value)?;
+        self.write(name, &json)
+    }
+}
+
+/// The variables with the values of those whose names look like secrets
+/// replaced, unless `include_secrets`
+pub fn redact_env(
+    vars: impl Iterator<Item = (String, String)>,
+    include_secrets: bool,
+) -> BTreeMap<String, String> {
+    vars.map(|(key, value)| {
+        if !include_secrets && is_secret_key(&key) {
+            (key, REDACTED.to_string())
+        } else {
+            (key, value)
+        }
+    })
+    .collect()
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    SECRET_KEY_PARTS.iter().any(|part| key.contains(part)) || key.ends_with("_KEY")
+}
+
+/// The last `max_lines` lines of the file at `path`
+pub fn tail_lines(path: &Path, max_lines: usize) -> io::Result<String> {
+    let text = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&text);
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines.len().saturating_sub(max_lines);
+    let mut tail = lines[start..].join("\n");
+    tail.push('\n');
+    Ok(tail)
+}
@@ -0,0 +1,29 @@
+// src-tauri/src/terminal/device_attributes.rs
+
+use std::ops::Range;
+
+/// Primary Device Attributes request (DA1)
+pub const PRIMARY_DA_QUERY: &[u8] = b"\x1b[c";
+
+/// Where a DA1 response (`CSI ? Ps ; ... c`) is in a chunk of input, if it
+/// holds a whole one
+pub fn find_response(data: &[u8]) -> Option<Range<usize>> {
+    let mut from = 0;
+    while let Some(i) = find(&data[from..], b"\x1b[?") {
+        let start = from + i;
+        let params = start + 3;
+        let len = data[params..]
+            .iter()
+            .take_while(|&&b| b.is_ascii_digit() || b == b';')
+            .count();
+        if data.get(params + len) == Some(&b'c') {
+            return Some(start..params + len + 1);
+        }
+        from = params;
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
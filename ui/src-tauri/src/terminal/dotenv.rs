@@ -0,0 +1,120 @@
+// src-tauri/src/terminal/dotenv.rs
+
+/// Parse a dotenv file: `KEY=VALUE` lines, optionally prefixed with
+/// `export`, with `#` comments and blank lines ignored. Values may be
+/// unquoted (ending at a ` #` comment), single-quoted (taken literally) or
+/// double-quoted (with `\n`, `\t`, `\"`, `\\` and `\$` escapes), and quoted
+/// values may span lines. Errors name the line they're on.
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected KEY=VALUE", number));
+        };
+        let key = key.trim_end();
+        if !is_valid_key(key) {
+            return Err(format!("line {}: invalid variable name {:?}", number, key));
+        }
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                let mut quoted = value[1..].to_string();
+                let (value, rest) = loop {
+                    if let Some(end) = closing_quote(&quoted, quote) {
+                        break (quoted[..end].to_string(), quoted[end + 1..].to_string());
+                    }
+                    let Some((_, next)) = lines.next() else {
+                        return Err(format!("line {}: unterminated quoted value", number));
+                    };
+                    quoted.push('\n');
+                    quoted.push_str(next);
+                };
+                let rest = rest.trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!(
+                        "line {}: unexpected text after quoted value",
+                        number
+                    ));
+                }
+                if quote == '"' {
+                    unescape(&value)
+                } else {
+                    value
+                }
+            }
+            _ => match value.find(" #") {
+                Some(comment) => value[..comment].trim_end().to_string(),
+                None => value.trim_end().to_string(),
+            },
+        };
+        if value
+            .chars()
+            .any(|c| c.is_control() && c != '\n' && c != '\t')
+        {
+            return Err(format!(
+                "line {}: value of {} has control characters",
+                number, key
+            ));
+        }
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Byte offset of the quote ending a value that started with `quote`,
+/// skipping escaped ones in double quotes
+fn closing_quote(s: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c @ ('"' | '\\' | '$')) => out.push(c),
+            // Unknown escapes are kept as written
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Quote `value` for a POSIX shell
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,222 @@
+// src-tauri/src/terminal/encoding.rs
+
+use super::TerminalError;
+use encoding_rs::{Decoder, DecoderResult, Encoding, UTF_8};
+
+/// Name accepted by `set_text_encoding` for undecoded byte emission
+pub const RAW: &str = "raw";
+
+/// With `set_auto_binary`, a chunk of output is taken for binary once at
+/// least this many of its bytes can't be decoded...
+const AUTO_BINARY_MIN_INVALID: usize = 4;
+/// ... making up at least one in this many of its bytes
+const AUTO_BINARY_RATIO: usize = 16;
+
+/// A chunk of output decoded by `OutputDecoder::decode`
+pub enum Decoded {
+    Text(String),
+    /// To be emitted undecoded, in raw mode or because the chunk looks
+    /// binary. `detected` if it's the first to look binary after text.
+    Binary {
+        detected: bool,
+    },
+}
+
+/// Turns a session's output bytes into text for the frontend, in the
+/// session's configured encoding (UTF-8 unless changed)
+pub struct OutputDecoder {
+    // `None` when the frontend decodes raw bytes itself
+    encoding: Option<&'static Encoding>,
+    // Streaming state, so a character split across reads decodes intact
+    decoder: Decoder,
+    // The last chunk looked binary, see `set_auto_binary`
+    binary: bool,
+}
+
+impl Default for OutputDecoder {
+    fn default() -> Self {
+        Self::new(Some(UTF_8))
+    }
+}
+
+impl OutputDecoder {
+    fn new(encoding: Option<&'static Encoding>) -> Self {
+        Self {
+            encoding,
+            decoder: encoding.unwrap_or(UTF_8).new_decoder_without_bom_handling(),
+            binary: false,
+        }
+    }
+
+    /// Parse an encoding label (`utf-8`, `latin1`, `shift_jis`, ... or `raw`)
+    pub fn from_label(label: &str) -> Result<Self, TerminalError> {
+        if label.eq_ignore_ascii_case(RAW) {
+            return Ok(Self::new(None));
+        }
+        Encoding::for_label(label.as_bytes())
+            .map(|encoding| Self::new(Some(encoding)))
+            .ok_or_else(|| TerminalError::InvalidArgument(format!("Unknown encoding: {}", label)))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.map_or(RAW, |encoding| encoding.name())
+    }
+
+    /// Decode the next chunk of the stream, replacing invalid sequences.
+    /// With `auto_binary`, a chunk with too many of them is left undecoded
+    /// instead.
+    pub fn decode(&mut self, data: &[u8], auto_binary: bool) -> Decoded {
+        let Some(encoding) = self.encoding else {
+            return Decoded::Binary { detected: false };
+        };
+        let mut text = String::with_capacity(
+            self.decoder
+                .max_utf8_buffer_length(data.len())
+                .unwrap_or(data.len() * 3),
+        );
+        let mut invalid = 0;
+        let mut rest = data;
+        loop {
+            let (result, read) = self
+                .decoder
+                .decode_to_string_without_replacement(rest, &mut text, false);
+            rest = &rest[read..];
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => text.reserve(rest.len() * 3 + 4),
+                DecoderResult::Malformed(bad, _) => {
+                    invalid += bad as usize;
+                    text.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+        }
+
+        let binary = auto_binary
+            && invalid >= AUTO_BINARY_MIN_INVALID
+            && invalid * AUTO_BINARY_RATIO >= data.len();
+        if !binary {
+            self.binary = false;
+            return Decoded::Text(text);
+        }
+        // The whole chunk goes out undecoded, so a character it ends in the
+        // middle of mustn't carry over into the next one
+        self.decoder = encoding.new_decoder_without_bom_handling();
+        let detected = !self.binary;
+        self.binary = true;
+        Decoded::Binary { detected }
+    }
+
+    /// Decode a standalone buffer such as a scrollback snapshot. Raw mode
+    /// only affects live emission, so snapshots fall back to UTF-8 there.
+    pub fn decode_snapshot(&self, data: &[u8]) -> String {
+        let (text, _) = self
+            .encoding
+            .unwrap_or(UTF_8)
+            .decode_without_bom_handling(data);
+        text.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(decoded: Decoded) -> String {
+        match decoded {
+            Decoded::Text(text) => text,
+            Decoded::Binary { .. } => panic!("decoded as binary"),
+        }
+    }
+
+    #[test]
+    fn latin1_round_trips() {
+        let mut decoder = OutputDecoder::from_label("latin1").unwrap();
+        let bytes: Vec<u8> = (0x20..=0xff).collect();
+        let decoded = text(decoder.decode(&bytes, false));
+        let (encoded, _, unmappable) = decoder.encoding.unwrap().encode(&decoded);
+        assert!(!unmappable);
+        assert_eq!(encoded, bytes);
+    }
+
+    #[test]
+    fn latin1_decodes_accented_letters() {
+        let mut decoder = OutputDecoder::from_label("latin1").unwrap();
+        let decoded = text(decoder.decode(b"caf\xe9 na\xefve", false));
+        assert_eq!(decoded, "café naïve");
+        assert_eq!(decoder.decode_snapshot(b"\xe0 la carte"), "à la carte");
+    }
+
+    #[test]
+    fn utf8_character_split_across_chunks_decodes_intact() {
+        let mut decoder = OutputDecoder::default();
+        let bytes = "é".as_bytes();
+        assert_eq!(text(decoder.decode(&bytes[..1], false)), "");
+        assert_eq!(text(decoder.decode(&bytes[1..], false)), "é");
+    }
+
+    #[test]
+    fn raw_mode_leaves_output_undecoded() {
+        let mut decoder = OutputDecoder::from_label("raw").unwrap();
+        assert_eq!(decoder.name(), RAW);
+        assert!(matches!(
+            decoder.decode(b"hello", false),
+            Decoded::Binary { detected: false }
+        ));
+    }
+
+    /// `len` bytes of which the last `invalid` aren't valid UTF-8
+    fn chunk(len: usize, invalid: usize) -> Vec<u8> {
+        let mut data = vec![b'a'; len - invalid];
+        data.resize(len, 0xff);
+        data
+    }
+
+    #[test]
+    fn too_few_invalid_bytes_stay_text() {
+        let mut decoder = OutputDecoder::default();
+        let data = chunk(AUTO_BINARY_MIN_INVALID - 1, AUTO_BINARY_MIN_INVALID - 1);
+        assert!(matches!(decoder.decode(&data, true), Decoded::Text(_)));
+    }
+
+    #[test]
+    fn auto_binary_switches_at_the_ratio() {
+        let mut decoder = OutputDecoder::default();
+        let at_ratio = AUTO_BINARY_MIN_INVALID * AUTO_BINARY_RATIO;
+        let data = chunk(at_ratio, AUTO_BINARY_MIN_INVALID);
+        assert!(matches!(
+            decoder.decode(&data, true),
+            Decoded::Binary { detected: true }
+        ));
+        let mut decoder = OutputDecoder::default();
+        let data = chunk(at_ratio + 1, AUTO_BINARY_MIN_INVALID);
+        let decoded = text(decoder.decode(&data, true));
+        let replaced = decoded.matches(char::REPLACEMENT_CHARACTER).count();
+        assert_eq!(replaced, AUTO_BINARY_MIN_INVALID);
+    }
+
+    #[test]
+    fn binary_is_detected_once_per_switch_over() {
+        let mut decoder = OutputDecoder::default();
+        let binary = chunk(16, 16);
+        assert!(matches!(
+            decoder.decode(&binary, true),
+            Decoded::Binary { detected: true }
+        ));
+        assert!(matches!(
+            decoder.decode(&binary, true),
+            Decoded::Binary { detected: false }
+        ));
+        assert_eq!(text(decoder.decode(b"text again", true)), "text again");
+        assert!(matches!(
+            decoder.decode(&binary, true),
+            Decoded::Binary { detected: true }
+        ));
+    }
+
+    #[test]
+    fn without_auto_binary_invalid_bytes_are_replaced() {
+        let mut decoder = OutputDecoder::default();
+        let decoded = text(decoder.decode(&chunk(16, 16), false));
+        assert_eq!(decoded, "\u{fffd}".repeat(16));
+    }
+}
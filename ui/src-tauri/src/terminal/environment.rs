@@ -0,0 +1,84 @@
+// src-tauri/src/terminal/environment.rs
+
+use std::collections::HashMap;
+
+/// Check variables against what the platform allows in an environment,
+/// with surrounding whitespace trimmed off names. Names must not be empty
+/// or contain `=` or NUL, and values must not contain NUL; unlike
+/// `dotenv::parse` any other name is fine, as programs other than shells
+/// use names such as `foo.bar`. On failure, one message for each variable
+/// that's wrong, in order of name.
+pub fn normalize(env: HashMap<String, String>) -> Result<HashMap<String, String>, Vec<String>> {
+    let mut vars: Vec<(String, String)> = env.into_iter().collect();
+    vars.sort();
+    let mut normalized = HashMap::with_capacity(vars.len());
+    let mut problems = Vec::new();
+    for (key, value) in vars {
+        let name = key.trim();
+        let problem = if name.is_empty() {
+            Some("name is empty")
+        } else if name.contains('=') {
+            Some("name contains '='")
+        } else if name.contains('\0') {
+            Some("name contains NUL")
+        } else if value.contains('\0') {
+            Some("value contains NUL")
+        } else if normalized.contains_key(name) {
+            Some("name is given twice")
+        } else {
+            None
+        };
+        match problem {
+            Some(problem) => problems.push(format!("{:?}: {}", key, problem)),
+            None => {
+                normalized.insert(name.to_string(), value);
+            }
+        }
+    }
+    if problems.is_empty() {
+        Ok(normalized)
+    } else {
+        Err(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn names_are_trimmed() {
+        let normalized = normalize(env(&[(" FOO ", "bar"), ("BAZ", " kept ")])).unwrap();
+        assert_eq!(normalized, env(&[("FOO", "bar"), ("BAZ", " kept ")]));
+    }
+
+    #[test]
+    fn names_with_equals_sign_are_rejected() {
+        let problems = normalize(env(&[("A=B", "1"), ("OK", "2")])).unwrap_err();
+        assert_eq!(problems, vec![r#""A=B": name contains '='"#]);
+    }
+
+    #[test]
+    fn nul_in_names_and_values_is_rejected() {
+        let problems = normalize(env(&[("A\0B", "1"), ("C", "x\0y")])).unwrap_err();
+        assert_eq!(
+            problems,
+            vec![r#""A\0B": name contains NUL"#, r#""C": value contains NUL"#]
+        );
+    }
+
+    #[test]
+    fn empty_and_duplicate_names_are_rejected() {
+        let problems = normalize(env(&[(" ", "1"), ("X", "2"), ("X ", "3")])).unwrap_err();
+        assert_eq!(
+            problems,
+            vec![r#"" ": name is empty"#, r#""X ": name is given twice"#]
+        );
+    }
+}
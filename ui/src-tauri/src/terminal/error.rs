@@ -0,0 +1,52 @@
+// src-tauri/src/terminal/error.rs
+
+use std::fmt;
+
+/// Errors returned by the terminal commands.
+///
+/// Serialized as its display string so the frontend keeps receiving plain
+/// error messages from `invoke`.
+#[derive(Debug)]
+pub enum TerminalError {
+    /// No session with this id is registered
+    NotFound(u32),
+    /// The session's child has exited and it is about to be cleaned up
+    SessionExited(u32),
+    /// A command argument was rejected
+    InvalidArgument(String),
+    /// The `TerminalPolicy` forbids the operation
+    PermissionDenied(String),
+    /// The feature isn't available on this platform
+    #[cfg_attr(unix, allow(dead_code))]
+    Unsupported(String),
+    /// The system ran out of resources (e.g. file descriptors) for a new PTY
+    #[cfg_attr(not(unix), allow(dead_code))]
+    ResourceExhausted(String),
+    /// The session didn't respond in time
+    TimedOut(String),
+    /// An operation on the PTY itself failed
+    Pty(String),
+}
+
+impl fmt::Display for TerminalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "Terminal session {} not found", id),
+            Self::SessionExited(id) => write!(f, "Terminal session {} has exited", id),
+            Self::InvalidArgument(msg)
+            | Self::PermissionDenied(msg)
+            | Self::Unsupported(msg)
+            | Self::ResourceExhausted(msg)
+            | Self::TimedOut(msg)
+            | Self::Pty(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for TerminalError {}
+
+impl serde::Serialize for TerminalError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
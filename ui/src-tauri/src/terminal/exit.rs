@@ -0,0 +1,292 @@
+// src-tauri/src/terminal/exit.rs
+
+use super::LOG_TARGET;
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How a session's shell ended, as reported in `terminal-exit`
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExitReason {
+    Exited {
+        code: u32,
+    },
+    /// Killed by `signal`, e.g. 11 for a segfault
+    Signaled {
+        signal: i32,
+        core_dumped: bool,
+    },
+    /// Stopped by `signal` when its terminal went away; it's then killed so
+    /// it doesn't linger
+    Stopped {
+        signal: i32,
+    },
+}
+
+impl ExitReason {
+    /// The exit code reported alongside, which is 1 for anything but a
+    /// normal exit
+    pub fn exit_code(self) -> u32 {
+        match self {
+            Self::Exited { code } => code,
+            Self::Signaled { .. } | Self::Stopped { .. } => 1,
+        }
+    }
+}
+
+/// What happens to a session when its shell exits, see `set_exit_behavior`
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitBehavior {
+    #[default]
+    Close,
+    Keep,
+    Restart,
+}
+
+/// Longest pause between checks whether a child has exited
+const MAX_POLL_PAUSE: Duration = Duration::from_millis(50);
+
+/// Reap `pid`, returning how it ended. Unlike portable-pty's status this
+/// keeps the signal number and tells stops and core dumps apart.
+///
+/// A child still running after `timeout` is killed. If even that doesn't
+/// end it within another `timeout` (e.g. it's stuck in the kernel) it's left
+/// to a background thread to reap, so this never hangs and never leaves a
+/// zombie behind.
+#[cfg(unix)]
+pub fn wait(pid: u32, timeout: Duration) -> Option<ExitReason> {
+    let pid = pid as libc::pid_t;
+    let status = match poll(timeout, || waitpid(pid, libc::WUNTRACED | libc::WNOHANG)) {
+        Ok(Some(status)) => status,
+        Ok(None) => {
+            log::warn!(
+                target: LOG_TARGET,
+                "Process {} still running {} s after its terminal closed, killing it",
+                pid,
+                timeout.as_secs()
+            );
+            kill_and_wait(pid, timeout)?
+        }
+        // Someone else reaped it, e.g. the app ignores SIGCHLD
+        Err(_) => return None,
+    };
+    if libc::WIFEXITED(status) {
+        Some(ExitReason::Exited {
+            code: libc::WEXITSTATUS(status) as u32,
+        })
+    } else if libc::WIFSIGNALED(status) {
+        Some(ExitReason::Signaled {
+            signal: libc::WTERMSIG(status),
+            core_dumped: libc::WCOREDUMP(status),
+        })
+    } else if libc::WIFSTOPPED(status) {
+        // A stopped process ignores everything but SIGKILL
+        kill_and_wait(pid, timeout);
+        Some(ExitReason::Stopped {
+            signal: libc::WSTOPSIG(status),
+        })
+    } else {
+        None
+    }
+}
+
+/// SIGKILL `pid` and reap it, in the background if that takes longer than
+/// `timeout`
+#[cfg(unix)]
+fn kill_and_wait(pid: libc::pid_t, timeout: Duration) -> Option<libc::c_int> {
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+    match poll(timeout, || waitpid(pid, libc::WNOHANG)) {
+        Ok(Some(status)) => Some(status),
+        Ok(None) => {
+            log::warn!(
+                target: LOG_TARGET,
+                "Process {} didn't exit when killed, reaping it in the background",
+                pid
+            );
+            thread::spawn(move || waitpid(pid, 0));
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Reap `child` through portable-pty, for when there's no PID to `wait` on,
+/// with the same timeout, kill and background reaping
+pub fn wait_child(
+    mut child: Box<dyn portable_pty::Child + Send + Sync>,
+    timeout: Duration,
+) -> Option<ExitReason> {
+    let status = match poll(timeout, || child.try_wait()) {
+        Ok(Some(status)) => status,
+        Ok(None) => {
+            log::warn!(
+                target: LOG_TARGET,
+                "Shell still running {} s after its terminal closed, killing it",
+                timeout.as_secs()
+            );
+            let _ = child.kill();
+            match poll(timeout, || child.try_wait()) {
+                Ok(Some(status)) => status,
+                Ok(None) => {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "Shell didn't exit when killed, reaping it in the background"
+                    );
+                    thread::spawn(move || child.wait());
+                    return None;
+                }
+                Err(_) => return None,
+            }
+        }
+        Err(_) => return None,
+    };
+    Some(ExitReason::Exited {
+        code: status.exit_code(),
+    })
+}
+
+/// Call `try_reap` with growing pauses until it has a result, fails or
+/// `timeout` passes (`Ok(None)`)
+fn poll<T>(
+    timeout: Duration,
+    mut try_reap: impl FnMut() -> io::Result<Option<T>>,
+) -> io::Result<Option<T>> {
+    let deadline = Instant::now() + timeout;
+    let mut pause = Duration::from_millis(1);
+    loop {
+        if let Some(result) = try_reap()? {
+            return Ok(Some(result));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(pause);
+        pause = (pause * 2).min(MAX_POLL_PAUSE);
+    }
+}
+
+/// `waitpid`, retried when interrupted. `None` if `pid` hasn't changed state
+/// yet with `WNOHANG`.
+#[cfg(unix)]
+fn waitpid(pid: libc::pid_t, options: libc::c_int) -> io::Result<Option<libc::c_int>> {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, options) } {
+            0 => return Ok(None),
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            _ => return Ok(Some(status)),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+    use std::io::Read;
+    use std::sync::mpsc;
+
+    /// Fails a test that would otherwise hang
+    const TEST_DEADLINE: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn killing_many_sessions_at_once_never_hangs() {
+        const SESSIONS: u32 = 40;
+        let (done, results) = mpsc::channel();
+        for i in 0..SESSIONS {
+            let done = done.clone();
+            thread::spawn(move || {
+                let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+                let mut cmd = CommandBuilder::new("/bin/sh");
+                // Every other shell ignores the hangup, so only the kill
+                // after the timeout ends it
+                let ignores_hangup = i % 2 == 0;
+                let trap = if ignores_hangup { "trap '' HUP; " } else { "" };
+                let script = format!("{}echo ready; while :; do sleep 1; done", trap);
+                cmd.args(["-c", &script]);
+                let child = pair.slave.spawn_command(cmd).unwrap();
+                let pid = child.process_id().unwrap();
+                // Wait for the trap to be set before hanging up
+                let mut reader = pair.master.try_clone_reader().unwrap();
+                let mut output = Vec::new();
+                let mut buf = [0u8; 64];
+                while !String::from_utf8_lossy(&output).contains("ready") {
+                    let n = reader.read(&mut buf).unwrap();
+                    output.extend_from_slice(&buf[..n]);
+                }
+                // Signal the shell and close the PTY, as dropping the session
+                // in `kill_terminal` does
+                child.clone_killer().kill().unwrap();
+                drop(pair);
+                let reason = wait(pid, Duration::from_millis(200));
+                let _ = done.send((pid, ignores_hangup, reason));
+            });
+        }
+        drop(done);
+        for _ in 0..SESSIONS {
+            let (pid, ignores_hangup, reason) = results
+                .recv_timeout(TEST_DEADLINE)
+                .expect("reaping a killed session hung");
+            let signal = match reason {
+                Some(ExitReason::Signaled { signal, .. }) => signal,
+                _ => panic!("process {} wasn't reaped after a signal", pid),
+            };
+            let expected = if ignores_hangup {
+                libc::SIGKILL
+            } else {
+                libc::SIGHUP
+            };
+            assert_eq!(signal, expected, "process {}", pid);
+        }
+    }
+
+    /// Whether `pid` is a zombie, i.e. has exited but not been reaped
+    #[cfg(target_os = "linux")]
+    fn is_zombie(pid: u32) -> bool {
+        // The state follows the parenthesized command name
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|stat| Some(stat.rsplit_once(')')?.1.trim_start().starts_with('Z')))
+            .unwrap_or(false)
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn short_lived_children_leave_no_zombies() {
+        let pids: Vec<u32> = (0..100)
+            .map(|i| {
+                // Reaped by `wait` below, through its PID
+                #[allow(clippy::zombie_processes)]
+                let child = std::process::Command::new("/bin/sh")
+                    .args(["-c", &format!("exit {}", i % 3)])
+                    .spawn()
+                    .unwrap();
+                child.id()
+            })
+            .collect();
+        // Unreaped they do show up as zombies, so the check below means
+        // something
+        let last = pids[pids.len() - 1];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !is_zombie(last) {
+            assert!(Instant::now() < deadline, "process {} never exited", last);
+            thread::sleep(Duration::from_millis(10));
+        }
+        for (i, &pid) in pids.iter().enumerate() {
+            match wait(pid, Duration::from_secs(5)) {
+                Some(ExitReason::Exited { code }) => assert_eq!(code, i as u32 % 3),
+                _ => panic!("process {} didn't exit normally", pid),
+            }
+        }
+        let zombies: Vec<u32> = pids.into_iter().filter(|&pid| is_zombie(pid)).collect();
+        assert!(zombies.is_empty(), "zombies left: {:?}", zombies);
+    }
+}
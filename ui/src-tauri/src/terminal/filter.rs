@@ -0,0 +1,102 @@
+// src-tauri/src/terminal/filter.rs
+
+use super::ansi::{self, Segment};
+use super::TerminalError;
+use regex::bytes::Regex;
+use std::borrow::Cow;
+
+/// Longest partial line held back waiting for the rest of a possible match
+const MAX_HOLDBACK: usize = 4096;
+
+/// Filter kinds accepted by `add_output_filter`, e.g.
+/// `{ "kind": "redact_regex", "pattern": "ghp_\\w+", "replacement": "***" }`
+#[derive(serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutputFilterSpec {
+    /// Replace every match of `pattern` with `replacement`. `$1`-style
+    /// group references in the replacement are expanded.
+    RedactRegex {
+        pattern: String,
+        replacement: String,
+    },
+}
+
+pub enum OutputFilter {
+    RedactRegex { regex: Regex, replacement: Vec<u8> },
+}
+
+impl OutputFilter {
+    pub fn compile(spec: OutputFilterSpec) -> Result<Self, TerminalError> {
+        match spec {
+            OutputFilterSpec::RedactRegex {
+                pattern,
+                replacement,
+            } => {
+                let regex = Regex::new(&pattern).map_err(|e| {
+                    TerminalError::InvalidArgument(format!("Invalid filter pattern: {}", e))
+                })?;
+                Ok(Self::RedactRegex {
+                    regex,
+                    replacement: replacement.into_bytes(),
+                })
+            }
+        }
+    }
+
+    fn apply<'a>(&self, text: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            Self::RedactRegex { regex, replacement } => {
+                regex.replace_all(text, replacement.as_slice())
+            }
+        }
+    }
+}
+
+/// Run `filters` over the text between escape sequences.
+///
+/// Filters apply in the order they were added, each seeing the previous
+/// one's output. Escape sequences pass through untouched, so a match can't
+/// span one.
+pub fn apply_filters(filters: &[OutputFilter], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for segment in ansi::segments(data) {
+        match segment {
+            Segment::Text(text) => {
+                let mut text = Cow::Borrowed(text);
+                for filter in filters {
+                    if let Cow::Owned(replaced) = filter.apply(&text) {
+                        text = Cow::Owned(replaced);
+                    }
+                }
+                out.extend_from_slice(&text);
+            }
+            Segment::Escape(seq) | Segment::Incomplete(seq) => out.extend_from_slice(seq),
+        }
+    }
+    out
+}
+
+/// Where to split `data` so the filtered part ends on a line boundary and
+/// never inside an escape sequence. Everything from the returned offset on
+/// is held back until more output (or a flush timeout) arrives.
+pub fn holdback_point(data: &[u8]) -> usize {
+    let mut offset = 0;
+    let mut cut = 0;
+    for segment in ansi::segments(data) {
+        match segment {
+            Segment::Text(text) => {
+                if let Some(pos) = text.iter().rposition(|&b| b == b'\n') {
+                    cut = offset + pos + 1;
+                }
+                offset += text.len();
+            }
+            Segment::Escape(seq) => offset += seq.len(),
+            Segment::Incomplete(_) => break,
+        }
+    }
+    if data.len() - cut > MAX_HOLDBACK {
+        data.len()
+    } else {
+        cut
+    }
+}
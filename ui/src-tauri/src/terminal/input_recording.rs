@@ -0,0 +1,90 @@
+// src-tauri/src/terminal/input_recording.rs
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Appends a session's input to a file as a header line naming the session
+/// and giving its size, then asciicast-style event lines timed from when
+/// recording started: `[seconds, "i", data]` for input and
+/// `[seconds, "r", "COLSxROWS"]` for resizes.
+pub struct InputRecorder {
+    file: File,
+    started: Instant,
+}
+
+impl InputRecorder {
+    /// Open `path` for appending, creating it readable by the current user
+    /// only since it may end up holding passwords
+    pub fn open(path: &str, session_uuid: &str, cols: u16, rows: u16) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let header = serde_json::json!({
+            "session_uuid": session_uuid,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+        });
+        file.write_all(format!("{}\n", header).as_bytes())?;
+        Ok(Self {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, data: &str) -> io::Result<()> {
+        self.write_event("i", data)
+    }
+
+    pub fn record_resize(&mut self, cols: u16, rows: u16) -> io::Result<()> {
+        self.write_event("r", &format!("{}x{}", cols, rows))
+    }
+
+    fn write_event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let event = serde_json::json!([self.started.elapsed().as_secs_f64(), kind, data]);
+        // A single write, so each event is appended as a whole line
+        self.file.write_all(format!("{}\n", event).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn resizes_are_recorded_in_the_cast_file() {
+        let path = std::env::temp_dir().join(format!("karpi-{}.cast", uuid::Uuid::new_v4()));
+        let path_str = path.to_str().unwrap();
+        let mut recorder = InputRecorder::open(path_str, "uuid", 80, 24).unwrap();
+        recorder.record("ls\r").unwrap();
+        recorder.record_resize(120, 40).unwrap();
+        drop(recorder);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[0]["height"], 24);
+        assert_eq!(lines[1][1], "i");
+        assert_eq!(lines[2][1], "r");
+        assert_eq!(lines[2][2], "120x40");
+        // Timed relative to the start, in order
+        let time = |event: &Value| event[0].as_f64().unwrap();
+        assert!(time(&lines[1]) >= 0.0);
+        assert!(time(&lines[2]) >= time(&lines[1]));
+    }
+}
@@ -0,0 +1,65 @@
+// src-tauri/src/terminal/jobs.rs
+
+use super::process;
+
+/// Follows the shell's jobs being stopped (e.g. by Ctrl-Z) and continued,
+/// see `track_jobs`.
+///
+/// Only the shell can wait for its jobs, so they're followed by process
+/// group instead: the foreground group is noted whenever it's looked at,
+/// and one that has left the foreground with its leader stopped is a
+/// stopped job. Stopped jobs are then checked until they run again or are
+/// gone.
+#[derive(Default)]
+pub struct JobTracker {
+    // Foreground groups other than the shell's, since last checked
+    seen: Vec<u32>,
+    stopped: Vec<u32>,
+}
+
+pub enum JobChange {
+    Stopped(u32),
+    Continued(u32),
+}
+
+impl JobTracker {
+    /// Note the current foreground group
+    pub fn observe(&mut self, shell: u32, foreground: Option<u32>) {
+        if let Some(pgid) = foreground.filter(|&p| p != shell) {
+            if !self.seen.contains(&pgid) {
+                self.seen.push(pgid);
+            }
+        }
+    }
+
+    /// Check the groups seen and the stopped jobs, given the current
+    /// foreground group
+    pub fn update(&mut self, shell: u32, foreground: Option<u32>, changes: &mut Vec<JobChange>) {
+        self.observe(shell, foreground);
+        // A stopped job keeps the foreground until the shell takes it back
+        let (current, left): (Vec<u32>, Vec<u32>) = std::mem::take(&mut self.seen)
+            .into_iter()
+            .partition(|&pgid| Some(pgid) == foreground);
+        self.seen = current;
+        self.stopped
+            .retain(|&pgid| match process::is_stopped(pgid) {
+                Some(true) => true,
+                Some(false) => {
+                    changes.push(JobChange::Continued(pgid));
+                    false
+                }
+                None => false,
+            });
+        // Anything else finished or was moved to the background running
+        for pgid in left {
+            if process::is_stopped(pgid) == Some(true) && !self.stopped.contains(&pgid) {
+                self.stopped.push(pgid);
+                changes.push(JobChange::Stopped(pgid));
+            }
+        }
+    }
+
+    pub fn has_stopped(&self) -> bool {
+        !self.stopped.is_empty()
+    }
+}
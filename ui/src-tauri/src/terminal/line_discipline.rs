@@ -0,0 +1,81 @@
+// src-tauri/src/terminal/line_discipline.rs
+
+/// The user-facing part of a session's termios settings, see
+/// `get_line_discipline`. Special characters are single bytes (e.g. 127 for
+/// DEL, 3 for Ctrl-C), `None` when disabled.
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct LineDiscipline {
+    /// Delete the previous character
+    erase: Option<u8>,
+    /// Delete the previous word
+    werase: Option<u8>,
+    /// Delete the whole line
+    kill: Option<u8>,
+    /// Send SIGINT
+    intr: Option<u8>,
+    /// End of input at the start of a line
+    eof: Option<u8>,
+    /// Send SIGTSTP
+    susp: Option<u8>,
+    /// Echo input back
+    echo: bool,
+    /// Line editing: input is delivered a line at a time
+    icanon: bool,
+    /// `intr`, `susp` and quit send signals
+    isig: bool,
+    /// XOFF/XON (Ctrl-S/Ctrl-Q) stop and restart output
+    ixon: bool,
+}
+
+#[cfg(unix)]
+impl LineDiscipline {
+    pub fn from_termios(t: &libc::termios) -> Self {
+        let special = |index: usize| Some(t.c_cc[index]).filter(|&c| c != libc::_POSIX_VDISABLE);
+        Self {
+            erase: special(libc::VERASE),
+            werase: special(libc::VWERASE),
+            kill: special(libc::VKILL),
+            intr: special(libc::VINTR),
+            eof: special(libc::VEOF),
+            susp: special(libc::VSUSP),
+            echo: t.c_lflag & libc::ECHO != 0,
+            icanon: t.c_lflag & libc::ICANON != 0,
+            isig: t.c_lflag & libc::ISIG != 0,
+            ixon: t.c_iflag & libc::IXON != 0,
+        }
+    }
+
+    /// Write these settings into `t`, leaving everything else as it is
+    pub fn apply(&self, t: &mut libc::termios) {
+        let chars = [
+            (libc::VERASE, self.erase),
+            (libc::VWERASE, self.werase),
+            (libc::VKILL, self.kill),
+            (libc::VINTR, self.intr),
+            (libc::VEOF, self.eof),
+            (libc::VSUSP, self.susp),
+        ];
+        for (index, c) in chars {
+            t.c_cc[index] = c.unwrap_or(libc::_POSIX_VDISABLE);
+        }
+        let flags = [
+            (libc::ECHO, self.echo),
+            (libc::ICANON, self.icanon),
+            (libc::ISIG, self.isig),
+        ];
+        for (flag, on) in flags {
+            set_flag(&mut t.c_lflag, flag, on);
+        }
+        set_flag(&mut t.c_iflag, libc::IXON, self.ixon);
+    }
+}
+
+#[cfg(unix)]
+fn set_flag(flags: &mut libc::tcflag_t, flag: libc::tcflag_t, on: bool) {
+    if on {
+        *flags |= flag;
+    } else {
+        *flags &= !flag;
+    }
+}
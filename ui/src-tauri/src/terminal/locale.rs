@@ -0,0 +1,93 @@
+// src-tauri/src/terminal/locale.rs
+
+/// Variables `spawn_terminal`'s `locale` sets
+pub const LOCALE_VARS: [&str; 3] = ["LANG", "LC_ALL", "LC_CTYPE"];
+
+/// Most alternatives suggested for a locale that isn't installed
+const MAX_ALTERNATIVES: usize = 5;
+
+/// Check that `locale` is installed, comparing codesets the way glibc does
+/// so `en_US.UTF-8` matches `en_US.utf8`. On failure, the message to show,
+/// with a few installed locales to use instead. `C` and `POSIX` are always
+/// there. Elsewhere than Unix, or if `locale -a` can't be run, any name
+/// that isn't empty is taken.
+pub fn check(locale: &str) -> Result<(), String> {
+    if locale.is_empty() || locale.contains('\0') {
+        return Err(format!("Invalid locale {:?}", locale));
+    }
+    if matches!(locale, "C" | "POSIX") {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if let Some(available) = available() {
+        let wanted = normalize(locale);
+        if !available.iter().any(|name| normalize(name) == wanted) {
+            return Err(format!(
+                "Locale {:?} is not available, try one of: {}",
+                locale,
+                alternatives(locale, available).join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The output of `locale -a`, run once as installing locales while the app
+/// runs is rare
+#[cfg(unix)]
+fn available() -> Option<&'static [String]> {
+    static AVAILABLE: std::sync::OnceLock<Option<Vec<String>>> = std::sync::OnceLock::new();
+    AVAILABLE
+        .get_or_init(|| {
+            let output = std::process::Command::new("locale")
+                .arg("-a")
+                .stdin(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            let names: Vec<String> = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+            (!names.is_empty()).then_some(names)
+        })
+        .as_deref()
+}
+
+/// `locale` with its codeset lowercased and stripped of anything but
+/// letters and digits, as glibc looks it up
+#[cfg(unix)]
+fn normalize(locale: &str) -> String {
+    let Some((language, rest)) = locale.split_once('.') else {
+        return locale.to_string();
+    };
+    let (codeset, modifier) = match rest.split_once('@') {
+        Some((codeset, modifier)) => (codeset, Some(modifier)),
+        None => (rest, None),
+    };
+    let codeset: String = codeset
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    match modifier {
+        Some(modifier) => format!("{}.{}@{}", language, codeset, modifier),
+        None => format!("{}.{}", language, codeset),
+    }
+}
+
+/// Installed locales to suggest instead of `locale`: those of the same
+/// language first, then UTF-8 ones
+#[cfg(unix)]
+fn alternatives<'a>(locale: &str, available: &'a [String]) -> Vec<&'a str> {
+    let language = |name: &str| name.split(['_', '.', '@']).next().unwrap_or("").to_string();
+    let is_utf8 = |name: &str| normalize(name).contains(".utf8");
+    let wanted = language(locale);
+    let mut ranked: Vec<&str> = available.iter().map(String::as_str).collect();
+    ranked.sort_by_key(|name| (language(name) != wanted, !is_utf8(name)));
+    ranked.truncate(MAX_ALTERNATIVES);
+    ranked
+}
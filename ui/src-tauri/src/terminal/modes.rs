@@ -0,0 +1,76 @@
+// src-tauri/src/terminal/modes.rs
+
+/// Which mouse events the program asked to be reported
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseTracking {
+    /// Mode 9: button presses only
+    X10,
+    /// Mode 1000: presses and releases
+    Normal,
+    /// Mode 1002: also motion while a button is held
+    ButtonEvent,
+    /// Mode 1003: all motion
+    AnyEvent,
+}
+
+impl MouseTracking {
+    fn from_mode(mode: u16) -> Option<Self> {
+        match mode {
+            9 => Some(Self::X10),
+            1000 => Some(Self::Normal),
+            1002 => Some(Self::ButtonEvent),
+            1003 => Some(Self::AnyEvent),
+            _ => None,
+        }
+    }
+}
+
+/// DEC private modes the program has set that change how input must be
+/// encoded or output rendered, see `get_terminal_modes`
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct TerminalModes {
+    /// Mode 1 (DECCKM): cursor keys send `ESC O` instead of `ESC [`
+    pub application_cursor: bool,
+    /// Mode 2004: pastes must be wrapped in `ESC [200~` … `ESC [201~`
+    pub bracketed_paste: bool,
+    pub mouse_tracking: Option<MouseTracking>,
+    /// Mode 1006: mouse events use the SGR encoding
+    pub mouse_sgr: bool,
+    /// Mode 7 (DECAWM): text reaching the right margin wraps to the next
+    /// line instead of overwriting the last column. On unless turned off.
+    pub autowrap: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self {
+            application_cursor: false,
+            bracketed_paste: false,
+            mouse_tracking: None,
+            mouse_sgr: false,
+            autowrap: true,
+        }
+    }
+}
+
+impl TerminalModes {
+    /// Apply a set (`h`) or reset (`l`) of private mode `mode`. Returns
+    /// whether anything changed.
+    pub fn apply(&mut self, mode: u16, set: bool) -> bool {
+        let before = *self;
+        match mode {
+            1 => self.application_cursor = set,
+            7 => self.autowrap = set,
+            2004 => self.bracketed_paste = set,
+            1006 => self.mouse_sgr = set,
+            _ => match MouseTracking::from_mode(mode) {
+                Some(tracking) if set => self.mouse_tracking = Some(tracking),
+                // Resetting any tracking mode turns reporting off, as in xterm
+                Some(_) => self.mouse_tracking = None,
+                None => {}
+            },
+        }
+        *self != before
+    }
+}
@@ -0,0 +1,119 @@
+// src-tauri/src/terminal/mouse.rs
+
+use super::modes::{MouseTracking, TerminalModes};
+
+/// Largest coordinate the legacy encoding can carry (a byte offset by 32)
+const MAX_LEGACY_COORD: u16 = 255 - 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    /// Motion with no button held
+    None,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseAction {
+    Press,
+    Release,
+    Move,
+}
+
+#[derive(Clone, Copy, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct MouseModifiers {
+    shift: bool,
+    alt: bool,
+    ctrl: bool,
+}
+
+/// A mouse event at zero-based cell `x`, `y` of a `cols` x `rows` terminal
+pub struct MouseEvent {
+    pub button: MouseButton,
+    pub action: MouseAction,
+    pub modifiers: MouseModifiers,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// Encode `event` as the program asked for in `modes`, or `None` if it
+/// didn't ask for events like this one
+pub fn encode(event: &MouseEvent, modes: &TerminalModes, cols: u16, rows: u16) -> Option<Vec<u8>> {
+    let tracking = modes.mouse_tracking?;
+    let is_wheel = matches!(
+        event.button,
+        MouseButton::WheelUp
+            | MouseButton::WheelDown
+            | MouseButton::WheelLeft
+            | MouseButton::WheelRight
+    );
+    let wanted = match event.action {
+        MouseAction::Press => event.button != MouseButton::None,
+        // Wheels have no release
+        MouseAction::Release => tracking != MouseTracking::X10 && !is_wheel,
+        MouseAction::Move => match tracking {
+            MouseTracking::X10 | MouseTracking::Normal => false,
+            MouseTracking::ButtonEvent => event.button != MouseButton::None && !is_wheel,
+            MouseTracking::AnyEvent => !is_wheel,
+        },
+    };
+    if !wanted {
+        return None;
+    }
+
+    let mut code: u16 = match event.button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+        MouseButton::None => 3,
+        MouseButton::WheelUp => 64,
+        MouseButton::WheelDown => 65,
+        MouseButton::WheelLeft => 66,
+        MouseButton::WheelRight => 67,
+    };
+    // X10 mode reports no modifiers
+    if tracking != MouseTracking::X10 {
+        let MouseModifiers { shift, alt, ctrl } = event.modifiers;
+        code += 4 * shift as u16 + 8 * alt as u16 + 16 * ctrl as u16;
+    }
+    if event.action == MouseAction::Move {
+        code += 32;
+    }
+    // One-based, and never outside the terminal
+    let x = event.x.min(cols.saturating_sub(1)) + 1;
+    let y = event.y.min(rows.saturating_sub(1)) + 1;
+
+    if modes.mouse_sgr {
+        let final_byte = if event.action == MouseAction::Release {
+            'm'
+        } else {
+            'M'
+        };
+        return Some(format!("\x1b[<{};{};{}{}", code, x, y, final_byte).into_bytes());
+    }
+    // The legacy encoding can't tell which button was released
+    if event.action == MouseAction::Release {
+        code = (code & !0b11) | 3;
+    }
+    // Nor carry larger coordinates; sending a wrong position would be worse
+    // than sending nothing
+    if x > MAX_LEGACY_COORD || y > MAX_LEGACY_COORD {
+        return None;
+    }
+    Some(vec![
+        0x1b,
+        b'[',
+        b'M',
+        (32 + code) as u8,
+        (32 + x) as u8,
+        (32 + y) as u8,
+    ])
+}
@@ -0,0 +1,70 @@
+// src-tauri/src/terminal/ndjson_log.rs
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Appends a session's output, resizes and exit to a file as one JSON
+/// object per line, each with `ts` (Unix time in seconds), `type` and
+/// `session` (the session's UUID):
+/// `{"type": "output", "seq": ..., "data": "<base64>"}`,
+/// `{"type": "resize", "cols": ..., "rows": ...}` and
+/// `{"type": "exit", "exit_code": ...}`.
+pub struct NdjsonLog {
+    file: File,
+    session_uuid: String,
+}
+
+impl NdjsonLog {
+    /// Open `path` for appending, creating it readable by the current user
+    /// only since output may show secrets
+    pub fn open(path: &str, session_uuid: &str) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        Ok(Self {
+            file: options.open(path)?,
+            session_uuid: session_uuid.to_string(),
+        })
+    }
+
+    pub fn output(&mut self, seq: u64, data: &[u8]) -> io::Result<()> {
+        self.write_record(serde_json::json!({
+            "type": "output",
+            "seq": seq,
+            "data": BASE64.encode(data),
+        }))
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) -> io::Result<()> {
+        self.write_record(serde_json::json!({
+            "type": "resize",
+            "cols": cols,
+            "rows": rows,
+        }))
+    }
+
+    pub fn exit(&mut self, exit_code: Option<u32>) -> io::Result<()> {
+        self.write_record(serde_json::json!({
+            "type": "exit",
+            "exit_code": exit_code,
+        }))
+    }
+
+    fn write_record(&mut self, mut record: serde_json::Value) -> io::Result<()> {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64());
+        record["ts"] = ts.into();
+        record["session"] = self.session_uuid.as_str().into();
+        // A single write, so each record is appended as a whole line
+        self.file.write_all(format!("{}\n", record).as_bytes())?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,86 @@
+// src-tauri/src/terminal/output_pipe.rs
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+/// A FIFO that a session's output is mirrored to, see `tee_output_to_pipe`.
+///
+/// Opening a FIFO for writing blocks until a reader opens it, so it's opened
+/// non-blocking instead, which fails while there is no reader. Output is
+/// dropped until one shows up, and whenever the reader falls behind and the
+/// pipe is full; a reader that goes away is waited for again.
+pub struct OutputPipe {
+    path: PathBuf,
+    file: Option<File>,
+    // Made by `open` rather than already there, so removed when done
+    created: bool,
+}
+
+impl OutputPipe {
+    /// Use the FIFO at `path`, creating it readable and writable by the
+    /// current user only if it doesn't exist. Fails if something else is
+    /// there.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let created = match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => false,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "not a named pipe",
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let c_path = CString::new(path.as_os_str().as_bytes())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                true
+            }
+            Err(e) => return Err(e),
+        };
+        let mut pipe = Self {
+            path,
+            file: None,
+            created,
+        };
+        pipe.connect();
+        Ok(pipe)
+    }
+
+    /// Open the FIFO if a reader has it open
+    fn connect(&mut self) {
+        self.file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.path)
+            .ok();
+    }
+
+    pub fn write(&mut self, data: &[u8]) {
+        if self.file.is_none() {
+            self.connect();
+        }
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        match file.write_all(data) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(_) => self.file = None,
+            Ok(()) => {}
+        }
+    }
+}
+
+impl Drop for OutputPipe {
+    fn drop(&mut self) {
+        if self.created {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
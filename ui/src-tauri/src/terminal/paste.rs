@@ -0,0 +1,58 @@
+// src-tauri/src/terminal/paste.rs
+
+/// Commands flagged by `check_paste_safety` unless configured otherwise
+pub const DEFAULT_DANGEROUS_COMMANDS: &[&str] = &[
+    "sudo", "su", "doas", "rm -rf", "rm -fr", "mkfs", "dd", "shutdown", "reboot", "| sh", "| bash",
+    "> /dev/",
+];
+
+/// What makes a paste worth confirming, see `check_paste_safety`
+#[derive(serde::Serialize)]
+pub struct PasteRisk {
+    /// A newline would run the text as soon as it's pasted
+    has_newlines: bool,
+    /// Control characters other than tab and newlines, including escape
+    /// sequences that could break out of bracketed paste
+    has_control_chars: bool,
+    /// Entries of the dangerous-command list found in the text
+    dangerous_commands: Vec<String>,
+    /// Any of the above
+    risky: bool,
+}
+
+pub fn assess(data: &str, dangerous_commands: &[String]) -> PasteRisk {
+    let has_newlines = data.contains(['\n', '\r']);
+    let has_control_chars = data
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'));
+    let dangerous_commands: Vec<String> = dangerous_commands
+        .iter()
+        .filter(|command| contains_command(data, command))
+        .cloned()
+        .collect();
+    let risky = has_newlines || has_control_chars || !dangerous_commands.is_empty();
+    PasteRisk {
+        has_newlines,
+        has_control_chars,
+        dangerous_commands,
+        risky,
+    }
+}
+
+/// Whether `command` occurs in `data` as whole words, so `su` matches
+/// `su root` but not `sum`
+fn contains_command(data: &str, command: &str) -> bool {
+    let command = command.trim();
+    if command.is_empty() {
+        return false;
+    }
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let needs_start = command.starts_with(is_word);
+    let needs_end = command.ends_with(is_word);
+    data.match_indices(command).any(|(i, _)| {
+        let before = data[..i].chars().next_back();
+        let after = data[i + command.len()..].chars().next();
+        (!needs_start || !before.is_some_and(is_word))
+            && (!needs_end || !after.is_some_and(is_word))
+    })
+}
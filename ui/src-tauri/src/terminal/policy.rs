@@ -0,0 +1,84 @@
+// src-tauri/src/terminal/policy.rs
+
+use super::TerminalError;
+use portable_pty::CommandBuilder;
+use std::path::{Path, PathBuf};
+
+/// Security-sensitive spawn features, fixed when `TerminalState` is created
+/// so the frontend can't enable them for itself
+pub struct TerminalPolicy {
+    /// Allow `spawn_terminal(run_as)`. Off by default.
+    pub allow_run_as: bool,
+    /// Privilege escalation tool used for `run_as`; invoked as
+    /// `<tool> -u <user> <shell> -l`, which both sudo and doas accept
+    pub escalation_command: String,
+    /// Allow `start_input_recording`. Off by default, since a keystroke log
+    /// captures anything typed, passwords included.
+    pub allow_input_recording: bool,
+    /// Allow `spawn_terminal(control_socket)`. Off by default, since anything
+    /// connecting to the socket can type into the shell.
+    pub allow_control_socket: bool,
+    /// Allow `adopt_fd`. Off by default, since a session takes over the
+    /// descriptor it's given and closes it in the end, whatever in the app
+    /// it belongs to.
+    pub allow_adopt_fd: bool,
+}
+
+impl Default for TerminalPolicy {
+    fn default() -> Self {
+        Self {
+            allow_run_as: false,
+            escalation_command: "sudo".to_string(),
+            allow_input_recording: false,
+            allow_control_socket: false,
+            allow_adopt_fd: false,
+        }
+    }
+}
+
+impl TerminalPolicy {
+    /// Build the command running `shell` as `user` through the escalation
+    /// tool. Its password prompt appears in the terminal like any other
+    /// output, so it works interactively. The app user still needs the
+    /// corresponding sudoers/doas.conf rights.
+    pub fn run_as_command(&self, user: &str, shell: &str) -> Result<CommandBuilder, TerminalError> {
+        if !self.allow_run_as {
+            return Err(TerminalError::PermissionDenied(
+                "Running terminals as another user is disabled by policy".to_string(),
+            ));
+        }
+        let valid = !user.is_empty()
+            && !user.starts_with('-')
+            && user
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid {
+            return Err(TerminalError::InvalidArgument(format!(
+                "Invalid user name: {:?}",
+                user
+            )));
+        }
+        let tool = find_program(&self.escalation_command).ok_or_else(|| {
+            TerminalError::InvalidArgument(format!(
+                "Escalation command {} not found",
+                self.escalation_command
+            ))
+        })?;
+
+        let mut cmd = CommandBuilder::new(tool);
+        cmd.args(["-u", user, shell, "-l"]);
+        Ok(cmd)
+    }
+}
+
+/// Resolve a program name against `PATH` (or check it directly if it's a path)
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        let path = Path::new(program);
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
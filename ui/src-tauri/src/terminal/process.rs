@@ -0,0 +1,206 @@
+// src-tauri/src/terminal/process.rs
+
+use super::TerminalError;
+
+/// A process running in a session, see `get_process_tree`
+#[derive(serde::Serialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    /// Command line arguments; empty where the platform doesn't expose them
+    pub cmdline: Vec<String>,
+}
+
+/// `root` followed by all of its descendants, parents before children.
+/// Empty if `root` no longer exists.
+#[cfg(target_os = "linux")]
+pub fn tree(root: u32) -> Result<Vec<ProcessInfo>, TerminalError> {
+    let entries = std::fs::read_dir("/proc")
+        .map_err(|e| TerminalError::Pty(format!("Failed to read /proc: {}", e)))?;
+    // Processes can exit while we scan; those are simply skipped
+    let all: Vec<ProcessInfo> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter_map(linux_process)
+        .collect();
+
+    let Some(shell) = all.iter().position(|p| p.pid == root) else {
+        return Ok(Vec::new());
+    };
+    let mut all: Vec<Option<ProcessInfo>> = all.into_iter().map(Some).collect();
+    let mut tree = vec![all[shell].take().unwrap()];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i].pid;
+        for slot in all.iter_mut() {
+            if slot.as_ref().is_some_and(|p| p.ppid == parent) {
+                tree.push(slot.take().unwrap());
+            }
+        }
+        i += 1;
+    }
+    Ok(tree)
+}
+
+#[cfg(target_os = "linux")]
+fn linux_process(pid: u32) -> Option<ProcessInfo> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // `pid (comm) state ppid ...`, where comm may itself contain parentheses
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let name = stat.get(open + 1..close)?.to_string();
+    let ppid = stat
+        .get(close + 1..)?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))
+        .map(|raw| {
+            raw.split(|&b| b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(ProcessInfo {
+        pid,
+        ppid,
+        name,
+        cmdline,
+    })
+}
+
+/// `root` followed by all of its descendants, parents before children.
+/// Empty if `root` no longer exists. Command lines aren't collected here.
+#[cfg(target_os = "macos")]
+pub fn tree(root: u32) -> Result<Vec<ProcessInfo>, TerminalError> {
+    let Some(name) = macos_name(root) else {
+        return Ok(Vec::new());
+    };
+    let mut tree = vec![ProcessInfo {
+        pid: root,
+        // The shell is spawned directly by us
+        ppid: std::process::id(),
+        name,
+        cmdline: Vec::new(),
+    }];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i].pid;
+        for pid in macos_children(parent) {
+            if let Some(name) = macos_name(pid) {
+                tree.push(ProcessInfo {
+                    pid,
+                    ppid: parent,
+                    name,
+                    cmdline: Vec::new(),
+                });
+            }
+        }
+        i += 1;
+    }
+    Ok(tree)
+}
+
+#[cfg(target_os = "macos")]
+fn macos_children(pid: u32) -> Vec<u32> {
+    let mut pids = vec![0 as libc::pid_t; 1024];
+    let size = (pids.len() * std::mem::size_of::<libc::pid_t>()) as libc::c_int;
+    // SAFETY: the buffer is valid for `size` bytes
+    let count =
+        unsafe { libc::proc_listchildpids(pid as libc::pid_t, pids.as_mut_ptr().cast(), size) };
+    pids.truncate(count.max(0) as usize);
+    pids.into_iter()
+        .filter(|&p| p > 0)
+        .map(|p| p as u32)
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn macos_name(pid: u32) -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its length
+    let len = unsafe {
+        libc::proc_name(
+            pid as libc::c_int,
+            buf.as_mut_ptr().cast(),
+            buf.len() as u32,
+        )
+    };
+    (len > 0).then(|| String::from_utf8_lossy(&buf[..len as usize]).into_owned())
+}
+
+/// Whether the process is stopped by a signal, `None` if it's gone or the
+/// platform can't tell
+#[cfg(target_os = "linux")]
+pub fn is_stopped(pid: u32) -> Option<bool> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let close = stat.rfind(')')?;
+    let state = stat.get(close + 1..)?.split_whitespace().next()?;
+    Some(state == "T")
+}
+
+#[cfg(target_os = "macos")]
+pub fn is_stopped(pid: u32) -> Option<bool> {
+    // `SSTOP` in <sys/proc.h>
+    const SSTOP: u32 = 4;
+    let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    // SAFETY: the buffer is valid for `size` bytes
+    let len = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            (&mut info as *mut libc::proc_bsdinfo).cast(),
+            size,
+        )
+    };
+    (len == size).then_some(info.pbi_status == SSTOP)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+pub fn is_stopped(_pid: u32) -> Option<bool> {
+    None
+}
+
+/// The process's working directory, `None` if it's gone or can't be read
+#[cfg(target_os = "linux")]
+pub fn cwd(pid: u32) -> Option<std::path::PathBuf> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+}
+
+#[cfg(target_os = "macos")]
+pub fn cwd(pid: u32) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut info: libc::proc_vnodepathinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_vnodepathinfo>() as libc::c_int;
+    // SAFETY: the buffer is valid for `size` bytes
+    let len = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDVNODEPATHINFO,
+            0,
+            (&mut info as *mut libc::proc_vnodepathinfo).cast(),
+            size,
+        )
+    };
+    if len != size {
+        return None;
+    }
+    let path = &info.pvi_cdir.vip_path;
+    // SAFETY: the path is a `MAXPATHLEN` byte buffer, split into rows
+    let path = unsafe {
+        std::slice::from_raw_parts(path.as_ptr().cast::<u8>(), std::mem::size_of_val(path))
+    };
+    let path = std::ffi::CStr::from_bytes_until_nul(path).ok()?.to_bytes();
+    (!path.is_empty()).then(|| std::ffi::OsStr::from_bytes(path).into())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn tree(_root: u32) -> Result<Vec<ProcessInfo>, TerminalError> {
+    Err(TerminalError::Unsupported(
+        "Process trees are only supported on Linux and macOS".to_string(),
+    ))
+}
@@ -0,0 +1,78 @@
+// src-tauri/src/terminal/rate_limit.rs
+
+use std::time::Instant;
+
+/// Token bucket capping a session's output rate, see `set_output_rate_limit`.
+/// Bursts of up to one second's worth pass at once.
+pub struct RateLimit {
+    bytes_per_sec: u64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimit {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            tokens: bytes_per_sec as f64,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// How many of `wanted` bytes may be emitted now
+    pub fn take(&mut self, wanted: usize) -> usize {
+        let now = Instant::now();
+        let rate = self.bytes_per_sec as f64;
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.refilled = now;
+        let granted = (self.tokens as usize).min(wanted);
+        self.tokens -= granted as f64;
+        granted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    const RATE: u64 = 20_000;
+
+    /// Ask for far more than the cap for `period`, returning what was granted
+    fn flood(limit: &mut RateLimit, period: Duration) -> usize {
+        let start = Instant::now();
+        let mut granted = 0;
+        while start.elapsed() < period {
+            granted += limit.take(64 * 1024);
+            thread::sleep(Duration::from_millis(1));
+        }
+        granted
+    }
+
+    #[test]
+    fn a_burst_of_one_seconds_worth_passes_at_once() {
+        let mut limit = RateLimit::new(RATE);
+        assert_eq!(limit.take(usize::MAX), RATE as usize);
+        assert!(limit.take(usize::MAX) < 100);
+    }
+
+    #[test]
+    fn emitted_rate_stays_under_the_cap() {
+        let mut limit = RateLimit::new(RATE);
+        limit.take(usize::MAX);
+        let start = Instant::now();
+        let granted = flood(&mut limit, Duration::from_millis(500));
+        let allowed = RATE as f64 * start.elapsed().as_secs_f64();
+        assert!(granted as f64 <= allowed + 1.0, "{} > {}", granted, allowed);
+        // Throttled, not stopped
+        assert!(granted as f64 >= allowed / 2.0, "only {} emitted", granted);
+    }
+
+    #[test]
+    fn a_smaller_request_is_granted_whole() {
+        let mut limit = RateLimit::new(RATE);
+        assert_eq!(limit.take(100), 100);
+    }
+}
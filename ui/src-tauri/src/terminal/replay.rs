@@ -0,0 +1,151 @@
+// src-tauri/src/terminal/replay.rs
+
+use super::{TerminalError, RESET_SEQUENCE};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Output events of an asciinema v2 recording (`.cast` file)
+pub struct Recording {
+    /// Seconds since the start of the recording, and the output at that time
+    frames: Vec<(f64, String)>,
+}
+
+impl Recording {
+    pub fn parse(contents: &str) -> Result<Self, TerminalError> {
+        let invalid = |msg: String| TerminalError::InvalidArgument(msg);
+        let mut lines = contents.lines();
+        let header: serde_json::Value = lines
+            .next()
+            .and_then(|line| serde_json::from_str(line).ok())
+            .ok_or_else(|| invalid("Recording has no asciicast header".to_string()))?;
+        if header.get("version").and_then(|v| v.as_u64()) != Some(2) {
+            return Err(invalid(
+                "Only asciicast v2 recordings are supported".to_string(),
+            ));
+        }
+
+        let mut frames = Vec::new();
+        for (i, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, kind, data): (f64, String, String) =
+                serde_json::from_str(line).map_err(|e| {
+                    invalid(format!("Invalid recording event on line {}: {}", i + 2, e))
+                })?;
+            // Input, resize and marker events don't produce output
+            if kind == "o" {
+                frames.push((time, data));
+            }
+        }
+        Ok(Self { frames })
+    }
+}
+
+/// Plays a recording back at its recorded timing, scaled by a speed factor.
+/// Controlled from any thread while `run` drives it on another.
+pub struct Replay {
+    recording: Recording,
+    speed: f64,
+    state: Mutex<ReplayState>,
+    wake: Condvar,
+}
+
+struct ReplayState {
+    /// Index of the next frame to emit
+    next: usize,
+    /// Recording time reached so far, in seconds
+    position: f64,
+    paused: bool,
+    seek_to: Option<f64>,
+    stopped: bool,
+}
+
+impl Replay {
+    pub fn new(recording: Recording, speed: f64) -> Self {
+        Self {
+            recording,
+            speed,
+            state: Mutex::new(ReplayState {
+                next: 0,
+                position: 0.0,
+                paused: false,
+                seek_to: None,
+                stopped: false,
+            }),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Emit the frames until the recording ends, `stop` is called or `emit`
+    /// returns false
+    pub fn run(&self, mut emit: impl FnMut(&[u8]) -> bool) {
+        let frames = &self.recording.frames;
+        let mut state = self.state.lock();
+        loop {
+            if state.stopped {
+                return;
+            }
+            if let Some(target) = state.seek_to.take() {
+                // Output can't be taken back, so seeking backwards resets the
+                // screen and fast-forwards from the start
+                let mut out = Vec::new();
+                if target < state.position {
+                    out.extend_from_slice(RESET_SEQUENCE.as_bytes());
+                    state.next = 0;
+                }
+                while state.next < frames.len() && frames[state.next].0 <= target {
+                    out.extend_from_slice(frames[state.next].1.as_bytes());
+                    state.next += 1;
+                }
+                state.position = target;
+                if !out.is_empty() && !MutexGuard::unlocked(&mut state, || emit(&out)) {
+                    return;
+                }
+                continue;
+            }
+            if state.paused {
+                self.wake.wait(&mut state);
+                continue;
+            }
+            let Some((time, data)) = frames.get(state.next) else {
+                return;
+            };
+
+            let delay = ((time - state.position) / self.speed).max(0.0);
+            let waited_from = Instant::now();
+            let woken = !self
+                .wake
+                .wait_for(&mut state, Duration::from_secs_f64(delay))
+                .timed_out();
+            if woken {
+                // Paused, seeked or stopped mid-wait; keep the time that passed
+                let elapsed = waited_from.elapsed().as_secs_f64() * self.speed;
+                state.position = (state.position + elapsed).min(*time);
+                continue;
+            }
+
+            state.position = *time;
+            state.next += 1;
+            if !MutexGuard::unlocked(&mut state, || emit(data.as_bytes())) {
+                return;
+            }
+        }
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.state.lock().paused = paused;
+        self.wake.notify_all();
+    }
+
+    /// Jump to `position` seconds into the recording
+    pub fn seek(&self, position: f64) {
+        self.state.lock().seek_to = Some(position.max(0.0));
+        self.wake.notify_all();
+    }
+
+    pub fn stop(&self) {
+        self.state.lock().stopped = true;
+        self.wake.notify_all();
+    }
+}
@@ -0,0 +1,39 @@
+// src-tauri/src/terminal/repo.rs
+
+use std::path::{Path, PathBuf};
+
+/// A Git working tree found by `find`
+pub struct Repo {
+    /// Top of the working tree, the directory holding `.git`
+    pub root: PathBuf,
+    /// The repository itself: `.git`, or for a linked worktree or submodule
+    /// wherever its `.git` file points
+    pub git_dir: PathBuf,
+}
+
+/// Find the working tree enclosing `start` (an absolute path) by walking up
+/// for a `.git` directory or file
+pub fn find(start: &Path) -> Option<Repo> {
+    start.ancestors().find_map(|dir| {
+        let dot_git = dir.join(".git");
+        let git_dir = if dot_git.is_dir() {
+            dot_git
+        } else if dot_git.is_file() {
+            linked_git_dir(dir, &dot_git)?
+        } else {
+            return None;
+        };
+        Some(Repo {
+            root: dir.to_path_buf(),
+            git_dir,
+        })
+    })
+}
+
+/// Where a `.git` file's `gitdir:` line points, taken relative to `dir`
+fn linked_git_dir(dir: &Path, dot_git: &Path) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(dot_git).ok()?;
+    let target = contents.lines().find_map(|l| l.strip_prefix("gitdir:"))?;
+    let git_dir = std::fs::canonicalize(dir.join(target.trim())).ok()?;
+    git_dir.is_dir().then_some(git_dir)
+}
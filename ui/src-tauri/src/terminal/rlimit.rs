@@ -0,0 +1,183 @@
+// src-tauri/src/terminal/rlimit.rs
+
+use super::TerminalError;
+#[cfg(unix)]
+use portable_pty::CommandBuilder;
+#[cfg(unix)]
+use std::ffi::OsString;
+
+/// Resource limits for each process of a session, see `spawn_terminal`
+#[derive(Clone, serde::Deserialize)]
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct RlimitSpec {
+    /// Largest address space, in bytes (`RLIMIT_AS`), rounded down to KiB
+    address_space: Option<u64>,
+    /// CPU time, in seconds (`RLIMIT_CPU`)
+    cpu_secs: Option<u64>,
+    /// Most open file descriptors (`RLIMIT_NOFILE`)
+    open_files: Option<u64>,
+}
+
+#[cfg(unix)]
+impl RlimitSpec {
+    /// The limits that are set
+    fn limits(&self) -> Vec<Limit> {
+        // The resources' type differs between platforms
+        let limits = [
+            Limit {
+                name: "address_space",
+                option: "-v",
+                value: self.address_space.map(|b| b / 1024),
+                resource: libc::RLIMIT_AS as _,
+                unit: 1024,
+            },
+            Limit {
+                name: "cpu_secs",
+                option: "-t",
+                value: self.cpu_secs,
+                resource: libc::RLIMIT_CPU as _,
+                unit: 1,
+            },
+            Limit {
+                name: "open_files",
+                option: "-n",
+                value: self.open_files,
+                resource: libc::RLIMIT_NOFILE as _,
+                unit: 1,
+            },
+        ];
+        limits.into_iter().filter(|l| l.value.is_some()).collect()
+    }
+
+    /// Check the limits can be set: they're above 0 and, since only a
+    /// privileged process may raise a hard limit, within the app's own
+    pub fn validate(&self) -> Result<(), TerminalError> {
+        for limit in self.limits() {
+            let value = limit.value.unwrap_or_default();
+            if value == 0 {
+                return Err(TerminalError::InvalidArgument(format!(
+                    "Resource limit {} must be above 0",
+                    limit.name
+                )));
+            }
+            let hard = hard_limit(limit.resource)?;
+            if hard != libc::RLIM_INFINITY && value.saturating_mul(limit.unit) > hard {
+                return Err(TerminalError::InvalidArgument(format!(
+                    "Resource limit {} is above the app's own limit of {}",
+                    limit.name,
+                    hard / limit.unit
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Make `cmd` apply the limits before it runs. It's started through
+    /// `/bin/sh`, whose `ulimit` calls `setrlimit` and which then `exec`s
+    /// the command in its place, keeping its PID.
+    pub fn wrap(&self, cmd: &mut CommandBuilder) {
+        let mut script: Vec<String> = self
+            .limits()
+            .into_iter()
+            .map(|l| format!("ulimit {} {}", l.option, l.value.unwrap_or_default()))
+            .collect();
+        script.push("exec \"$@\"".to_string());
+        let wrapper = ["/bin/sh", "-c", &script.join(" && "), "sh"];
+        let argv = cmd.get_argv_mut();
+        argv.splice(0..0, wrapper.map(OsString::from));
+    }
+}
+
+/// One limit of an `RlimitSpec`
+#[cfg(unix)]
+struct Limit {
+    name: &'static str,
+    /// The `ulimit` option setting it, and the value in `ulimit`'s units
+    option: &'static str,
+    value: Option<u64>,
+    resource: libc::c_int,
+    /// Size of `ulimit`'s unit in the resource's own
+    unit: u64,
+}
+
+#[cfg(unix)]
+fn hard_limit(resource: libc::c_int) -> Result<libc::rlim_t, TerminalError> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(resource as _, &mut limit) } != 0 {
+        return Err(TerminalError::Pty(format!(
+            "Failed to read resource limit: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    Ok(limit.rlim_max)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use portable_pty::{native_pty_system, PtySize};
+    use std::io::Read;
+
+    /// Run `script` in a PTY under `spec`, returning its output and whether
+    /// it succeeded
+    fn run(spec: &RlimitSpec, script: &str) -> (String, bool) {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new("/bin/sh");
+        cmd.args(["-c", script]);
+        spec.validate().unwrap();
+        spec.wrap(&mut cmd);
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let mut output = Vec::new();
+        // Ends with EIO once the child has closed the PTY
+        let _ = reader.read_to_end(&mut output);
+        let success = child.wait().unwrap().success();
+        (String::from_utf8_lossy(&output).into_owned(), success)
+    }
+
+    /// Opens six descriptors on top of the standard three
+    const OPEN_SIX: &str = "exec 3</dev/null 4</dev/null 5</dev/null 6</dev/null 7</dev/null \
+                            8</dev/null; echo opened";
+
+    #[test]
+    fn a_tiny_open_files_limit_is_hit_in_the_session() {
+        let spec = RlimitSpec {
+            address_space: None,
+            cpu_secs: None,
+            open_files: Some(8),
+        };
+        // The sixth open fails, and with it the shell
+        let (output, success) = run(&spec, &format!("ulimit -n; {}", OPEN_SIX));
+        let limit = output.lines().next().unwrap_or_default();
+        assert_eq!(limit.trim(), "8", "{:?}", output);
+        assert!(!output.contains("opened"), "{:?}", output);
+        assert!(!success);
+    }
+
+    #[test]
+    fn without_the_limit_the_same_program_succeeds() {
+        let spec = RlimitSpec {
+            address_space: None,
+            cpu_secs: Some(60),
+            open_files: None,
+        };
+        let (output, success) = run(&spec, OPEN_SIX);
+        assert!(output.contains("opened"), "{:?}", output);
+        assert!(success);
+    }
+
+    #[test]
+    fn a_zero_limit_is_rejected() {
+        let spec = RlimitSpec {
+            address_space: None,
+            cpu_secs: None,
+            open_files: Some(0),
+        };
+        let result = spec.validate();
+        assert!(matches!(result, Err(TerminalError::InvalidArgument(_))));
+    }
+}
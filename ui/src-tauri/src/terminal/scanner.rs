@@ -0,0 +1,136 @@
+// src-tauri/src/terminal/scanner.rs
+
+/// Longest OSC payload we keep; anything longer is dropped as garbage
+const MAX_OSC_LEN: usize = 4096;
+
+/// Longest CSI parameter string we parse; longer sequences are ignored
+const MAX_CSI_LEN: usize = 64;
+
+/// Escape sequences of interest recognised in PTY output
+#[derive(Debug)]
+pub enum ScanEvent {
+    /// An Operating System Command payload (between `ESC ]` and its
+    /// terminator). `bel` records whether it was terminated by BEL rather
+    /// than ST, so replies can use the same terminator.
+    Osc { payload: Vec<u8>, bel: bool },
+    /// An ENQ (`0x05`) outside any sequence, asking for the answerback
+    Enq,
+    /// DEC private modes set (`CSI ? Pm h`) or reset (`CSI ? Pm l`)
+    PrivateModes { modes: Vec<u16>, set: bool },
+    /// A full reset (RIS, `ESC c`)
+    Reset,
+}
+
+#[derive(Clone, Copy, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    // A CSI sequence too long to parse, skipped up to its final byte
+    CsiIgnore,
+    Osc,
+    OscEscape,
+}
+
+/// Incremental scanner over the raw PTY byte stream.
+///
+/// Keeps its state between calls so sequences split across reads are still
+/// recognised. It only observes the stream; output is emitted unchanged.
+#[derive(Default)]
+pub struct Scanner {
+    state: State,
+    osc: Vec<u8>,
+    // Parameter and intermediate bytes of the CSI sequence in progress
+    csi: Vec<u8>,
+}
+
+impl Scanner {
+    pub fn feed(&mut self, data: &[u8], events: &mut Vec<ScanEvent>) {
+        for &b in data {
+            self.state = match (self.state, b) {
+                // CAN / SUB abort any sequence in progress
+                (_, 0x18 | 0x1a) => State::Ground,
+                (State::Ground, 0x1b) => State::Escape,
+                (State::Ground, 0x05) => {
+                    events.push(ScanEvent::Enq);
+                    State::Ground
+                }
+                (State::Ground, _) => State::Ground,
+                (State::Escape, b']') => {
+                    self.osc.clear();
+                    State::Osc
+                }
+                (State::Escape, b'[') => {
+                    self.csi.clear();
+                    State::Csi
+                }
+                (State::Escape, b'c') => {
+                    events.push(ScanEvent::Reset);
+                    State::Ground
+                }
+                (State::Escape, 0x1b) => State::Escape,
+                (State::Escape, _) => State::Ground,
+                (State::Csi, 0x1b) => State::Escape,
+                (State::Csi, 0x40..=0x7e) => {
+                    if let Some(event) = private_modes(&self.csi, b) {
+                        events.push(event);
+                    }
+                    State::Ground
+                }
+                (State::Csi, 0x20..=0x3f) if self.csi.len() < MAX_CSI_LEN => {
+                    self.csi.push(b);
+                    State::Csi
+                }
+                (State::Csi, 0x20..=0x3f) => State::CsiIgnore,
+                // Other controls are executed without ending the sequence
+                (State::Csi, _) => State::Csi,
+                (State::CsiIgnore, 0x1b) => State::Escape,
+                (State::CsiIgnore, 0x40..=0x7e) => State::Ground,
+                (State::CsiIgnore, _) => State::CsiIgnore,
+                (State::Osc, 0x07) => {
+                    events.push(ScanEvent::Osc {
+                        payload: std::mem::take(&mut self.osc),
+                        bel: true,
+                    });
+                    State::Ground
+                }
+                (State::Osc, 0x1b) => State::OscEscape,
+                (State::Osc, _) if self.osc.len() >= MAX_OSC_LEN => State::Ground,
+                (State::Osc, _) => {
+                    self.osc.push(b);
+                    State::Osc
+                }
+                (State::OscEscape, b'\\') => {
+                    events.push(ScanEvent::Osc {
+                        payload: std::mem::take(&mut self.osc),
+                        bel: false,
+                    });
+                    State::Ground
+                }
+                // An unterminated OSC followed by a new escape sequence
+                (State::OscEscape, b']') => {
+                    self.osc.clear();
+                    State::Osc
+                }
+                (State::OscEscape, _) => State::Ground,
+            };
+        }
+    }
+}
+
+/// The event for a completed `CSI ? Pm h` / `CSI ? Pm l`, if that's what
+/// `params` and `final_byte` make up
+fn private_modes(params: &[u8], final_byte: u8) -> Option<ScanEvent> {
+    let set = match final_byte {
+        b'h' => true,
+        b'l' => false,
+        _ => return None,
+    };
+    let params = std::str::from_utf8(params.strip_prefix(b"?")?).ok()?;
+    let modes = params
+        .split(';')
+        .filter_map(|mode| mode.parse().ok())
+        .collect();
+    Some(ScanEvent::PrivateModes { modes, set })
+}
@@ -0,0 +1,681 @@
+// src-tauri/src/terminal/screen.rs
+
+use super::modes::TerminalModes;
+use std::mem;
+
+/// Longest CSI parameter string we parse; longer sequences are ignored
+const MAX_PARAMS_LEN: usize = 64;
+
+/// Parameters are capped, as no screen is larger
+const MAX_PARAM: usize = u16::MAX as usize;
+
+/// Columns between tab stops
+const TAB_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    // An escape sequence with intermediate bytes, e.g. `ESC ( B`
+    EscapeIntermediate,
+    Csi,
+    // OSC, DCS, APC, PM or SOS: skipped up to BEL or ST
+    String,
+    StringEscape,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Cursor {
+    row: usize,
+    col: usize,
+}
+
+/// A color set with SGR
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Color {
+    /// One of the 256 palette colors, the first 16 being the ANSI ones
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// How characters are drawn, as set with SGR (`CSI ... m`)
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Attrs {
+    /// None for the renderer's default color
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    /// Any style of underline
+    underline: bool,
+    blink: bool,
+    inverse: bool,
+    hidden: bool,
+    strikethrough: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    c: char,
+    attrs: Attrs,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            attrs: Attrs::default(),
+        }
+    }
+}
+
+/// A session's screen as `export_grid_state` returns it
+#[derive(serde::Serialize)]
+pub struct GridState {
+    cols: usize,
+    rows: usize,
+    /// Each row as runs of characters drawn alike, one cell per character,
+    /// together covering every column
+    lines: Vec<Vec<Span>>,
+    /// Zero-based
+    cursor_row: usize,
+    cursor_col: usize,
+    /// Mode 25 (DECTCEM)
+    cursor_visible: bool,
+    /// What characters printed from here on look like
+    attrs: Attrs,
+    alternate_screen: bool,
+    /// First and last row of the scroll region, zero-based
+    scroll_region: (usize, usize),
+    modes: TerminalModes,
+}
+
+#[derive(serde::Serialize)]
+pub struct Span {
+    text: String,
+    attrs: Attrs,
+}
+
+/// A model of what a session's screen shows, kept by feeding it the output,
+/// see `enable_screen_model`.
+///
+/// It tracks printing with autowrap, colors and other SGR attributes,
+/// cursor movement and visibility, erasing, inserting and deleting
+/// characters and lines, scroll regions and the alternate screen. Erased
+/// and inserted cells take the current background color, as in xterm.
+/// Every character takes one cell, so rows with double-width characters
+/// come out misaligned.
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    grid: Vec<Vec<Cell>>,
+    // The main screen's contents while the alternate screen is shown
+    main_grid: Option<Vec<Vec<Cell>>>,
+    cursor: Cursor,
+    saved_cursor: Cursor,
+    // Attributes printed with, and those saved with the cursor
+    pen: Attrs,
+    saved_pen: Attrs,
+    cursor_visible: bool,
+    // The last column was just written, so the next character wraps first
+    wrap_pending: bool,
+    autowrap: bool,
+    // Scroll region, first and last row
+    top: usize,
+    bottom: usize,
+    state: State,
+    // Parameter and intermediate bytes of the CSI sequence in progress
+    params: Vec<u8>,
+    // A UTF-8 character split across reads
+    utf8: Vec<u8>,
+}
+
+impl Screen {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        let (cols, rows) = (usize::from(cols.max(1)), usize::from(rows.max(1)));
+        Self {
+            cols,
+            rows,
+            grid: vec![vec![Cell::default(); cols]; rows],
+            main_grid: None,
+            cursor: Cursor::default(),
+            saved_cursor: Cursor::default(),
+            pen: Attrs::default(),
+            saved_pen: Attrs::default(),
+            cursor_visible: true,
+            wrap_pending: false,
+            autowrap: true,
+            top: 0,
+            bottom: rows - 1,
+            state: State::Ground,
+            params: Vec::new(),
+            utf8: Vec::new(),
+        }
+    }
+
+    /// The visible rows, without trailing blanks
+    pub fn text(&self) -> Vec<String> {
+        self.grid
+            .iter()
+            .map(|row| {
+                let text: String = row.iter().map(|cell| cell.c).collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// The visible rows with their attributes, the cursor and the modes,
+    /// `modes` being the session's
+    pub fn grid_state(&self, modes: TerminalModes) -> GridState {
+        let lines = self
+            .grid
+            .iter()
+            .map(|row| {
+                let mut spans: Vec<Span> = Vec::new();
+                for cell in row {
+                    match spans.last_mut() {
+                        Some(span) if span.attrs == cell.attrs => span.text.push(cell.c),
+                        _ => spans.push(Span {
+                            text: cell.c.to_string(),
+                            attrs: cell.attrs,
+                        }),
+                    }
+                }
+                spans
+            })
+            .collect();
+        GridState {
+            cols: self.cols,
+            rows: self.rows,
+            lines,
+            cursor_row: self.cursor.row,
+            cursor_col: self.cursor.col,
+            cursor_visible: self.cursor_visible,
+            attrs: self.pen,
+            alternate_screen: self.main_grid.is_some(),
+            scroll_region: (self.top, self.bottom),
+            modes,
+        }
+    }
+
+    /// Change the size, keeping the rows around the cursor: shrinking drops
+    /// rows from the top if the cursor would fall off the bottom
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        let (cols, rows) = (usize::from(cols.max(1)), usize::from(rows.max(1)));
+        let dropped = (self.cursor.row + 1).saturating_sub(rows);
+        for grid in [Some(&mut self.grid), self.main_grid.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            grid.drain(..dropped);
+            grid.resize(rows, vec![Cell::default(); cols]);
+            for row in grid.iter_mut() {
+                row.resize(cols, Cell::default());
+            }
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.cursor.row -= dropped;
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.saved_cursor.row = self.saved_cursor.row.min(rows - 1);
+        self.saved_cursor.col = self.saved_cursor.col.min(cols - 1);
+        self.wrap_pending = false;
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        for &b in data {
+            self.byte(b);
+        }
+    }
+
+    fn byte(&mut self, b: u8) {
+        // A UTF-8 character cut short
+        if b < 0x80 && !self.utf8.is_empty() {
+            self.utf8.clear();
+            self.print(char::REPLACEMENT_CHARACTER);
+        }
+        self.state = match (self.state, b) {
+            // CAN / SUB abort any sequence in progress
+            (_, 0x18 | 0x1a) => State::Ground,
+            (State::Ground, 0x1b) => State::Escape,
+            (State::Ground, 0x80..) => {
+                self.utf8_byte(b);
+                State::Ground
+            }
+            (State::Ground, 0x20..=0x7e) => {
+                self.print(char::from(b));
+                State::Ground
+            }
+            (State::Ground, _) => {
+                self.control(b);
+                State::Ground
+            }
+            (State::Escape | State::StringEscape, _) => self.escape(b),
+            (State::EscapeIntermediate, 0x30..=0x7e) => State::Ground,
+            (State::EscapeIntermediate, _) => State::EscapeIntermediate,
+            (State::Csi, 0x1b) => State::Escape,
+            (State::Csi, 0x20..=0x3f) => {
+                self.params.push(b);
+                State::Csi
+            }
+            (State::Csi, 0x40..=0x7e) => {
+                if self.params.len() <= MAX_PARAMS_LEN {
+                    let params = mem::take(&mut self.params);
+                    self.csi(&params, b);
+                }
+                self.params.clear();
+                State::Ground
+            }
+            // Other controls are executed without ending the sequence
+            (State::Csi, _) => {
+                self.control(b);
+                State::Csi
+            }
+            (State::String, 0x07) => State::Ground,
+            (State::String, 0x1b) => State::StringEscape,
+            (State::String, _) => State::String,
+        };
+    }
+
+    /// The byte after an ESC, returning the state to go on in
+    fn escape(&mut self, b: u8) -> State {
+        match b {
+            b'[' => {
+                self.params.clear();
+                return State::Csi;
+            }
+            b']' | b'P' | b'_' | b'^' | b'X' => return State::String,
+            0x1b => return State::Escape,
+            0x20..=0x2f => return State::EscapeIntermediate,
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.index(),
+            b'E' => {
+                self.cursor.col = 0;
+                self.index();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => *self = Self::new(self.cols as u16, self.rows as u16),
+            // Including the `\` of a string's ST
+            _ => {}
+        }
+        State::Ground
+    }
+
+    fn utf8_byte(&mut self, b: u8) {
+        if b & 0xc0 != 0x80 {
+            if !self.utf8.is_empty() {
+                self.print(char::REPLACEMENT_CHARACTER);
+            }
+            self.utf8.clear();
+        } else if self.utf8.is_empty() {
+            self.print(char::REPLACEMENT_CHARACTER);
+            return;
+        }
+        self.utf8.push(b);
+        let len = match self.utf8[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        if self.utf8.len() >= len {
+            let c = std::str::from_utf8(&self.utf8)
+                .ok()
+                .and_then(|s| s.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            self.utf8.clear();
+            self.print(c);
+        }
+    }
+
+    fn control(&mut self, b: u8) {
+        match b {
+            0x08 => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            b'\t' => {
+                let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor.col = next.min(self.cols - 1);
+            }
+            // The line discipline turns `\n` into `\r\n` on the way out
+            b'\n' | 0x0b | 0x0c => self.index(),
+            b'\r' => {
+                self.cursor.col = 0;
+                self.wrap_pending = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        if self.wrap_pending && self.autowrap {
+            self.cursor.col = 0;
+            self.index();
+        }
+        self.wrap_pending = false;
+        self.grid[self.cursor.row][self.cursor.col] = Cell { c, attrs: self.pen };
+        if self.cursor.col + 1 < self.cols {
+            self.cursor.col += 1;
+        } else {
+            self.wrap_pending = self.autowrap;
+        }
+    }
+
+    fn index(&mut self) {
+        if self.cursor.row == self.bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Move the scroll region's contents up `n` rows
+    fn scroll_up(&mut self, n: usize) {
+        self.delete_rows(self.top, n);
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        self.insert_rows(self.top, n);
+    }
+
+    /// Insert `n` blank rows at `row`, pushing rows off the region's bottom
+    fn insert_rows(&mut self, row: usize, n: usize) {
+        let n = n.min(self.bottom + 1 - row);
+        self.grid.drain(self.bottom + 1 - n..=self.bottom);
+        let blank = vec![self.blank(); self.cols];
+        self.grid.splice(row..row, std::iter::repeat(blank).take(n));
+    }
+
+    /// Delete `n` rows at `row`, pulling blank rows in at the region's bottom
+    fn delete_rows(&mut self, row: usize, n: usize) {
+        let n = n.min(self.bottom + 1 - row);
+        self.grid.drain(row..row + n);
+        let blank = vec![self.blank(); self.cols];
+        let at = self.bottom + 1 - n;
+        self.grid.splice(at..at, std::iter::repeat(blank).take(n));
+    }
+
+    /// An erased cell, which keeps the background color
+    fn blank(&self) -> Cell {
+        Cell {
+            c: ' ',
+            attrs: Attrs {
+                bg: self.pen.bg,
+                ..Attrs::default()
+            },
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = self.cursor;
+        self.saved_pen = self.pen;
+    }
+
+    fn restore_cursor(&mut self) {
+        self.cursor = self.saved_cursor;
+        self.pen = self.saved_pen;
+        self.wrap_pending = false;
+    }
+
+    fn set_alternate_screen(&mut self, on: bool, save_cursor: bool) {
+        if on && self.main_grid.is_none() {
+            if save_cursor {
+                self.save_cursor();
+            }
+            let blank = vec![vec![Cell::default(); self.cols]; self.rows];
+            self.main_grid = Some(mem::replace(&mut self.grid, blank));
+        } else if !on {
+            if let Some(grid) = self.main_grid.take() {
+                self.grid = grid;
+                if save_cursor {
+                    self.restore_cursor();
+                }
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &[u8], final_byte: u8) {
+        // Intermediate bytes (e.g. `CSI 2 SP q`) or unknown parameter bytes
+        // make it a sequence we don't model
+        if params
+            .iter()
+            .any(|b| !matches!(b, b'0'..=b'9' | b';' | b':' | b'?'))
+        {
+            return;
+        }
+        let (private, params) = match params.strip_prefix(b"?") {
+            Some(rest) => (true, rest),
+            None => (false, params),
+        };
+        let values: Vec<usize> = params
+            .split(|&b| b == b';' || b == b':')
+            .map(|p| std::str::from_utf8(p).ok().and_then(|p| p.parse().ok()))
+            .map(|v: Option<usize>| v.unwrap_or(0).min(MAX_PARAM))
+            .collect();
+        // The `i`th parameter, with 0 or a missing one meaning `default`
+        let arg = |i: usize, default: usize| match values.get(i) {
+            Some(&0) | None => default,
+            Some(&v) => v,
+        };
+        if private {
+            let set = match final_byte {
+                b'h' => true,
+                b'l' => false,
+                _ => return,
+            };
+            for &mode in &values {
+                match mode {
+                    7 => self.autowrap = set,
+                    25 => self.cursor_visible = set,
+                    47 | 1047 => self.set_alternate_screen(set, false),
+                    1049 => self.set_alternate_screen(set, true),
+                    _ => {}
+                }
+            }
+            return;
+        }
+        if final_byte == b'm' {
+            self.sgr(params);
+            return;
+        }
+
+        let n = arg(0, 1);
+        let Cursor { row, col } = self.cursor;
+        let (last_row, last_col) = (self.rows - 1, self.cols - 1);
+        match final_byte {
+            b'A' => self.cursor.row = row.saturating_sub(n),
+            b'B' | b'e' => self.cursor.row = (row + n).min(last_row),
+            b'C' | b'a' => self.cursor.col = (col + n).min(last_col),
+            b'D' => self.cursor.col = col.saturating_sub(n),
+            b'E' => {
+                self.cursor = Cursor {
+                    row: (row + n).min(last_row),
+                    col: 0,
+                }
+            }
+            b'F' => {
+                self.cursor = Cursor {
+                    row: row.saturating_sub(n),
+                    col: 0,
+                }
+            }
+            b'G' | b'`' => self.cursor.col = (n - 1).min(last_col),
+            b'd' => self.cursor.row = (n - 1).min(last_row),
+            b'H' | b'f' => {
+                self.cursor = Cursor {
+                    row: (arg(0, 1) - 1).min(last_row),
+                    col: (arg(1, 1) - 1).min(last_col),
+                }
+            }
+            b'J' => {
+                let (from, to) = match values.first().copied().unwrap_or(0) {
+                    0 => ((row, col), (last_row, last_col)),
+                    1 => ((0, 0), (row, col)),
+                    _ => ((0, 0), (last_row, last_col)),
+                };
+                self.erase(from, to);
+            }
+            b'K' => {
+                let (from, to) = match values.first().copied().unwrap_or(0) {
+                    0 => (col, last_col),
+                    1 => (0, col),
+                    _ => (0, last_col),
+                };
+                self.erase((row, from), (row, to));
+            }
+            b'X' => self.erase((row, col), (row, (col + n - 1).min(last_col))),
+            b'@' => {
+                let blank = self.blank();
+                let line = &mut self.grid[row];
+                let n = n.min(self.cols - col);
+                line.truncate(self.cols - n);
+                line.splice(col..col, std::iter::repeat(blank).take(n));
+            }
+            b'P' => {
+                let blank = self.blank();
+                let line = &mut self.grid[row];
+                let n = n.min(self.cols - col);
+                line.drain(col..col + n);
+                line.resize(self.cols, blank);
+            }
+            b'L' | b'M' if (self.top..=self.bottom).contains(&row) => {
+                if final_byte == b'L' {
+                    self.insert_rows(row, n);
+                } else {
+                    self.delete_rows(row, n);
+                }
+                self.cursor.col = 0;
+            }
+            b'S' => self.scroll_up(n),
+            b'T' => self.scroll_down(n),
+            b'r' => {
+                let top = arg(0, 1) - 1;
+                let bottom = arg(1, self.rows).min(self.rows) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.cursor = Cursor::default();
+                }
+            }
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Blank the cells from `from` to `to` (row and column, inclusive) in
+    /// reading order
+    fn erase(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let blank = self.blank();
+        for row in from.0..=to.0 {
+            let start = if row == from.0 { from.1 } else { 0 };
+            let end = if row == to.0 { to.1 } else { self.cols - 1 };
+            for cell in &mut self.grid[row][start..=end] {
+                *cell = blank;
+            }
+        }
+    }
+
+    /// Apply SGR parameters. Extended colors come as `38;5;n` or
+    /// `38;2;r;g;b`, or with colons in one parameter, `38:2::r:g:b`
+    /// including a color space.
+    fn sgr(&mut self, params: &[u8]) {
+        let groups: Vec<Vec<usize>> = params
+            .split(|&b| b == b';')
+            .map(|group| {
+                group
+                    .split(|&b| b == b':')
+                    .map(|p| std::str::from_utf8(p).ok().and_then(|p| p.parse().ok()))
+                    .map(|v: Option<usize>| v.unwrap_or(0).min(MAX_PARAM))
+                    .collect()
+            })
+            .collect();
+        let mut i = 0;
+        while i < groups.len() {
+            let group = &groups[i];
+            i += 1;
+            let pen = &mut self.pen;
+            match group[0] {
+                0 => *pen = Attrs::default(),
+                1 => pen.bold = true,
+                2 => pen.dim = true,
+                3 => pen.italic = true,
+                // `4:0` turns it off, other sub-parameters pick a style
+                4 => pen.underline = group.get(1) != Some(&0),
+                5 | 6 => pen.blink = true,
+                7 => pen.inverse = true,
+                8 => pen.hidden = true,
+                9 => pen.strikethrough = true,
+                21 => pen.underline = true,
+                22 => {
+                    pen.bold = false;
+                    pen.dim = false;
+                }
+                23 => pen.italic = false,
+                24 => pen.underline = false,
+                25 => pen.blink = false,
+                27 => pen.inverse = false,
+                28 => pen.hidden = false,
+                29 => pen.strikethrough = false,
+                n @ 30..=37 => pen.fg = Some(Color::Indexed((n - 30) as u8)),
+                39 => pen.fg = None,
+                n @ 40..=47 => pen.bg = Some(Color::Indexed((n - 40) as u8)),
+                49 => pen.bg = None,
+                n @ 90..=97 => pen.fg = Some(Color::Indexed((n - 90 + 8) as u8)),
+                n @ 100..=107 => pen.bg = Some(Color::Indexed((n - 100 + 8) as u8)),
+                // 58 is the underline color, which isn't modelled
+                kind @ (38 | 48 | 58) => {
+                    let color = if group.len() > 1 {
+                        match group[1..] {
+                            [2, _, r, g, b] => extended_color(&[2, r, g, b]).0,
+                            ref values => extended_color(values).0,
+                        }
+                    } else {
+                        let values: Vec<usize> = groups[i..].iter().map(|g| g[0]).collect();
+                        let (color, used) = extended_color(&values);
+                        i += used;
+                        color
+                    };
+                    match (kind, color) {
+                        (38, Some(color)) => pen.fg = Some(color),
+                        (48, Some(color)) => pen.bg = Some(color),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The color SGR 38 or 48 is followed by, `5, n` or `2, r, g, b`, and how
+/// many parameters it takes up. Anything else takes up the rest.
+fn extended_color(values: &[usize]) -> (Option<Color>, usize) {
+    let byte = |v: usize| u8::try_from(v).ok();
+    match *values {
+        [5, n, ..] => (byte(n).map(Color::Indexed), 2),
+        [2, r, g, b, ..] => {
+            let rgb = byte(r).zip(byte(g)).zip(byte(b));
+            (rgb.map(|((r, g), b)| Color::Rgb(r, g, b)), 4)
+        }
+        _ => (None, values.len()),
+    }
+}
@@ -0,0 +1,267 @@
+// src-tauri/src/terminal/scrollback.rs
+
+use super::ansi;
+use regex::bytes::Regex;
+use std::collections::VecDeque;
+
+/// Scrollback kept per session unless configured otherwise
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 1024 * 1024;
+
+/// How carriage returns are stored, see `spawn_terminal`'s
+/// `scrollback_cr_handling`
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrHandling {
+    /// Exactly as output
+    #[default]
+    Raw,
+    /// Text after a carriage return replaces the line so far
+    Collapse,
+}
+
+/// A session's recent output, capped at `cap` bytes (oldest evicted first).
+///
+/// Lines are numbered from the oldest retained byte, so line 0 may be the
+/// tail of a line whose start was evicted. With indexing enabled, the
+/// offsets of line starts are maintained as output arrives, making line
+/// lookups O(1) instead of a scan of the whole buffer.
+pub struct Scrollback {
+    data: VecDeque<u8>,
+    cap: usize,
+    // Absolute stream offset of `data[0]`, i.e. bytes evicted so far
+    start: u64,
+    // Absolute offsets just past each retained `\n`, when indexing is on
+    newlines: Option<VecDeque<u64>>,
+    // Number of retained `\n`s, kept up to date so sizes are cheap to query
+    newline_count: usize,
+    cr_handling: CrHandling,
+    // With `CrHandling::Collapse`, the last byte pushed was a `\r`, held
+    // back until the next one shows whether it ends the line
+    pending_cr: bool,
+}
+
+impl Scrollback {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            cap,
+            start: 0,
+            newlines: None,
+            newline_count: 0,
+            cr_handling: CrHandling::Raw,
+            pending_cr: false,
+        }
+    }
+
+    pub fn set_cr_handling(&mut self, cr_handling: CrHandling) {
+        if self.pending_cr {
+            self.pending_cr = false;
+            self.append(b"\r");
+        }
+        self.cr_handling = cr_handling;
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        match self.cr_handling {
+            CrHandling::Raw => self.append(bytes),
+            CrHandling::Collapse => self.push_collapsing(bytes),
+        }
+        self.trim();
+    }
+
+    /// Push output, dropping the part of a line that a carriage return
+    /// followed by more text goes back over
+    fn push_collapsing(&mut self, mut bytes: &[u8]) {
+        while let Some((&first, rest)) = bytes.split_first() {
+            if self.pending_cr {
+                match first {
+                    // The line ends here, or the cursor only moves
+                    b'\n' => self.append(b"\r"),
+                    b'\r' => {
+                        bytes = rest;
+                        continue;
+                    }
+                    _ => self.drop_current_line(),
+                }
+                self.pending_cr = false;
+            }
+            match bytes.iter().position(|&b| b == b'\r') {
+                Some(i) => {
+                    self.append(&bytes[..i]);
+                    self.pending_cr = true;
+                    bytes = &bytes[i + 1..];
+                }
+                None => {
+                    self.append(bytes);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Drop what's retained of the last line, back to its `\n`
+    fn drop_current_line(&mut self) {
+        while self.data.back().is_some_and(|&b| b != b'\n') {
+            self.data.pop_back();
+        }
+    }
+
+    fn append(&mut self, bytes: &[u8]) {
+        let end = self.start + self.data.len() as u64;
+        if let Some(index) = &mut self.newlines {
+            index.extend(
+                bytes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &b)| b == b'\n')
+                    .map(|(i, _)| end + i as u64 + 1),
+            );
+        }
+        self.data.extend(bytes);
+        self.newline_count += bytes.iter().filter(|&&b| b == b'\n').count();
+    }
+
+    /// Change the cap, evicting whatever no longer fits. Returns how many
+    /// bytes were dropped.
+    pub fn set_cap(&mut self, cap: usize) -> usize {
+        self.cap = cap;
+        self.trim()
+    }
+
+    /// Evict the oldest bytes beyond the cap, returning how many
+    fn trim(&mut self) -> usize {
+        if self.data.len() <= self.cap {
+            return 0;
+        }
+        let excess = self.data.len() - self.cap;
+        self.newline_count -= self.data.drain(..excess).filter(|&b| b == b'\n').count();
+        self.start += excess as u64;
+        if let Some(index) = &mut self.newlines {
+            while index.front().is_some_and(|&o| o <= self.start) {
+                index.pop_front();
+            }
+        }
+        excess
+    }
+
+    pub fn set_indexed(&mut self, indexed: bool) {
+        if !indexed {
+            self.newlines = None;
+        } else if self.newlines.is_none() {
+            let start = self.start;
+            self.newlines = Some(
+                self.data
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &b)| b == b'\n')
+                    .map(|(i, _)| start + i as u64 + 1)
+                    .collect(),
+            );
+        }
+    }
+
+    pub fn contents(&mut self) -> &[u8] {
+        self.data.make_contiguous()
+    }
+
+    /// Number of lines, counting a trailing line without a newline
+    pub fn line_count(&self) -> usize {
+        let unterminated = self.data.back().is_some_and(|&b| b != b'\n');
+        self.newline_count + unterminated as usize
+    }
+
+    /// Bytes currently retained
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Bytes allocated, including room for more output not yet used
+    pub fn allocated(&self) -> usize {
+        let index = self.newlines.as_ref().map_or(0, VecDeque::capacity);
+        self.data.capacity() + index * std::mem::size_of::<u64>()
+    }
+
+    /// Release the room allocated beyond what's retained
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        if let Some(index) = &mut self.newlines {
+            index.shrink_to_fit();
+        }
+    }
+
+    /// Whether older output has been evicted to stay within the cap
+    pub fn is_truncated(&self) -> bool {
+        self.start > 0
+    }
+
+    /// Offset into the buffer where `line` starts; `line_count()` maps to the
+    /// end of the buffer
+    fn line_offset(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+        let offset = match &self.newlines {
+            Some(index) => index.get(line - 1).map(|&o| (o - self.start) as usize),
+            None => self
+                .data
+                .iter()
+                .enumerate()
+                .filter(|(_, &b)| b == b'\n')
+                .nth(line - 1)
+                .map(|(i, _)| i + 1),
+        };
+        offset.or_else(|| (line == self.line_count()).then_some(self.data.len()))
+    }
+
+    /// Everything from the start of `line` on, or `None` if there is no
+    /// such line
+    pub fn since_line(&mut self, line: usize) -> Option<&[u8]> {
+        let offset = self.line_offset(line)?;
+        Some(&self.contents()[offset..])
+    }
+
+    /// Lines `start..=end`, or `None` if the range isn't within the buffer
+    pub fn line_range(&mut self, start: usize, end: usize) -> Option<&[u8]> {
+        if start > end || end >= self.line_count() {
+            return None;
+        }
+        let from = self.line_offset(start)?;
+        let to = self.line_offset(end + 1)?;
+        Some(&self.contents()[from..to])
+    }
+
+    /// Find the first line at or after `from_line` whose text (escape
+    /// sequences stripped) contains `query`. Returns the line number and the
+    /// character column of the match.
+    pub fn search(&mut self, query: &[u8], from_line: usize) -> Option<(usize, usize)> {
+        if query.is_empty() {
+            return None;
+        }
+        let rest = self.since_line(from_line)?;
+        for (i, line) in rest.split_inclusive(|&b| b == b'\n').enumerate() {
+            let text = ansi::strip(line);
+            if let Some(pos) = text.windows(query.len()).position(|w| w == query) {
+                let column = String::from_utf8_lossy(&text[..pos]).chars().count();
+                return Some((from_line + i, column));
+            }
+        }
+        None
+    }
+
+    /// Whether the text of any line (escape sequences and line ending
+    /// stripped) matches `pattern`, stopping at the first that does
+    pub fn contains(&mut self, pattern: &Regex) -> bool {
+        self.contents()
+            .split_inclusive(|&b| b == b'\n')
+            .any(|line| {
+                let text = ansi::strip(line);
+                let text = text.strip_suffix(b"\n").unwrap_or(&text);
+                let text = text.strip_suffix(b"\r").unwrap_or(text);
+                pattern.is_match(text)
+            })
+    }
+}
@@ -0,0 +1,233 @@
+// src-tauri/src/terminal/shell_capture.rs
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::oneshot;
+
+static CAPTURE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Most output accepted before the capture gives up
+pub const MAX_CAPTURE: usize = 1024 * 1024;
+
+enum Stage {
+    AwaitingBegin,
+    Capturing,
+}
+
+/// Takes the output of a command injected into a session's shell (e.g.
+/// `env` for `get_shell_env`) out of its output stream, between sentinel
+/// lines unique to this capture
+pub struct ShellCapture {
+    // What's run between the sentinels
+    body: String,
+    begin: Vec<u8>,
+    end: Vec<u8>,
+    stage: Stage,
+    // End of the previous chunk that may be the start of a sentinel
+    held: Vec<u8>,
+    captured: Vec<u8>,
+    // Taken when the result is sent
+    reply: Option<oneshot::Sender<String>>,
+}
+
+impl ShellCapture {
+    /// Capture the output of the shell command `body`
+    pub fn new(body: &str) -> (Self, oneshot::Receiver<String>) {
+        let id = CAPTURE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let nonce = format!("{}_{}", std::process::id(), id);
+        let (reply, receiver) = oneshot::channel();
+        let capture = Self {
+            body: body.to_string(),
+            begin: format!("__KARPI_CAPTURE_BEGIN_{}", nonce).into_bytes(),
+            end: format!("__KARPI_CAPTURE_END_{}", nonce).into_bytes(),
+            stage: Stage::AwaitingBegin,
+            held: Vec::new(),
+            captured: Vec::new(),
+            reply: Some(reply),
+        };
+        (capture, receiver)
+    }
+
+    /// Shell input that runs the command between the sentinels. They
+    /// are printed in two halves so the echoed command line never contains
+    /// them, and the leading space keeps it out of most shells' history.
+    pub fn command(&self) -> String {
+        let begin = String::from_utf8_lossy(&self.begin);
+        let end = String::from_utf8_lossy(&self.end);
+        let (begin_a, begin_b) = begin.split_at(2);
+        let (end_a, end_b) = end.split_at(2);
+        format!(
+            " printf '%s%s\\n' {} {}; {}; printf '%s%s\\n' {} {}\r",
+            begin_a, begin_b, self.body, end_a, end_b
+        )
+    }
+
+    /// Pass a chunk of output through, appending whatever isn't captured to
+    /// `out`. Returns true once the capture is over: complete, or abandoned
+    /// because the caller stopped waiting.
+    pub fn feed(&mut self, data: &[u8], out: &mut Vec<u8>) -> bool {
+        let mut buf = std::mem::take(&mut self.held);
+        buf.extend_from_slice(data);
+        let waiting = self.reply.as_ref().is_some_and(|reply| !reply.is_closed());
+        if !waiting {
+            out.extend_from_slice(&buf);
+            return true;
+        }
+
+        let mut rest = &buf[..];
+        loop {
+            match self.stage {
+                Stage::AwaitingBegin => match find(rest, &self.begin) {
+                    Some(i) => {
+                        out.extend_from_slice(&rest[..i]);
+                        rest = &rest[i + self.begin.len()..];
+                        self.stage = Stage::Capturing;
+                    }
+                    None => {
+                        let keep = partial_suffix(rest, &self.begin);
+                        out.extend_from_slice(&rest[..rest.len() - keep]);
+                        self.held = rest[rest.len() - keep..].to_vec();
+                        return false;
+                    }
+                },
+                Stage::Capturing => match find(rest, &self.end) {
+                    Some(i) => {
+                        self.captured.extend_from_slice(&rest[..i]);
+                        rest = &rest[i + self.end.len()..];
+                        let rest = rest.strip_prefix(b"\r").unwrap_or(rest);
+                        let rest = rest.strip_prefix(b"\n").unwrap_or(rest);
+                        out.extend_from_slice(rest);
+                        self.finish();
+                        return true;
+                    }
+                    None => {
+                        let keep = partial_suffix(rest, &self.end);
+                        self.captured.extend_from_slice(&rest[..rest.len() - keep]);
+                        self.held = rest[rest.len() - keep..].to_vec();
+                        if self.captured.len() > MAX_CAPTURE {
+                            out.append(&mut self.held);
+                            return true;
+                        }
+                        return false;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Hand the captured output to the waiting command, without the line
+    /// breaks around it
+    fn finish(&mut self) {
+        let text = String::from_utf8_lossy(&self.captured);
+        let text = text.trim_matches(|c| c == '\r' || c == '\n');
+        if let Some(reply) = self.reply.take() {
+            let _ = reply.send(text.to_string());
+        }
+    }
+}
+
+/// Copies the output between two markers from a session's output stream
+/// as it passes, for `capture_between`; unlike `ShellCapture` it takes
+/// nothing out
+pub struct MarkerCapture {
+    start: Vec<u8>,
+    end: Vec<u8>,
+    stage: Stage,
+    // End of the previous chunk that may be the start of a marker
+    held: Vec<u8>,
+    captured: Vec<u8>,
+    // Taken when the result is sent
+    reply: Option<oneshot::Sender<Vec<u8>>>,
+}
+
+impl MarkerCapture {
+    pub fn new(start: &[u8], end: &[u8]) -> (Self, oneshot::Receiver<Vec<u8>>) {
+        let (reply, receiver) = oneshot::channel();
+        let capture = Self {
+            start: start.to_vec(),
+            end: end.to_vec(),
+            stage: Stage::AwaitingBegin,
+            held: Vec::new(),
+            captured: Vec::new(),
+            reply: Some(reply),
+        };
+        (capture, receiver)
+    }
+
+    /// Look at a chunk of output. Returns true once the capture is over:
+    /// complete, abandoned because the caller stopped waiting, or given up
+    /// as more than `MAX_CAPTURE` bytes.
+    pub fn feed(&mut self, data: &[u8]) -> bool {
+        if !self.reply.as_ref().is_some_and(|reply| !reply.is_closed()) {
+            return true;
+        }
+        let mut buf = std::mem::take(&mut self.held);
+        buf.extend_from_slice(data);
+
+        let mut rest = &buf[..];
+        loop {
+            match self.stage {
+                Stage::AwaitingBegin => match find(rest, &self.start) {
+                    Some(i) => {
+                        rest = &rest[i + self.start.len()..];
+                        self.stage = Stage::Capturing;
+                    }
+                    None => {
+                        let keep = partial_suffix(rest, &self.start);
+                        self.held = rest[rest.len() - keep..].to_vec();
+                        return false;
+                    }
+                },
+                Stage::Capturing => match find(rest, &self.end) {
+                    Some(i) => {
+                        self.captured.extend_from_slice(&rest[..i]);
+                        if let Some(reply) = self.reply.take() {
+                            let _ = reply.send(std::mem::take(&mut self.captured));
+                        }
+                        return true;
+                    }
+                    None => {
+                        let keep = partial_suffix(rest, &self.end);
+                        self.captured.extend_from_slice(&rest[..rest.len() - keep]);
+                        self.held = rest[rest.len() - keep..].to_vec();
+                        return self.captured.len() > MAX_CAPTURE;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Parse `env` output. Lines without `=` continue the previous value (it
+/// contained a newline).
+pub fn parse_env(text: &str) -> HashMap<String, String> {
+    let mut env = HashMap::new();
+    let mut last: Option<String> = None;
+    for line in text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)) {
+        match line.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !key.contains(' ') => {
+                env.insert(key.to_string(), value.to_string());
+                last = Some(key.to_string());
+            }
+            _ => {
+                if let Some(value) = last.as_ref().and_then(|key| env.get_mut(key)) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    env
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Length of the longest suffix of `data` that is a proper prefix of `marker`
+fn partial_suffix(data: &[u8], marker: &[u8]) -> usize {
+    (1..marker.len().min(data.len() + 1))
+        .rev()
+        .find(|&n| data.ends_with(&marker[..n]))
+        .unwrap_or(0)
+}
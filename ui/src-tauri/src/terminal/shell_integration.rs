@@ -0,0 +1,142 @@
+// src-tauri/src/terminal/shell_integration.rs
+
+use portable_pty::CommandBuilder;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+
+/// Loaded in place of bash's startup files: reads them as a login shell
+/// would, then adds the marks
+const BASH_RC: &str = r#"# Written by karpi, see `inject_shell_integration`
+[ -r /etc/profile ] && . /etc/profile
+if [ -r ~/.bash_profile ]; then
+    . ~/.bash_profile
+elif [ -r ~/.bash_login ]; then
+    . ~/.bash_login
+elif [ -r ~/.profile ]; then
+    . ~/.profile
+fi
+__karpi_prompt() {
+    local ret=$?
+    printf '\033]133;D;%s\007\033]7;file://%s%s\007\033]133;A\007' "$ret" "$HOSTNAME" "$PWD"
+    return $ret
+}
+PROMPT_COMMAND="__karpi_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+PS0="${PS0}\e]133;C\a"
+"#;
+
+/// Each of zsh's startup files loads the user's own from their `ZDOTDIR`,
+/// then points `ZDOTDIR` back here for the next one
+const ZSH_STARTUP: &str = r#"# Written by karpi, see `inject_shell_integration`
+_karpi_dir=$ZDOTDIR
+ZDOTDIR=${KARPI_ZDOTDIR:-$HOME}
+[[ -r $ZDOTDIR/NAME ]] && source $ZDOTDIR/NAME
+KARPI_ZDOTDIR=$ZDOTDIR
+ZDOTDIR=$_karpi_dir
+"#;
+
+/// Appended to `.zshrc`
+const ZSH_HOOKS: &str = r#"_karpi_precmd() {
+    local ret=$?
+    printf '\e]133;D;%s\a\e]7;file://%s%s\a\e]133;A\a' $ret $HOST $PWD
+}
+_karpi_preexec() {
+    printf '\e]133;C\a'
+}
+precmd_functions=(_karpi_precmd $precmd_functions)
+preexec_functions+=(_karpi_preexec)
+"#;
+
+/// Appended to `.zlogin`, the last file a login shell reads
+const ZSH_RESTORE: &str = r#"ZDOTDIR=$KARPI_ZDOTDIR
+unset KARPI_ZDOTDIR _karpi_dir
+"#;
+
+const FISH_INIT: &str = r#"# Written by karpi, see `inject_shell_integration`
+function __karpi_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+function __karpi_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
+function __karpi_prompt --on-event fish_prompt
+    printf '\e]7;file://%s%s\a\e]133;A\a' $hostname $PWD
+end
+"#;
+
+/// The scripts loading shell integration into one session, see
+/// `spawn_terminal`. Dropping it removes them.
+pub struct IntegrationFiles {
+    dir: PathBuf,
+}
+
+impl IntegrationFiles {
+    /// Write the scripts for `shell` and change `cmd` (the shell with its
+    /// usual arguments) to load them. `None` if the shell isn't one we have
+    /// scripts for.
+    pub fn inject(shell: &str, cmd: &mut CommandBuilder) -> io::Result<Option<Self>> {
+        let name = Path::new(shell)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if !matches!(name, "bash" | "zsh" | "fish") {
+            return Ok(None);
+        }
+        let files = Self::create()?;
+        let dir = &files.dir;
+        match name {
+            "bash" => {
+                let rc = dir.join("bashrc");
+                std::fs::write(&rc, BASH_RC)?;
+                // A login shell ignores `--rcfile`, so it runs as an
+                // interactive one reading the login files itself
+                let argv = cmd.get_argv_mut();
+                argv.truncate(1);
+                argv.extend(["--rcfile".into(), rc.into_os_string(), "-i".into()]);
+            }
+            "zsh" => {
+                for file in [".zshenv", ".zprofile", ".zshrc", ".zlogin"] {
+                    let mut script = ZSH_STARTUP.replace("NAME", file);
+                    match file {
+                        ".zshrc" => script.push_str(ZSH_HOOKS),
+                        ".zlogin" => script.push_str(ZSH_RESTORE),
+                        _ => {}
+                    }
+                    std::fs::write(dir.join(file), script)?;
+                }
+                if let Some(user_dir) = std::env::var_os("ZDOTDIR") {
+                    cmd.env("KARPI_ZDOTDIR", user_dir);
+                }
+                cmd.env("ZDOTDIR", dir);
+            }
+            _ => {
+                let init = dir.join("init.fish");
+                std::fs::write(&init, FISH_INIT)?;
+                let command = format!("source {}", fish_quote(&init.display().to_string()));
+                let argv = cmd.get_argv_mut();
+                argv.extend([OsString::from("--init-command"), command.into()]);
+            }
+        }
+        Ok(Some(files))
+    }
+
+    /// A new directory only the current user can read, so nobody else can
+    /// put their own scripts in it
+    fn create() -> io::Result<Self> {
+        let dir =
+            std::env::temp_dir().join(format!("karpi-shell-integration-{}", uuid::Uuid::new_v4()));
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        Ok(Self { dir })
+    }
+}
+
+impl Drop for IntegrationFiles {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
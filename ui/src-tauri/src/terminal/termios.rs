@@ -0,0 +1,50 @@
+// src-tauri/src/terminal/termios.rs
+
+use std::io;
+use std::os::unix::io::RawFd;
+
+pub fn get(fd: RawFd) -> io::Result<libc::termios> {
+    let mut t = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut t) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(t)
+}
+
+pub fn set(fd: RawFd, t: &libc::termios) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, t) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restore cooked-mode line discipline settings, like `stty sane`
+pub fn make_sane(fd: RawFd) -> io::Result<()> {
+    let mut t = get(fd)?;
+    t.c_iflag |= libc::BRKINT | libc::ICRNL | libc::IMAXBEL;
+    t.c_iflag &= !(libc::IGNBRK | libc::INLCR | libc::IGNCR | libc::IXOFF | libc::IXANY);
+    t.c_oflag |= libc::OPOST | libc::ONLCR;
+    t.c_oflag &= !(libc::OCRNL | libc::ONOCR | libc::ONLRET);
+    t.c_cflag |= libc::CREAD;
+    t.c_lflag |= libc::ISIG
+        | libc::ICANON
+        | libc::IEXTEN
+        | libc::ECHO
+        | libc::ECHOE
+        | libc::ECHOK
+        | libc::ECHOCTL
+        | libc::ECHOKE;
+    t.c_lflag &= !(libc::ECHONL | libc::NOFLSH | libc::TOSTOP | libc::ECHOPRT);
+    set(fd, &t)
+}
+
+/// Path of the slave device for a PTY master, e.g. `/dev/pts/7`
+#[cfg(target_os = "linux")]
+pub fn pts_name(fd: RawFd) -> Option<String> {
+    let mut buf = [0 as libc::c_char; 128];
+    if unsafe { libc::ptsname_r(fd, buf.as_mut_ptr(), buf.len()) } != 0 {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
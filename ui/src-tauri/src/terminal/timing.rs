@@ -0,0 +1,97 @@
+// src-tauri/src/terminal/timing.rs
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Most samples of each kind kept; older ones are dropped
+const MAX_SAMPLES: usize = 4096;
+
+/// Timestamps of a session's PTY reads and writes, see `enable_timing`
+#[derive(Default)]
+pub struct Timing {
+    reads: VecDeque<(Instant, usize)>,
+    writes: VecDeque<Instant>,
+}
+
+/// Distribution of a measurement
+#[derive(serde::Serialize)]
+pub struct Percentiles {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+/// Summary of the samples collected, see `get_timing_report`
+#[derive(serde::Serialize)]
+pub struct TimingReport {
+    reads: usize,
+    writes: usize,
+    /// Bytes returned per read
+    read_size: Option<Percentiles>,
+    /// Time between consecutive reads
+    read_interval_ms: Option<Percentiles>,
+    /// Time from a write to the next read, i.e. how quickly the PTY echoes
+    /// or answers input
+    echo_latency_ms: Option<Percentiles>,
+}
+
+impl Timing {
+    pub fn record_read(&mut self, len: usize) {
+        push_bounded(&mut self.reads, (Instant::now(), len));
+    }
+
+    pub fn record_write(&mut self) {
+        push_bounded(&mut self.writes, Instant::now());
+    }
+
+    pub fn report(&self) -> TimingReport {
+        let sizes = self.reads.iter().map(|&(_, len)| len as f64).collect();
+        let intervals = self
+            .reads
+            .iter()
+            .zip(self.reads.iter().skip(1))
+            .map(|((a, _), (b, _))| millis(*a, *b))
+            .collect();
+        let echoes = self
+            .writes
+            .iter()
+            .filter_map(|&write| {
+                let next = self.reads.iter().find(|&&(read, _)| read >= write)?;
+                Some(millis(write, next.0))
+            })
+            .collect();
+        TimingReport {
+            reads: self.reads.len(),
+            writes: self.writes.len(),
+            read_size: percentiles(sizes),
+            read_interval_ms: percentiles(intervals),
+            echo_latency_ms: percentiles(echoes),
+        }
+    }
+}
+
+fn push_bounded<T>(samples: &mut VecDeque<T>, sample: T) {
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+fn millis(from: Instant, to: Instant) -> f64 {
+    to.duration_since(from).as_secs_f64() * 1000.0
+}
+
+fn percentiles(mut values: Vec<f64>) -> Option<Percentiles> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let at = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+    Some(Percentiles {
+        p50: at(0.5),
+        p90: at(0.9),
+        p99: at(0.99),
+        max: values[values.len() - 1],
+    })
+}
@@ -0,0 +1,169 @@
+// src-tauri/src/terminal/trigger.rs
+
+use super::ansi::{self, Segment};
+use super::TerminalError;
+use regex::bytes::Regex;
+
+/// Most triggers a session may have
+pub const MAX_TRIGGERS: usize = 32;
+
+/// Longest line kept for matching; older text of a longer line is dropped
+const MAX_LINE: usize = 8192;
+
+/// What a trigger does when its pattern matches, as sent by the frontend
+#[derive(Clone, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TriggerAction {
+    /// Write `text` to the session as if typed
+    Write { text: String },
+    /// Emit a frontend event named `event`
+    Emit { event: String },
+}
+
+/// A trigger that matched, to be acted on by the caller
+pub struct Fired {
+    pub trigger_id: u32,
+    pub action: TriggerAction,
+    /// The matched text
+    pub text: String,
+}
+
+struct Trigger {
+    id: u32,
+    regex: Regex,
+    action: TriggerAction,
+    once: bool,
+    // Offset into the current line up to which this trigger has matched,
+    // so a match isn't reported again as the line grows
+    matched_to: usize,
+}
+
+/// A session's output triggers, matched line by line against the output
+/// with escape sequences stripped
+#[derive(Default)]
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+    next_id: u32,
+    // Text of the current, incomplete line
+    line: Vec<u8>,
+    // An escape sequence cut off at the end of the previous chunk
+    partial_escape: Vec<u8>,
+}
+
+impl Triggers {
+    pub fn add(
+        &mut self,
+        pattern: &str,
+        action: TriggerAction,
+        once: bool,
+    ) -> Result<u32, TerminalError> {
+        if self.triggers.len() >= MAX_TRIGGERS {
+            return Err(TerminalError::InvalidArgument(format!(
+                "A session can have at most {} triggers",
+                MAX_TRIGGERS
+            )));
+        }
+        if let TriggerAction::Emit { event } = &action {
+            let valid = !event.is_empty()
+                && event
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '/' | ':' | '_'));
+            if !valid {
+                return Err(TerminalError::InvalidArgument(format!(
+                    "Invalid event name: {:?}",
+                    event
+                )));
+            }
+        }
+        let regex = Regex::new(pattern).map_err(|e| {
+            TerminalError::InvalidArgument(format!("Invalid trigger pattern: {}", e))
+        })?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.triggers.push(Trigger {
+            id,
+            regex,
+            action,
+            once,
+            matched_to: self.line.len(),
+        });
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.triggers.len();
+        self.triggers.retain(|t| t.id != id);
+        self.triggers.len() != before
+    }
+
+    /// Match a chunk of raw output, appending the triggers that fire to
+    /// `fired`: in the order the triggers were added, and for each trigger
+    /// in the order of its matches
+    pub fn feed(&mut self, data: &[u8], fired: &mut Vec<Fired>) {
+        if self.triggers.is_empty() {
+            self.line.clear();
+            self.partial_escape.clear();
+            return;
+        }
+        let mut data_owned = std::mem::take(&mut self.partial_escape);
+        let data = if data_owned.is_empty() {
+            data
+        } else {
+            data_owned.extend_from_slice(data);
+            &data_owned
+        };
+
+        for segment in ansi::segments(data) {
+            match segment {
+                Segment::Text(text) => {
+                    for piece in text.split_inclusive(|&b| b == b'\n') {
+                        self.line.extend_from_slice(piece);
+                        self.match_line(fired);
+                        if piece.ends_with(b"\n") {
+                            self.start_line(self.line.len());
+                        } else if self.line.len() > MAX_LINE {
+                            self.start_line(self.line.len() - MAX_LINE / 2);
+                        }
+                    }
+                }
+                Segment::Escape(_) => {}
+                // Kept for the next chunk, unless it's runaway garbage
+                Segment::Incomplete(escape) if escape.len() <= MAX_LINE => {
+                    self.partial_escape = escape.to_vec()
+                }
+                Segment::Incomplete(_) => {}
+            }
+        }
+    }
+
+    fn match_line(&mut self, fired: &mut Vec<Fired>) {
+        let line = &self.line;
+        self.triggers.retain_mut(|trigger| {
+            while trigger.matched_to <= line.len() {
+                let Some(m) = trigger.regex.find_at(line, trigger.matched_to) else {
+                    break;
+                };
+                // An empty match would otherwise repeat at the same offset
+                trigger.matched_to = m.end().max(m.start() + 1);
+                fired.push(Fired {
+                    trigger_id: trigger.id,
+                    action: trigger.action.clone(),
+                    text: String::from_utf8_lossy(m.as_bytes()).into_owned(),
+                });
+                if trigger.once {
+                    return false;
+                }
+            }
+            true
+        });
+    }
+
+    /// Drop the first `consumed` bytes of the current line
+    fn start_line(&mut self, consumed: usize) {
+        self.line.drain(..consumed);
+        for trigger in &mut self.triggers {
+            trigger.matched_to = trigger.matched_to.saturating_sub(consumed);
+        }
+    }
+}
@@ -8,6 +8,7 @@ import { FitAddon } from "@xterm/addon-fit";
 
 interface ITerminalOutput {
   session_id: number;
+  seq: number;
   data: string;
 }
 
@@ -85,9 +86,7 @@ export function useTerminal(options: IUseTerminalOptions = {}) {
       try {
         // Spawn PTY session
         const sessionId = await invoke<number>("spawn_terminal", {
-          cols,
-          rows,
-          cwd: options.cwd,
+          options: { cols, rows, cwd: options.cwd },
         });
         sessionIdRef.current = sessionId;
 